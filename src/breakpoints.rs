@@ -0,0 +1,147 @@
+//! Breakpoints as the editor keeps them: on tiles rather than on clusters, so that they stay on
+//! the same wire when its cluster is renumbered by an edit.
+
+use crate::circuit::Circuit;
+use crate::cluster_cache::ClusterCache;
+use crate::simulation::{Breakpoint, Combine, Condition, Hit};
+use glam::IVec2;
+
+#[derive(Default)]
+pub struct Breakpoints {
+    list: Vec<Breakpoint<IVec2>>,
+    sent: ClusterCache<Vec<Breakpoint>>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Changes the condition on the tile at `position` to the next one, removing it after the
+    /// last one. A tile without a condition gets a breakpoint of its own.
+    pub fn cycle(&mut self, position: IVec2) {
+        let found = self
+            .list
+            .iter()
+            .enumerate()
+            .find_map(|(index, breakpoint)| {
+                let trigger = breakpoint.triggers.iter().position(|t| t.0 == position)?;
+                Some((index, trigger))
+            });
+        let (index, trigger) = match found {
+            Some(x) => x,
+            None => {
+                self.list.push(Breakpoint {
+                    triggers: vec![(position, Condition::Rises)],
+                    combine: Combine::Any,
+                });
+                return;
+            }
+        };
+        let breakpoint = &mut self.list[index];
+        let condition = &mut breakpoint.triggers[trigger].1;
+        *condition = match *condition {
+            Condition::Rises => Condition::Falls,
+            Condition::Falls => Condition::Changes,
+            Condition::Changes => Condition::High,
+            Condition::High => Condition::Low,
+            Condition::Low => {
+                breakpoint.triggers.remove(trigger);
+                if breakpoint.triggers.is_empty() {
+                    self.list.remove(index);
+                }
+                return;
+            }
+        };
+    }
+
+    /// Adds the tile at `position` to the most recent breakpoint.
+    pub fn join(&mut self, position: IVec2) {
+        let in_use = self
+            .list
+            .iter()
+            .any(|breakpoint| breakpoint.triggers.iter().any(|t| t.0 == position));
+        match self.list.last_mut() {
+            Some(breakpoint) if !in_use => {
+                breakpoint.triggers.push((position, Condition::Rises));
+            }
+            _ => self.cycle(position),
+        }
+    }
+
+    /// Switches the most recent breakpoint between needing all of its triggers and any of them.
+    pub fn toggle_combine(&mut self) {
+        if let Some(breakpoint) = self.list.last_mut() {
+            breakpoint.combine = match breakpoint.combine {
+                Combine::All => Combine::Any,
+                Combine::Any => Combine::All,
+            };
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    /// The tile of the trigger that made a breakpoint fire.
+    pub fn position(&self, hit: Hit) -> Option<IVec2> {
+        let breakpoint = self.list.get(hit.breakpoint)?;
+        breakpoint.triggers.get(hit.trigger).map(|t| t.0)
+    }
+
+    /// Looks up the cluster of every tile. Returns the breakpoints for the simulation of
+    /// `circuit` if they differ from the ones that were returned last time.
+    ///
+    /// A breakpoint with a tile that has nothing on it is kept, without any triggers, so that
+    /// the indices stay the same.
+    pub fn resolve(&mut self, circuit: &Circuit) -> Option<Vec<Breakpoint>> {
+        let list = &self.list;
+        self.sent.resolve(circuit, |cluster| {
+            list.iter()
+                .map(|breakpoint| {
+                    breakpoint
+                        .resolve(|&position| cluster(position))
+                        .unwrap_or(Breakpoint {
+                            triggers: Vec::new(),
+                            combine: breakpoint.combine,
+                        })
+                })
+                .collect()
+        })
+    }
+
+    /// One line per breakpoint, for the debug overlay.
+    pub fn describe(&self, circuit: &Circuit) -> String {
+        let mut text = String::new();
+        for (index, breakpoint) in self.list.iter().enumerate() {
+            let separator = match breakpoint.combine {
+                Combine::All => " and ",
+                Combine::Any => " or ",
+            };
+            let triggers: Vec<String> = breakpoint
+                .triggers
+                .iter()
+                .map(|&(position, condition)| {
+                    let condition = match condition {
+                        Condition::Rises => "rises",
+                        Condition::Falls => "falls",
+                        Condition::Changes => "changes",
+                        Condition::High => "is high",
+                        Condition::Low => "is low",
+                    };
+                    format!("{:?} {}", <(i32, i32)>::from(position), condition)
+                })
+                .collect();
+            let inactive = breakpoint
+                .resolve(|&position| circuit.tile_cluster(position))
+                .is_none();
+            text.push_str(&format!(
+                "Breakpoint {}: {}{}\n",
+                index + 1,
+                triggers.join(separator),
+                if inactive { " (inactive)" } else { "" },
+            ));
+        }
+        text
+    }
+}
@@ -0,0 +1,312 @@
+//! Chips: subcircuits that are defined once and placed as a single component.
+//!
+//! A chip is defined from a [`Schematic`]. Its switches become input ports and its lamps become
+//! output ports, ordered from top to bottom (then left to right) in the schematic, and named by
+//! their labels (see [`Circuit::ports`]). Everything
+//! else is compiled down to a [`Netlist`] of flips and flops, with any chips used inside of it
+//! flattened into the same netlist.
+//!
+//! Each placed instance allocates its own clusters for the netlist. A port shares its cluster
+//! with the wire attached to it, the same way a pin does, so an instance has exactly the same
+//! timing as a flat copy of its schematic with every switch and lamp replaced by a pin.
+
+use super::{Circuit, ComponentData};
+use crate::direction::Direction;
+use crate::schematic::Schematic;
+use crate::simulation::Simulation;
+use anyhow::ensure;
+use glam::IVec2;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Chip definitions by ID. Definitions are never changed once they are added; editing a chip
+/// adds a new definition instead.
+pub type ChipLibrary = BTreeMap<u32, Arc<ChipDefinition>>;
+
+pub struct ChipDefinition {
+    name: String,
+    schematic: Schematic,
+    netlist: Netlist,
+    // Inputs first, then outputs.
+    port_names: Vec<String>,
+}
+
+impl ChipDefinition {
+    /// Compiles `schematic` into a chip, using `library` for any chips placed inside of it.
+    pub fn new(name: String, schematic: Schematic, library: &ChipLibrary) -> anyhow::Result<Self> {
+        let mut circuit = Circuit::with_chips(library.clone());
+        circuit.place_schematic(&schematic, IVec2::ZERO)?;
+        let netlist = Netlist::compile(&circuit);
+        ensure!(
+            !netlist.inputs.is_empty() || !netlist.outputs.is_empty(),
+            "a chip needs at least one switch or lamp to use as a port"
+        );
+        let ports = circuit.ports();
+        let port_names = ports
+            .inputs
+            .into_iter()
+            .chain(ports.outputs)
+            .map(|port| port.name)
+            .collect();
+        Ok(Self {
+            name,
+            schematic,
+            netlist,
+            port_names,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schematic(&self) -> &Schematic {
+        &self.schematic
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.netlist.inputs.len()
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.netlist.outputs.len()
+    }
+
+    /// The number of tiles that the body of the chip covers.
+    pub fn length(&self) -> i32 {
+        self.inputs().max(self.outputs()).max(1) as i32
+    }
+
+    /// The tiles covered by an instance at `position`, starting with `position` itself.
+    ///
+    /// The body extends to the right of `orientation`. Inputs are on the back of the body and
+    /// outputs on the front, one per tile.
+    pub fn tiles(&self, position: IVec2, orientation: Direction) -> impl Iterator<Item = IVec2> {
+        let step = orientation.right().offset();
+        (0..self.length()).map(move |index| position + step * index)
+    }
+
+    /// Where a port is: the tile, and the face of the tile that it connects to.
+    pub fn port_location(
+        &self,
+        port: usize,
+        position: IVec2,
+        orientation: Direction,
+    ) -> (IVec2, Direction) {
+        let step = orientation.right().offset();
+        if port < self.inputs() {
+            (position + step * port as i32, orientation.opposite())
+        } else {
+            let index = port - self.inputs();
+            (position + step * index as i32, orientation)
+        }
+    }
+
+    /// The port on the `face` of `tile`, for an instance at `position`, if there is one.
+    pub fn port_at(
+        &self,
+        position: IVec2,
+        orientation: Direction,
+        tile: IVec2,
+        face: Direction,
+    ) -> Option<usize> {
+        let index = usize::try_from((tile - position).dot(orientation.right().offset())).ok()?;
+        if face == orientation.opposite() && index < self.inputs() {
+            Some(index)
+        } else if face == orientation && index < self.outputs() {
+            Some(self.inputs() + index)
+        } else {
+            None
+        }
+    }
+
+    /// The name of a port: the label on its switch or lamp, or else `in0`, `in1`, ... for
+    /// inputs and `out0`, `out1`, ... for outputs.
+    pub fn port_name(&self, port: usize) -> &str {
+        &self.port_names[port]
+    }
+
+    pub fn num_ports(&self) -> usize {
+        self.inputs() + self.outputs()
+    }
+
+    /// The netlist cluster that a port is attached to.
+    pub(super) fn port_cluster_index(&self, port: usize) -> usize {
+        if port < self.inputs() {
+            self.netlist.inputs[port]
+        } else {
+            self.netlist.outputs[port - self.inputs()]
+        }
+    }
+}
+
+/// The flips and flops of a chip, between clusters numbered from zero.
+struct Netlist {
+    num_clusters: usize,
+    flips: Vec<(usize, usize)>,
+    flops: Vec<(usize, usize)>,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+}
+
+impl Netlist {
+    fn compile(circuit: &Circuit) -> Self {
+        let mut clusters = HashMap::new();
+        let mut number = |id: u32| {
+            let next = clusters.len();
+            *clusters.entry(id).or_insert(next)
+        };
+
+        let mut components: Vec<_> = circuit.components.iter().map(|(_, c)| c).collect();
+        components.sort_by_key(|component| (-component.position.y, component.position.x));
+
+        let mut netlist = Self {
+            num_clusters: 0,
+            flips: Vec::new(),
+            flops: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+        for component in components {
+            match &component.data {
+                ComponentData::Pin(..) => {}
+                ComponentData::Flip(state) => {
+                    netlist.flips.push((
+                        number(state.input_cluster_index),
+                        number(state.output_cluster_index),
+                    ));
+                }
+                ComponentData::Flop(state) => {
+                    netlist.flops.push((
+                        number(state.input_cluster_index),
+                        number(state.output_cluster_index),
+                    ));
+                }
+                ComponentData::Switch(state) => {
+                    netlist.inputs.push(number(state.output_cluster_index));
+                }
+                ComponentData::Lamp(state) => {
+                    netlist.outputs.push(number(state.cluster_index));
+                }
+                ComponentData::Chip(state) => {
+                    let inner = &state.definition.netlist;
+                    for &(input, output) in &inner.flips {
+                        netlist.flips.push((
+                            number(state.clusters[input]),
+                            number(state.clusters[output]),
+                        ));
+                    }
+                    for &(input, output) in &inner.flops {
+                        netlist.flops.push((
+                            number(state.clusters[input]),
+                            number(state.clusters[output]),
+                        ));
+                    }
+                }
+            }
+        }
+        netlist.num_clusters = clusters.len();
+        netlist
+    }
+}
+
+/// A placed instance of a chip, with the simulation clusters allocated for its netlist.
+pub(super) struct ChipState {
+    pub(super) id: u32,
+    pub(super) definition: Arc<ChipDefinition>,
+    clusters: Vec<u32>,
+}
+
+impl ChipState {
+    /// Adds an instance of `definition` to the simulation. `existing` gives the cluster of each
+    /// netlist cluster that is already connected to a wire; the rest are allocated.
+    pub(super) fn new(
+        id: u32,
+        definition: Arc<ChipDefinition>,
+        existing: &[Option<u32>],
+        simulation: &mut Simulation,
+    ) -> Self {
+        let clusters = (0..definition.netlist.num_clusters)
+            .map(|cluster| match existing.get(cluster).copied().flatten() {
+                Some(id) => id,
+                None => simulation.alloc_cluster(),
+            })
+            .collect();
+        let state = Self {
+            id,
+            definition,
+            clusters,
+        };
+        for &(input, output) in &state.definition.netlist.flips {
+            simulation.add_flip(state.clusters[input], state.clusters[output]);
+        }
+        for &(input, output) in &state.definition.netlist.flops {
+            simulation.add_flop(state.clusters[input], state.clusters[output]);
+        }
+        state
+    }
+
+    pub(super) fn num_clusters(&self) -> usize {
+        self.clusters.len()
+    }
+
+    pub(super) fn cluster(&self, index: usize) -> u32 {
+        self.clusters[index]
+    }
+
+    pub(super) fn port_cluster_index(&self, port: usize) -> usize {
+        self.definition.port_cluster_index(port)
+    }
+
+    /// The simulation cluster of a port.
+    pub(super) fn port_cluster(&self, port: usize) -> u32 {
+        self.clusters[self.port_cluster_index(port)]
+    }
+
+    /// Ports that share the netlist cluster of `port`, including `port` itself.
+    pub(super) fn connected_ports(&self, port: usize) -> impl Iterator<Item = usize> + '_ {
+        let cluster = self.port_cluster_index(port);
+        (0..self.definition.num_ports())
+            .filter(move |&other| self.port_cluster_index(other) == cluster)
+    }
+
+    /// Moves a netlist cluster to a different simulation cluster, along with every flip and flop
+    /// attached to it.
+    pub(super) fn relabel(&mut self, cluster: usize, id: u32, simulation: &mut Simulation) {
+        if self.clusters[cluster] == id {
+            return;
+        }
+        let netlist = &self.definition.netlist;
+        let touches = |&&(input, output): &&(usize, usize)| input == cluster || output == cluster;
+        for &(input, output) in netlist.flips.iter().filter(touches) {
+            simulation.remove_flip(self.clusters[input], self.clusters[output]);
+        }
+        for &(input, output) in netlist.flops.iter().filter(touches) {
+            simulation.remove_flop(self.clusters[input], self.clusters[output]);
+        }
+        self.clusters[cluster] = id;
+        for &(input, output) in netlist.flips.iter().filter(touches) {
+            simulation.add_flip(self.clusters[input], self.clusters[output]);
+        }
+        for &(input, output) in netlist.flops.iter().filter(touches) {
+            simulation.add_flop(self.clusters[input], self.clusters[output]);
+        }
+    }
+
+    /// Removes the flips and flops of the instance from the simulation, and frees every cluster
+    /// that isn't attached to a port.
+    pub(super) fn remove(&self, simulation: &mut Simulation) {
+        let netlist = &self.definition.netlist;
+        for &(input, output) in &netlist.flips {
+            simulation.remove_flip(self.clusters[input], self.clusters[output]);
+        }
+        for &(input, output) in &netlist.flops {
+            simulation.remove_flop(self.clusters[input], self.clusters[output]);
+        }
+        for (cluster, &id) in self.clusters.iter().enumerate() {
+            if !netlist.inputs.contains(&cluster) && !netlist.outputs.contains(&cluster) {
+                simulation.free_cluster(id);
+            }
+        }
+    }
+}
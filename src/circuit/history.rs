@@ -0,0 +1,104 @@
+use crate::schematic::{PlacedComponent, PlacedLabel, PlacedWire};
+use glam::IVec2;
+
+// Oldest entries are dropped once the undo stack grows past this.
+const MAX_ENTRIES: usize = 1000;
+
+/// A single primitive layout change.
+///
+/// Edits refer to components and wires by position rather than by handle, so they can still be
+/// replayed after the items they touch have been removed and re-created.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    InsertComponent(PlacedComponent),
+    RemoveComponent(PlacedComponent),
+    InsertWire(PlacedWire),
+    RemoveWire(PlacedWire),
+    ToggleSwitch(IVec2),
+    InsertLabel(PlacedLabel),
+    RemoveLabel(PlacedLabel),
+}
+
+impl Edit {
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::InsertComponent(component) => Self::RemoveComponent(*component),
+            Self::RemoveComponent(component) => Self::InsertComponent(*component),
+            Self::InsertWire(wire) => Self::RemoveWire(*wire),
+            Self::RemoveWire(wire) => Self::InsertWire(*wire),
+            Self::ToggleSwitch(position) => Self::ToggleSwitch(*position),
+            Self::InsertLabel(label) => Self::RemoveLabel(label.clone()),
+            Self::RemoveLabel(label) => Self::InsertLabel(label.clone()),
+        }
+    }
+}
+
+/// Undo and redo stacks, each entry holding the edits of one transaction in the order they
+/// were made.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    pending: Vec<Edit>,
+    depth: usize,
+}
+
+impl History {
+    pub fn begin(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Closes the innermost transaction. Once the outermost one is closed, everything recorded
+    /// in it becomes a single undo entry.
+    pub fn end(&mut self) {
+        self.depth -= 1;
+        if self.depth == 0 && !self.pending.is_empty() {
+            let entry = std::mem::take(&mut self.pending);
+            self.undo.push(entry);
+            if self.undo.len() > MAX_ENTRIES {
+                self.undo.remove(0);
+            }
+            self.redo.clear();
+        }
+    }
+
+    /// Closes the innermost transaction without keeping it. Once the outermost one is closed,
+    /// returns everything recorded in it so the caller can revert it.
+    pub fn abort(&mut self) -> Option<Vec<Edit>> {
+        self.depth -= 1;
+        if self.depth == 0 {
+            Some(std::mem::take(&mut self.pending))
+        } else {
+            None
+        }
+    }
+
+    /// Records an edit in the current transaction. Edits made outside of a transaction (for
+    /// example, while replaying an undo) are not recorded.
+    pub fn record(&mut self, edit: Edit) {
+        if self.depth > 0 {
+            self.pending.push(edit);
+        }
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Vec<Edit>> {
+        self.undo.pop()
+    }
+
+    pub fn push_undo(&mut self, entry: Vec<Edit>) {
+        self.undo.push(entry);
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Vec<Edit>> {
+        self.redo.pop()
+    }
+
+    pub fn push_redo(&mut self, entry: Vec<Edit>) {
+        self.redo.push(entry);
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
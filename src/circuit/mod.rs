@@ -0,0 +1,3152 @@
+mod chip;
+mod history;
+mod raster;
+mod renderer;
+
+pub use self::chip::{ChipDefinition, ChipLibrary};
+pub use self::raster::render_image;
+pub use self::renderer::CircuitRenderer;
+
+use self::chip::ChipState;
+use self::history::{Edit, History};
+
+use crate::depot::{self, Depot};
+use crate::direction::{Direction, Relative, Transform};
+use crate::rect::WireConnection;
+use crate::schematic::{
+    self, Anchor, PlacedComponent, PlacedLabel, PlacedWire, Schematic, SchematicChip,
+};
+use crate::simulation::{Command, Simulation};
+use anyhow::{ensure, Context};
+use glam::{IVec2, Vec2};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+static NEXT_CIRCUIT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The logical model of a circuit: its tiles, components, wires and clusters, along with the
+/// simulation that runs on them.
+///
+/// The model does not own any rendering state. Every edit is recorded in a set of
+/// [`Changes`], which a [`CircuitRenderer`] picks up to keep its sprites in sync.
+pub struct Circuit {
+    id: u64,
+    tiles: HashMap<IVec2, Tile>,
+    components: Depot<Component>,
+    wires: Depot<Wire>,
+    simulation: Simulation,
+    changes: Changes,
+    history: History,
+    chips: ChipLibrary,
+    labels: Vec<PlacedLabel>,
+}
+
+impl Circuit {
+    pub fn new() -> Self {
+        Self::with_chips(ChipLibrary::new())
+    }
+
+    /// Creates an empty circuit that can use the chips in `chips`.
+    pub fn with_chips(chips: ChipLibrary) -> Self {
+        Self {
+            id: NEXT_CIRCUIT_ID.fetch_add(1, Ordering::Relaxed),
+            tiles: HashMap::new(),
+            components: Depot::new(),
+            wires: Depot::new(),
+            simulation: Simulation::new(),
+            changes: Changes::default(),
+            history: History::default(),
+            chips,
+            labels: Vec::new(),
+        }
+    }
+
+    /// Identifies the circuit, so its observers can tell when they are looking at a new one.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    pub fn tick(&mut self) {
+        self.simulation.tick();
+    }
+
+    /// See [`Simulation::run_until_stable`].
+    pub fn run_until_stable(&mut self, max_ticks: u64) -> (u64, bool) {
+        self.simulation.run_until_stable(max_ticks)
+    }
+
+    /// Returns a copy of the simulation to be run elsewhere, such as on a
+    /// [`SimulationWorker`](crate::worker::SimulationWorker). Changes made to the circuit from
+    /// now on are journaled, and can be collected with [`Circuit::take_simulation_commands`].
+    pub fn detach_simulation(&mut self) -> Simulation {
+        self.simulation.start_journal();
+        let mut copy = self.simulation.clone();
+        copy.stop_journal();
+        copy
+    }
+
+    /// The changes made to the simulation since the last call, or since it was detached.
+    pub fn take_simulation_commands(&mut self) -> Vec<Command> {
+        self.simulation.take_journal()
+    }
+
+    /// Takes back a simulation that was detached and kept up to date with its commands, so that
+    /// its power states are not lost.
+    pub fn restore_simulation(&mut self, mut simulation: Simulation) {
+        simulation.start_journal();
+        self.simulation = simulation;
+    }
+
+    /// Loads a circuit that was written by [`Circuit::save`].
+    ///
+    /// Components and wires are rebuilt through [`Circuit::place_component`] and
+    /// [`Circuit::place_wire`], so clusters are recomputed from scratch.
+    pub fn load<R: BufRead>(reader: R) -> anyhow::Result<Self> {
+        let schematic = Schematic::read(reader)?;
+        let mut circuit = Self::new();
+        for chip in &schematic.chips {
+            let definition =
+                ChipDefinition::new(chip.name.clone(), chip.schematic.clone(), &circuit.chips)
+                    .with_context(|| format!("in chip {}", chip.id))?;
+            circuit.chips.insert(chip.id, Arc::new(definition));
+        }
+        circuit.place_schematic(&schematic, IVec2::ZERO)?;
+        circuit.history.clear();
+        Ok(circuit)
+    }
+
+    /// Writes the circuit to `writer` and flushes it; see [`Schematic::write`].
+    pub fn save<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.schematic().write(writer)
+    }
+
+    /// Describes the current layout, ordered by position so the output is stable.
+    pub fn schematic(&self) -> Schematic {
+        let mut components: Vec<PlacedComponent> = self
+            .components
+            .iter()
+            .map(|(_, component)| component.placed())
+            .collect();
+        components.sort_by_key(|component| <[i32; 2]>::from(component.position));
+
+        let mut wires: Vec<PlacedWire> = self
+            .wires
+            .iter()
+            .map(|(_, wire)| PlacedWire {
+                start: wire.start,
+                end: wire.end,
+            })
+            .collect();
+        wires.sort_by_key(|wire| (<[i32; 2]>::from(wire.start), <[i32; 2]>::from(wire.end)));
+
+        // Every chip that is used, directly or inside of another chip.
+        let mut used = BTreeSet::new();
+        let mut queue: Vec<u32> = components.iter().filter_map(|c| c.ty.chip_id()).collect();
+        while let Some(id) = queue.pop() {
+            if used.insert(id) {
+                let definition = &self.chips[&id];
+                queue.extend(
+                    definition
+                        .schematic()
+                        .components
+                        .iter()
+                        .filter_map(|c| c.ty.chip_id()),
+                );
+            }
+        }
+        // Labels on tiles come first, in the same order as components, then the ones that are
+        // free.
+        let mut labels = self.labels.clone();
+        labels.sort_by(|a, b| match (a.anchor, b.anchor) {
+            (Anchor::Tile(a), Anchor::Tile(b)) => <[i32; 2]>::from(a).cmp(&b.into()),
+            (Anchor::Tile(..), Anchor::Free(..)) => std::cmp::Ordering::Less,
+            (Anchor::Free(..), Anchor::Tile(..)) => std::cmp::Ordering::Greater,
+            (Anchor::Free(a), Anchor::Free(b)) => a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)),
+        });
+
+        let chips = used
+            .into_iter()
+            .map(|id| SchematicChip {
+                id,
+                name: self.chips[&id].name().to_owned(),
+                schematic: self.chips[&id].schematic().clone(),
+            })
+            .collect();
+
+        Schematic {
+            components,
+            wires,
+            labels,
+            chips,
+        }
+    }
+
+    pub fn chips(&self) -> &ChipLibrary {
+        &self.chips
+    }
+
+    /// A copy of the circuit with its own simulation and no undo history, for trying out inputs
+    /// without disturbing this one. Power states start over from the switches.
+    pub fn duplicate(&self) -> anyhow::Result<Circuit> {
+        let mut circuit = Circuit::with_chips(self.chips.clone());
+        circuit.place_schematic(&self.schematic(), IVec2::ZERO)?;
+        circuit.history.clear();
+        Ok(circuit)
+    }
+
+    /// Defines a new chip from everything inside the rectangle from `min` to `max` (inclusive),
+    /// and returns its ID.
+    pub fn define_chip(&mut self, min: IVec2, max: IVec2) -> anyhow::Result<u32> {
+        let id = self.next_chip_id();
+        let definition = ChipDefinition::new(
+            format!("chip {}", id),
+            self.copy_region(min, max),
+            &self.chips,
+        )?;
+        self.chips.insert(id, Arc::new(definition));
+        Ok(id)
+    }
+
+    /// Creates a circuit for editing the contents of a chip. Hand it back to
+    /// [`Circuit::finish_chip_edit`] when done.
+    pub fn edit_chip(&self, id: u32) -> anyhow::Result<Circuit> {
+        let definition = self.chips.get(&id).context("no such chip")?;
+        let mut circuit = Circuit::with_chips(self.chips.clone());
+        circuit.place_schematic(definition.schematic(), IVec2::ZERO)?;
+        circuit.history.clear();
+        Ok(circuit)
+    }
+
+    /// Turns an edited chip back into a definition, and switches every instance of chip `id`
+    /// over to it. Returns the ID of the new definition.
+    ///
+    /// Fails without changing any instances if the edited chip doesn't compile, or if its ports
+    /// no longer line up with the wires attached to an instance.
+    pub fn finish_chip_edit(&mut self, id: u32, edited: &Circuit) -> anyhow::Result<u32> {
+        // Chips may have been defined while editing.
+        for (&chip_id, definition) in &edited.chips {
+            self.chips
+                .entry(chip_id)
+                .or_insert_with(|| definition.clone());
+        }
+        let old = self.chips.get(&id).context("no such chip")?.clone();
+        let mut schematic = edited.schematic();
+        schematic.chips.clear();
+        if &schematic == old.schematic() {
+            return Ok(id);
+        }
+
+        let new_id = self.next_chip_id();
+        let definition = ChipDefinition::new(old.name().to_owned(), schematic, &self.chips)?;
+        self.chips.insert(new_id, Arc::new(definition));
+
+        let instances: Vec<depot::Handle> = self
+            .components
+            .iter()
+            .filter(|(_, component)| component.get_type() == ComponentType::Chip(id))
+            .map(|(handle, _)| handle)
+            .collect();
+        let result = self.try_transaction(|circuit| {
+            for handle in instances {
+                let component = circuit.components.get(&handle);
+                let (position, orientation) = (component.position, component.orientation);
+                let wires = circuit.remove_with_wires(handle);
+                ensure!(
+                    circuit.place_component(ComponentType::Chip(new_id), position, orientation),
+                    "the edited chip doesn't fit at {}",
+                    position,
+                );
+                for wire in wires {
+                    ensure!(
+                        circuit.place_wire(wire.start, wire.end),
+                        "the wire from {} to {} no longer lines up with a port of the chip at {}",
+                        wire.start,
+                        wire.end,
+                        position,
+                    );
+                }
+            }
+            Ok(())
+        });
+        if let Err(err) = result {
+            self.chips.remove(&new_id);
+            return Err(err);
+        }
+        Ok(new_id)
+    }
+
+    fn next_chip_id(&self) -> u32 {
+        self.chips.keys().next_back().map_or(0, |id| id + 1)
+    }
+
+    /// Places every component, wire and label of `schematic`, shifted by `offset`.
+    ///
+    /// A pin may be placed on top of an existing pin, which connects the two; any other
+    /// component or wire that doesn't fit fails with an error, leaving the edits made so far in
+    /// place. Use it inside [`Circuit::try_transaction`] to undo them.
+    fn place_schematic(&mut self, schematic: &Schematic, offset: IVec2) -> anyhow::Result<()> {
+        // Wires must not overlap existing wires lengthwise; `place_wire` doesn't check for that.
+        for wire in &schematic.wires {
+            let (start, end) = (wire.start + offset, wire.end + offset);
+            let direction = wire_direction(start, end);
+            for position in wire_tiles(start, end) {
+                let tile = match self.tile(position) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                ensure!(
+                    (position == start || tile.wires.get(direction.opposite()).is_none())
+                        && (position == end || tile.wires.get(direction).is_none()),
+                    "wire from {} to {} overlaps an existing wire",
+                    start,
+                    end,
+                );
+            }
+        }
+
+        // Components go first, so every wire segment ends at the component it was attached to.
+        for component in &schematic.components {
+            let position = component.position + offset;
+            if component.ty == ComponentType::Pin
+                && self.component_at(position) == Some(ComponentType::Pin)
+            {
+                continue;
+            }
+            ensure!(
+                self.place_component(component.ty, position, component.orientation),
+                "cannot place {:?} at {}",
+                component.ty,
+                position,
+            );
+            if component.switched {
+                self.toggle_switch(position);
+            }
+        }
+        for wire in &schematic.wires {
+            let (start, end) = (wire.start + offset, wire.end + offset);
+            ensure!(
+                self.place_wire(start, end),
+                "cannot place wire from {} to {}",
+                start,
+                end,
+            );
+        }
+        for label in &schematic.labels {
+            self.set_label(label.anchor.offset(offset), &label.text);
+        }
+        Ok(())
+    }
+
+    /// Copies every component and label inside the rectangle from `min` to `max` (inclusive),
+    /// along with the parts of wires that lie inside it. Positions are relative to `min`.
+    ///
+    /// Wires that cross the boundary are cut off at the edge of the rectangle and end in a new
+    /// pin there.
+    pub fn copy_region(&self, min: IVec2, max: IVec2) -> Schematic {
+        let mut schematic = Schematic::new();
+        for (_, component) in self.components.iter() {
+            if component.overlaps(min, max) {
+                let mut placed = component.placed();
+                placed.position -= min;
+                schematic.components.push(placed);
+            }
+        }
+        for (_, wire) in self.wires.iter() {
+            let (start, end) = match self.clip_wire(wire, min, max) {
+                Some(x) => x,
+                None => continue,
+            };
+            for position in [start, end] {
+                if position != wire.start && position != wire.end {
+                    schematic.components.push(PlacedComponent {
+                        ty: ComponentType::Pin,
+                        position: position - min,
+                        orientation: Direction::East,
+                        switched: false,
+                    });
+                }
+            }
+            schematic.wires.push(PlacedWire {
+                start: start - min,
+                end: end - min,
+            });
+        }
+        for label in &self.labels {
+            if label_in_region(label.anchor, min, max) {
+                schematic.labels.push(PlacedLabel {
+                    anchor: label.anchor.offset(-min),
+                    text: label.text.clone(),
+                });
+            }
+        }
+        schematic
+    }
+
+    /// Deletes every component and label inside the rectangle from `min` to `max` (inclusive),
+    /// along with the parts of wires that lie inside it.
+    ///
+    /// Wires that cross the boundary are cut off just outside the rectangle, and end in a new
+    /// pin there.
+    pub fn delete_region(&mut self, min: IVec2, max: IVec2) {
+        let mut outside = Vec::new();
+        let mut removed_wires = Vec::new();
+        for (handle, wire) in self.wires.iter() {
+            if !wire.tiles().any(|position| in_region(position, min, max)) {
+                continue;
+            }
+            removed_wires.push(handle);
+
+            // The pieces that are left over on either side. A piece ends just outside of the
+            // rectangle, unless that would put the new pin on a crossover.
+            let step = (wire.end - wire.start).clamp(IVec2::splat(-1), IVec2::splat(1));
+            let first_inside = wire.start.clamp(min, max);
+            let last_inside = wire.end.clamp(min, max);
+            if first_inside != wire.start {
+                let end = self.cut_point(first_inside - step, -step, wire.start);
+                if end != wire.start {
+                    outside.push((wire.start, end));
+                }
+            }
+            if last_inside != wire.end {
+                let start = self.cut_point(last_inside + step, step, wire.end);
+                if start != wire.end {
+                    outside.push((start, wire.end));
+                }
+            }
+        }
+        let removed_components: Vec<depot::Handle> = self
+            .components
+            .iter()
+            .filter(|(_, component)| component.overlaps(min, max))
+            .map(|(handle, _)| handle)
+            .collect();
+        let removed_labels: Vec<PlacedLabel> = self
+            .labels
+            .iter()
+            .filter(|label| label_in_region(label.anchor, min, max))
+            .cloned()
+            .collect();
+
+        self.history.begin();
+        for label in removed_labels {
+            self.remove_label(&label);
+        }
+        for handle in removed_wires {
+            self.remove_wire(handle);
+        }
+        for handle in removed_components {
+            // Chips can stick out of the rectangle, so they take their wires with them.
+            self.remove_with_wires(handle);
+        }
+        for (start, end) in outside {
+            self.place_wire(start, end);
+        }
+        self.history.end();
+    }
+
+    /// Pastes a schematic, shifted by `offset`. If any part of it can't be placed, nothing is.
+    pub fn paste(&mut self, schematic: &Schematic, offset: IVec2) -> anyhow::Result<()> {
+        self.try_transaction(|circuit| circuit.place_schematic(schematic, offset))
+    }
+
+    /// Moves everything inside the rectangle from `min` to `max` (inclusive) by `offset`, as
+    /// one undoable edit. If the moved parts don't fit at their destination, nothing is moved.
+    pub fn move_region(&mut self, min: IVec2, max: IVec2, offset: IVec2) -> anyhow::Result<()> {
+        let schematic = self.copy_region(min, max);
+        self.try_transaction(|circuit| {
+            circuit.delete_region(min, max);
+            circuit.place_schematic(&schematic, min + offset)
+        })
+    }
+
+    pub fn tile_debug_info(&self, pos: IVec2) -> TileDebugInfo {
+        TileDebugInfo { circuit: self, pos }
+    }
+
+    pub fn tile(&self, pos: IVec2) -> Option<&Tile> {
+        self.tiles.get(&pos)
+    }
+
+    pub fn component_at(&self, pos: IVec2) -> Option<ComponentType> {
+        self.component(pos).map(|component| component.get_type())
+    }
+
+    /// The cluster that stands for the tile at `pos`, for watching it: the output of a
+    /// component, the port of a chip (preferring outputs), or else the first wire.
+    pub fn tile_cluster(&self, pos: IVec2) -> Option<u32> {
+        let tile = self.tile(pos)?;
+        if let Some(handle) = tile.component {
+            let component = self.components.get(&handle);
+            match &component.data {
+                ComponentData::Pin(state) => return Some(state.cluster_index),
+                ComponentData::Flip(state) => return Some(state.output_cluster_index),
+                ComponentData::Flop(state) => return Some(state.output_cluster_index),
+                ComponentData::Switch(state) => return Some(state.output_cluster_index),
+                ComponentData::Lamp(state) => return Some(state.cluster_index),
+                ComponentData::Chip(state) => {
+                    let faces = [component.orientation, component.orientation.opposite()];
+                    if let Some(port) = faces.iter().find_map(|&face| component.port_at(pos, face))
+                    {
+                        return Some(state.port_cluster(port));
+                    }
+                }
+            }
+        }
+        let directions = [
+            Direction::East,
+            Direction::West,
+            Direction::North,
+            Direction::South,
+        ];
+        let wire = directions
+            .into_iter()
+            .find_map(|direction| tile.wires.get(direction))?;
+        Some(self.wires.get(&wire).cluster_index)
+    }
+
+    /// The cluster that the switch at `pos` powers while it is on. The switch is a flop from
+    /// it to the wire on its output, so the wire follows a tick later.
+    pub fn switch_input_cluster(&self, pos: IVec2) -> Option<u32> {
+        match &self.component(pos)?.data {
+            ComponentData::Switch(state) => Some(state.input_cluster_index),
+            _ => None,
+        }
+    }
+
+    /// Whether the switch at `pos` is on, or `None` if there is no switch there.
+    pub fn is_switched(&self, pos: IVec2) -> Option<bool> {
+        match &self.component(pos)?.data {
+            ComponentData::Switch(state) => Some(state.switched),
+            _ => None,
+        }
+    }
+
+    /// The input and output clusters of the flip, flop or switch at `pos`.
+    pub fn component_clusters(&self, pos: IVec2) -> Option<(u32, u32)> {
+        match &self.component(pos)?.data {
+            ComponentData::Flip(state) => {
+                Some((state.input_cluster_index, state.output_cluster_index))
+            }
+            ComponentData::Flop(state) => {
+                Some((state.input_cluster_index, state.output_cluster_index))
+            }
+            ComponentData::Switch(state) => {
+                Some((state.input_cluster_index, state.output_cluster_index))
+            }
+            _ => None,
+        }
+    }
+
+    /// Every tile that something is on, with its cluster (see [`Circuit::tile_cluster`]), from
+    /// top to bottom and then left to right.
+    pub fn cluster_tiles(&self) -> Vec<(IVec2, u32)> {
+        let mut tiles: Vec<(IVec2, u32)> = self
+            .tiles
+            .keys()
+            .filter_map(|&pos| Some((pos, self.tile_cluster(pos)?)))
+            .collect();
+        tiles.sort_by_key(|&(pos, _)| (-pos.y, pos.x));
+        tiles
+    }
+
+    /// Holds the cluster of the tile at `pos` (see [`Circuit::tile_cluster`]) on or off, or
+    /// releases it with `None`. Overrides are not edits, so they can't be undone, and they stay
+    /// on the wires when clusters are merged or split.
+    pub fn force(&mut self, pos: IVec2, value: Option<bool>) -> bool {
+        match self.tile_cluster(pos) {
+            Some(cluster) => {
+                self.simulation.force(cluster, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The value that the cluster of the tile at `pos` is held at, if any.
+    pub fn forced(&self, pos: IVec2) -> Option<bool> {
+        self.tile_cluster(pos)
+            .and_then(|cluster| self.simulation.forced(cluster))
+    }
+
+    /// Releases every cluster that is held on or off.
+    pub fn release_all(&mut self) {
+        let forced: Vec<u32> = self
+            .simulation
+            .forced_clusters()
+            .map(|(cluster, _)| cluster)
+            .collect();
+        for cluster in forced {
+            self.simulation.force(cluster, None);
+        }
+    }
+
+    /// Every forced cluster with its value and a tile that stands for it (the first one by row,
+    /// then column), in order of cluster IDs.
+    pub fn forced_tiles(&self) -> Vec<(u32, bool, IVec2)> {
+        let mut forced: Vec<(u32, bool, Option<IVec2>)> = self
+            .simulation
+            .forced_clusters()
+            .map(|(cluster, value)| (cluster, value, None))
+            .collect();
+        if forced.is_empty() {
+            return Vec::new();
+        }
+        for &position in self.tiles.keys() {
+            let cluster = match self.tile_cluster(position) {
+                Some(x) => x,
+                None => continue,
+            };
+            if let Ok(index) = forced.binary_search_by_key(&cluster, |entry| entry.0) {
+                let tile = &mut forced[index].2;
+                if tile.is_none_or(|tile| (position.y, position.x) < (tile.y, tile.x)) {
+                    *tile = Some(position);
+                }
+            }
+        }
+        forced
+            .into_iter()
+            .filter_map(|(cluster, value, tile)| Some((cluster, value, tile?)))
+            .collect()
+    }
+
+    pub fn labels(&self) -> &[PlacedLabel] {
+        &self.labels
+    }
+
+    /// The text of the label on the tile at `pos`, if there is one.
+    pub fn label_at(&self, pos: IVec2) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|label| label.anchor == Anchor::Tile(pos))
+            .map(|label| label.text.as_str())
+    }
+
+    /// The free label closest to `point`, if there is one within `distance` of it.
+    pub fn free_label_near(&self, point: Vec2, distance: f32) -> Option<Vec2> {
+        self.labels
+            .iter()
+            .filter_map(|label| match label.anchor {
+                Anchor::Free(anchor) => Some(anchor),
+                Anchor::Tile(..) => None,
+            })
+            .filter(|anchor| anchor.distance(point) <= distance)
+            .min_by(|a, b| a.distance(point).total_cmp(&b.distance(point)))
+    }
+
+    /// Replaces the text of the label at `anchor` as one undoable edit, adding the label if
+    /// there isn't one yet. Empty text removes the label.
+    pub fn set_label(&mut self, anchor: Anchor, text: &str) {
+        let text = schematic::normalize_text(text);
+        let old = self
+            .labels
+            .iter()
+            .find(|label| label.anchor == anchor)
+            .cloned();
+        if old.as_ref().map(|label| label.text.as_str()) == Some(text.as_str()) {
+            return;
+        }
+        self.history.begin();
+        if let Some(old) = old {
+            self.remove_label(&old);
+        }
+        if !text.is_empty() {
+            self.insert_label(PlacedLabel { anchor, text });
+        }
+        self.history.end();
+    }
+
+    /// The switches and lamps of the circuit, which are its inputs and outputs when it is used
+    /// as a chip or by other tooling.
+    ///
+    /// Ports are ordered from top to bottom, then left to right. Each one is named by the label
+    /// on its tile, or else `in0`, `in1`, ... for inputs and `out0`, `out1`, ... for outputs.
+    pub fn ports(&self) -> Ports {
+        let mut components: Vec<&Component> = self.components.iter().map(|(_, c)| c).collect();
+        components.sort_by_key(|component| (-component.position.y, component.position.x));
+        let mut ports = Ports::default();
+        for component in components {
+            let (list, prefix) = match component.data {
+                ComponentData::Switch(..) => (&mut ports.inputs, "in"),
+                ComponentData::Lamp(..) => (&mut ports.outputs, "out"),
+                _ => continue,
+            };
+            let name = match self.label_at(component.position) {
+                Some(label) => label.to_owned(),
+                None => format!("{}{}", prefix, list.len()),
+            };
+            list.push(Port {
+                name,
+                position: component.position,
+            });
+        }
+        ports
+    }
+
+    /// Finds the switch or lamp, as given by `ty`, that `reference` is to. Names are looked up
+    /// among the [`Circuit::ports`], and must be unique.
+    pub fn find_port(&self, reference: &PortRef, ty: ComponentType) -> anyhow::Result<Port> {
+        let (ports, kind) = match ty {
+            ComponentType::Switch => (self.ports().inputs, "switch"),
+            _ => (self.ports().outputs, "lamp"),
+        };
+        match reference {
+            PortRef::Name(name) => {
+                let mut found = ports.into_iter().filter(|port| &port.name == name);
+                let port = found
+                    .next()
+                    .with_context(|| format!("no {} is named `{}`", kind, name))?;
+                ensure!(
+                    found.next().is_none(),
+                    "more than one {} is named `{}`",
+                    kind,
+                    name
+                );
+                Ok(port)
+            }
+            &PortRef::Position(position) => {
+                ensure!(
+                    self.component_at(position) == Some(ty),
+                    "there is no {} at {}",
+                    kind,
+                    position
+                );
+                let name = self
+                    .label_at(position)
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| format!("{},{}", position.x, position.y));
+                Ok(Port { name, position })
+            }
+        }
+    }
+
+    pub fn interact(&mut self, pos: IVec2) {
+        if self.component_at(pos) == Some(ComponentType::Switch) {
+            self.history.begin();
+            self.toggle_switch(pos);
+            self.history.end();
+        }
+    }
+
+    /// Reverts the most recent edit. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let entry = match self.history.pop_undo() {
+            Some(x) => x,
+            None => return false,
+        };
+        for edit in entry.iter().rev() {
+            self.apply(edit.inverse());
+        }
+        self.history.push_redo(entry);
+        true
+    }
+
+    /// Re-applies the most recently undone edit. Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let entry = match self.history.pop_redo() {
+            Some(x) => x,
+            None => return false,
+        };
+        for edit in &entry {
+            self.apply(edit.clone());
+        }
+        self.history.push_undo(entry);
+        true
+    }
+
+    /// Rotates or mirrors the component at `position` in place, keeping the wires that are
+    /// attached to it.
+    ///
+    /// Fails without changing anything if the component or any of its wires would break the
+    /// placement rules in its new orientation.
+    pub fn transform_component(
+        &mut self,
+        position: IVec2,
+        transform: Transform,
+    ) -> anyhow::Result<()> {
+        let handle = self
+            .tile(position)
+            .and_then(|tile| tile.component)
+            .context("there is no component here")?;
+        let mut component = self.components.get(&handle).placed();
+        let position = component.position;
+        ensure!(
+            component.ty.chip_id().is_none() || matches!(transform, Transform::Rotate(..)),
+            "chips can't be mirrored"
+        );
+        component.orientation = transform.direction(component.orientation);
+
+        self.try_transaction(|circuit| {
+            // Detach the wires, so they can be re-attached under the placement rules.
+            let wires = circuit.remove_with_wires(handle);
+            ensure!(
+                circuit.place_component(component.ty, position, component.orientation),
+                "a {:?} can't face {:?} at {}",
+                component.ty,
+                component.orientation,
+                position,
+            );
+            if component.switched {
+                circuit.toggle_switch(position);
+            }
+            for wire in wires {
+                ensure!(
+                    circuit.place_wire(wire.start, wire.end),
+                    "the wire from {} to {} can't connect to a {:?} facing {:?}",
+                    wire.start,
+                    wire.end,
+                    component.ty,
+                    component.orientation,
+                );
+            }
+            Ok(())
+        })
+    }
+
+    /// Rotates or mirrors everything inside the rectangle from `min` to `max` (inclusive) about
+    /// its center, and returns the rectangle that it occupies afterwards.
+    ///
+    /// Wires that cross the boundary are cut, as in [`Circuit::delete_region`]. Fails without
+    /// changing anything if the result doesn't fit.
+    pub fn transform_region(
+        &mut self,
+        min: IVec2,
+        max: IVec2,
+        transform: Transform,
+    ) -> anyhow::Result<(IVec2, IVec2)> {
+        let size = max - min;
+        let schematic = self.copy_region(min, max);
+        ensure!(
+            matches!(transform, Transform::Rotate(..))
+                || schematic
+                    .components
+                    .iter()
+                    .all(|c| c.ty.chip_id().is_none()),
+            "chips can't be mirrored"
+        );
+        let (schematic, new_size) = schematic.transform(transform, size);
+        let new_min = min + (size - new_size) / 2;
+        self.try_transaction(|circuit| {
+            circuit.delete_region(min, max);
+            circuit.place_schematic(&schematic, new_min)
+        })?;
+        Ok((new_min, new_min + new_size))
+    }
+
+    /// Runs `f` as a single undoable edit. If it fails, every edit it made is reverted.
+    ///
+    /// When nested inside another transaction, the edits are only reverted once the error
+    /// reaches the outermost one.
+    fn try_transaction<T, F>(&mut self, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&mut Self) -> anyhow::Result<T>,
+    {
+        self.history.begin();
+        let result = f(self);
+        if result.is_ok() {
+            self.history.end();
+        } else if let Some(edits) = self.history.abort() {
+            for edit in edits.iter().rev() {
+                self.apply(edit.inverse());
+            }
+        }
+        result
+    }
+
+    /// Removes a component along with every wire attached to it, and returns the wires.
+    fn remove_with_wires(&mut self, handle: depot::Handle) -> Vec<Wire> {
+        let mut wire_handles = Vec::new();
+        for position in self.components.get(&handle).tiles() {
+            for id in self.tiles[&position].wires.as_array().into_iter().flatten() {
+                if !wire_handles.contains(&id) {
+                    wire_handles.push(id);
+                }
+            }
+        }
+        let wires = wire_handles
+            .into_iter()
+            .map(|handle| self.remove_wire(handle))
+            .collect();
+        self.remove_component(handle);
+        wires
+    }
+
+    pub fn can_place_wire(&self, start: IVec2, end: IVec2) -> bool {
+        let wire_direction = wire_direction(start, end);
+
+        // All the tiles on the wire's path must allow the wire.
+        for tile_pos in wire_tiles(start, end) {
+            let tile = match self.tile(tile_pos) {
+                Some(x) => x,
+                None => {
+                    // Wires can always be placed on empty tiles.
+                    continue;
+                }
+            };
+            if let Some(component_id) = &tile.component {
+                let component = self.components.get(component_id);
+                match component.get_type() {
+                    ComponentType::Pin | ComponentType::Lamp => {
+                        // Wires can always be placed across pins and lamps.
+                    }
+                    ComponentType::Flip => {
+                        // Wires can be placed across flips if it connects to _either_ the input or
+                        // the output, but not both.
+
+                        // If the flip is not at the start or end of the wire, the wire can be
+                        // placed across the flip if it only connects to the input pin; the output
+                        // pin must not be in the path of the wire.
+                        let illegal_directions =
+                            [component.orientation, component.orientation.opposite()];
+                        if tile_pos != start
+                            && tile_pos != end
+                            && illegal_directions.contains(&wire_direction)
+                        {
+                            return false;
+                        }
+
+                        // If the flip is at the start or end of the wire, then it is always legal.
+                    }
+                    ComponentType::Flop => {
+                        // Wires can _never_ be placed across flops.
+                        // (The flop must only be at the start or end of the wire).
+                        if tile_pos != start && tile_pos != end {
+                            return false;
+                        }
+
+                        // Wire endpoints can only connect to the input or the output of a flop;
+                        // the other sides are illegal.
+                        let illegal_directions =
+                            [component.orientation.left(), component.orientation.right()];
+                        if illegal_directions.contains(&wire_direction) {
+                            return false;
+                        }
+                    }
+                    ComponentType::Switch => {
+                        // Wires can never be placed across switches;
+                        // the switch must be at the start or end of the wire.
+                        if tile_pos != start && tile_pos != end {
+                            return false;
+                        }
+
+                        // The wire must be in the same direction if it is at the start,
+                        // or the opposite direction if it is at the end.
+                        if tile_pos == start && wire_direction != component.orientation
+                            || tile_pos == end && wire_direction != component.orientation.opposite()
+                        {
+                            return false;
+                        }
+                    }
+                    ComponentType::Chip(..) => {
+                        // Wires can only connect to the ports of a chip.
+                        let face = if tile_pos == start {
+                            wire_direction
+                        } else if tile_pos == end {
+                            wire_direction.opposite()
+                        } else {
+                            return false;
+                        };
+                        if component.port_at(tile_pos, face).is_none() {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    pub fn place_wire(&mut self, start: IVec2, end: IVec2) -> bool {
+        if !self.can_place_wire(start, end) {
+            return false;
+        }
+        self.history.begin();
+
+        self.place_component(ComponentType::Pin, start, Direction::East);
+        self.place_component(ComponentType::Pin, end, Direction::East);
+
+        // Split the wire at every tile where a component is present.
+        let split_points: Vec<IVec2> = wire_tiles(start, end)
+            .filter(|pos| {
+                self.tiles
+                    .get(&pos)
+                    .and_then(|tile| tile.component)
+                    .is_some()
+            })
+            .collect();
+
+        for v in split_points.windows(2) {
+            let sub_start = v[0];
+            let sub_end = v[1];
+            self.insert_wire(sub_start, sub_end);
+        }
+        self.history.end();
+        true
+    }
+
+    pub fn can_place_component(
+        &self,
+        ty: ComponentType,
+        position: IVec2,
+        orientation: Direction,
+    ) -> bool {
+        if let ComponentType::Chip(id) = ty {
+            // Chips can only be placed if their whole body is on empty tiles.
+            let definition = match self.chips.get(&id) {
+                Some(x) => x,
+                None => return false,
+            };
+            return definition.tiles(position, orientation).all(|position| {
+                self.tile(position)
+                    .is_none_or(|tile| tile.component.is_none() && tile.wires.count() == 0)
+            });
+        }
+
+        let tile = match self.tile(position) {
+            Some(x) => x,
+            None => {
+                // An empty tile is always legal.
+                return true;
+            }
+        };
+
+        // Components cannot be placed on a tile that already has a component.
+        if tile.component.is_some() {
+            return false;
+        }
+
+        match ty {
+            ComponentType::Pin | ComponentType::Lamp => {
+                // Pins and lamps have no special rules.
+            }
+            ComponentType::Flip => {
+                // Flips can be placed if there is no wire on the output side.
+                if tile.wires.get(orientation).is_some() {
+                    return false;
+                }
+            }
+            ComponentType::Flop | ComponentType::Switch => {
+                // Flops and switches cannot be placed on any location that has a wire.
+                if tile.wires.count() != 0 {
+                    return false;
+                }
+            }
+            ComponentType::Chip(..) => unreachable!(),
+        }
+        true
+    }
+
+    pub fn place_component(
+        &mut self,
+        ty: ComponentType,
+        position: IVec2,
+        orientation: Direction,
+    ) -> bool {
+        if !self.can_place_component(ty, position, orientation) {
+            return false;
+        }
+        self.history.begin();
+
+        let tile = self.tiles.entry(position).or_default();
+
+        // Logically split wires that pass over this tile,
+        // so they connect through the pin.
+        let wires = tile.wires.clone();
+        if let Some(wire_id) = wires.north {
+            if wires.north == wires.south {
+                let wire = self.remove_wire(wire_id);
+                self.insert_wire(wire.start, position);
+                self.insert_wire(position, wire.end);
+            }
+        }
+        if let Some(wire_id) = wires.east {
+            if wires.east == wires.west {
+                let wire = self.remove_wire(wire_id);
+                self.insert_wire(wire.start, position);
+                self.insert_wire(position, wire.end);
+            }
+        }
+
+        self.insert_component(ty, position, orientation);
+        self.history.end();
+        true
+    }
+
+    pub fn delete_component(&mut self, position: IVec2) {
+        if let Some(tile) = self.tiles.get(&position).cloned() {
+            let component_id = match tile.component {
+                Some(id) => id,
+                None => return,
+            };
+            self.history.begin();
+            let component = self.remove_component(component_id);
+            self.remove_wires_on_body(&component, position);
+
+            let north = tile.wires.north.map(|id| self.remove_wire(id));
+            let east = tile.wires.east.map(|id| self.remove_wire(id));
+            let south = tile.wires.south.map(|id| self.remove_wire(id));
+            let west = tile.wires.west.map(|id| self.remove_wire(id));
+
+            match component.get_type() {
+                ComponentType::Pin => {
+                    // Convert pin to crossover; merge opposite wires.
+
+                    if let (Some(north), Some(south)) = (north, south) {
+                        self.insert_wire(south.start, north.end);
+                    }
+                    if let (Some(east), Some(west)) = (east, west) {
+                        self.insert_wire(west.start, east.end);
+                    }
+                }
+                ComponentType::Flip => {}
+                ComponentType::Flop => {}
+                ComponentType::Switch => {}
+                ComponentType::Lamp => {}
+                ComponentType::Chip(..) => {}
+            }
+            self.history.end();
+        }
+    }
+
+    pub fn delete_all_at(&mut self, position: IVec2) {
+        if let Some(tile) = self.tiles.get(&position).cloned() {
+            self.history.begin();
+            if let Some(component_id) = tile.component {
+                let component = self.remove_component(component_id);
+                self.remove_wires_on_body(&component, position);
+            }
+
+            let wires = tile.wires.clone();
+            if let Some(id) = wires.north {
+                self.remove_wire(id);
+            }
+            if let Some(id) = wires.south {
+                if wires.south != wires.north {
+                    self.remove_wire(id);
+                }
+            }
+            if let Some(id) = wires.east {
+                self.remove_wire(id);
+            }
+            if let Some(id) = wires.west {
+                if wires.west != wires.east {
+                    self.remove_wire(id);
+                }
+            }
+            self.history.end();
+        }
+    }
+
+    /// Removes the wires attached to the tiles of a removed chip, other than the tile at
+    /// `position`.
+    fn remove_wires_on_body(&mut self, component: &Component, position: IVec2) {
+        for body_position in component.tiles() {
+            if body_position == position {
+                continue;
+            }
+            let wires = self.tiles[&body_position].wires.clone();
+            for id in wires.as_array().into_iter().flatten() {
+                self.remove_wire(id);
+            }
+        }
+    }
+
+    pub fn wire_connection(&self, position: IVec2, direction: Direction) -> Option<WireConnection> {
+        self.component(position)
+            .map(|component| component.connection_type(direction))
+    }
+
+    fn insert_component(
+        &mut self,
+        ty: ComponentType,
+        position: IVec2,
+        orientation: Direction,
+    ) -> bool {
+        if self
+            .tile(position)
+            .and_then(|tile| tile.component)
+            .is_some()
+        {
+            return false;
+        }
+        let data = match ty {
+            ComponentType::Pin => {
+                let mut node = None;
+                if let Some(tile) = self.tile(position).cloned() {
+                    let directions = [
+                        Direction::North,
+                        Direction::East,
+                        Direction::South,
+                        Direction::West,
+                    ];
+                    node = directions
+                        .iter()
+                        .flat_map(|&dir| tile.wires.get(dir))
+                        .map(|wire_handle| GraphNode::Wire(wire_handle))
+                        .fold(None, |acc, next| match acc {
+                            Some(current) => {
+                                self.merge_clusters(current, next);
+                                Some(current)
+                            }
+                            None => Some(next),
+                        });
+                }
+                let cluster_index = match node {
+                    Some(node) => self.cluster_id(&node),
+                    None => self.simulation.alloc_cluster(),
+                };
+
+                let state = PinState { cluster_index };
+                ComponentData::Pin(state)
+            }
+            ComponentType::Flip => {
+                let mut input_node = None;
+                let mut output_node = None;
+                if let Some(tile) = self.tile(position).cloned() {
+                    let input_directions = [
+                        orientation.right(),
+                        orientation.opposite(),
+                        orientation.left(),
+                    ];
+                    input_node = input_directions
+                        .iter()
+                        .flat_map(|&dir| tile.wires.get(dir))
+                        .map(|wire_handle| GraphNode::Wire(wire_handle))
+                        .fold(None, |acc, next| match acc {
+                            Some(current) => {
+                                self.merge_clusters(current, next);
+                                Some(current)
+                            }
+                            None => Some(next),
+                        });
+
+                    output_node = tile
+                        .wires
+                        .get(orientation)
+                        .map(|wire_handle| GraphNode::Wire(wire_handle));
+                }
+                let input_cluster_index = match input_node {
+                    Some(node) => self.cluster_id(&node),
+                    None => self.simulation.alloc_cluster(),
+                };
+                let output_cluster_index = match output_node {
+                    Some(node) => self.cluster_id(&node),
+                    None => self.simulation.alloc_cluster(),
+                };
+
+                self.simulation
+                    .add_flip(input_cluster_index, output_cluster_index);
+
+                let state = FlipState {
+                    input_cluster_index,
+                    output_cluster_index,
+                };
+                ComponentData::Flip(state)
+            }
+            ComponentType::Flop => {
+                let mut input_node = None;
+                let mut output_node = None;
+                if let Some(tile) = self.tile(position).cloned() {
+                    input_node = tile
+                        .wires
+                        .get(orientation.opposite())
+                        .map(|wire_handle| GraphNode::Wire(wire_handle));
+
+                    output_node = tile
+                        .wires
+                        .get(orientation)
+                        .map(|wire_handle| GraphNode::Wire(wire_handle));
+                }
+                let input_cluster_index = match input_node {
+                    Some(node) => self.cluster_id(&node),
+                    None => self.simulation.alloc_cluster(),
+                };
+                let output_cluster_index = match output_node {
+                    Some(node) => self.cluster_id(&node),
+                    None => self.simulation.alloc_cluster(),
+                };
+
+                self.simulation
+                    .add_flop(input_cluster_index, output_cluster_index);
+
+                let state = FlopState {
+                    input_cluster_index,
+                    output_cluster_index,
+                };
+                ComponentData::Flop(state)
+            }
+            ComponentType::Switch => {
+                let mut output_node = None;
+                if let Some(tile) = self.tile(position).cloned() {
+                    output_node = tile.wires.get(orientation).map(GraphNode::Wire);
+                }
+                let input_cluster_index = self.simulation.alloc_cluster();
+                let output_cluster_index = match output_node {
+                    Some(node) => self.cluster_id(&node),
+                    None => self.simulation.alloc_cluster(),
+                };
+                self.simulation
+                    .add_flop(input_cluster_index, output_cluster_index);
+                let state = SwitchState {
+                    input_cluster_index,
+                    output_cluster_index,
+                    switched: false,
+                };
+                ComponentData::Switch(state)
+            }
+            ComponentType::Lamp => {
+                let mut node = None;
+                if let Some(tile) = self.tile(position).cloned() {
+                    let directions = [
+                        Direction::North,
+                        Direction::East,
+                        Direction::South,
+                        Direction::West,
+                    ];
+                    node = directions
+                        .iter()
+                        .flat_map(|&dir| tile.wires.get(dir))
+                        .map(|wire_handle| GraphNode::Wire(wire_handle))
+                        .fold(None, |acc, next| match acc {
+                            Some(current) => {
+                                self.merge_clusters(current, next);
+                                Some(current)
+                            }
+                            None => Some(next),
+                        });
+                }
+                let cluster_index = match node {
+                    Some(node) => self.cluster_id(&node),
+                    None => self.simulation.alloc_cluster(),
+                };
+
+                let state = LampState { cluster_index };
+                ComponentData::Lamp(state)
+            }
+            ComponentType::Chip(id) => {
+                let definition = self.chips[&id].clone();
+
+                // Ports that already have wires attached share their clusters.
+                let mut nodes: Vec<Option<GraphNode>> = Vec::new();
+                for port in 0..definition.num_ports() {
+                    let (tile_pos, face) = definition.port_location(port, position, orientation);
+                    let wire_handle =
+                        match self.tile(tile_pos).and_then(|tile| tile.wires.get(face)) {
+                            Some(x) => x,
+                            None => continue,
+                        };
+                    let next = GraphNode::Wire(wire_handle);
+                    let cluster = definition.port_cluster_index(port);
+                    if nodes.len() <= cluster {
+                        nodes.resize(cluster + 1, None);
+                    }
+                    match nodes[cluster] {
+                        Some(current) => self.merge_clusters(current, next),
+                        None => nodes[cluster] = Some(next),
+                    }
+                }
+                let existing: Vec<Option<u32>> = nodes
+                    .iter()
+                    .map(|node| node.map(|node| self.cluster_id(&node)))
+                    .collect();
+
+                let state = ChipState::new(id, definition, &existing, &mut self.simulation);
+                ComponentData::Chip(state)
+            }
+        };
+        let component = Component {
+            data,
+            position,
+            orientation,
+        };
+        self.history
+            .record(Edit::InsertComponent(component.placed()));
+        let body = component.tiles();
+        let id = self.components.insert(component);
+        for position in body {
+            let tile = self.tiles.entry(position).or_default();
+            tile.component = Some(id);
+            self.changes.tiles.insert(position);
+        }
+        self.changes.components.insert(id);
+        true
+    }
+
+    fn insert_wire(&mut self, start: IVec2, end: IVec2) -> bool {
+        // Lexicographically order the start/end points to ensure "backwards" duplicates
+        // get caught.
+        if <[i32; 2]>::from(start) > <[i32; 2]>::from(end) {
+            return self.insert_wire(end, start);
+        }
+
+        // Either a wire's start and end X coordinates need to be the same,
+        // or their Y coordinates need to be the same, but not both.
+        // (If both, then the wire would be zero-length, which is not useful.)
+        assert!(
+            (start.x == end.x) ^ (start.y == end.y),
+            "Illegal wire start and end positions"
+        );
+
+        if self.find_wire(start, end).is_some() {
+            return false;
+        }
+
+        let direction = wire_direction(start, end);
+        let start_connection = self
+            .component(start)
+            .map(|component| component.connection_type(direction))
+            .unwrap_or(Default::default());
+        let end_connection = self
+            .component(end)
+            .map(|component| component.connection_type(direction.opposite()))
+            .unwrap_or(Default::default());
+
+        let mut node = None;
+        if let Some(next) = self.node_at(start, direction) {
+            match node {
+                Some(current) => {
+                    self.merge_clusters(current, next);
+                }
+                None => {
+                    node = Some(next);
+                }
+            }
+        }
+        if let Some(next) = self.node_at(end, direction.opposite()) {
+            match node {
+                Some(current) => {
+                    self.merge_clusters(current, next);
+                }
+                None => {
+                    node = Some(next);
+                }
+            }
+        }
+        let cluster_index = match node {
+            Some(node) => self.cluster_id(&node),
+            None => self.simulation.alloc_cluster(),
+        };
+
+        let id = self.wires.insert(Wire {
+            start,
+            end,
+            start_connection,
+            end_connection,
+            cluster_index,
+        });
+        self.changes.wires.insert(id);
+        self.history
+            .record(Edit::InsertWire(PlacedWire { start, end }));
+        let wire = self.wires.get(&id);
+        for pos in wire.tiles() {
+            let tile = self.tiles.entry(pos).or_default();
+            if pos != wire.start {
+                *tile.wires.get_mut(wire.direction().opposite()) = Some(id);
+            }
+            if pos != wire.end {
+                *tile.wires.get_mut(wire.direction()) = Some(id);
+            }
+            self.changes.tiles.insert(pos);
+        }
+        true
+    }
+
+    fn remove_component(&mut self, component_id: depot::Handle) -> Component {
+        let component = self.components.get(&component_id);
+        self.history
+            .record(Edit::RemoveComponent(component.placed()));
+
+        // Move/copy out to prevent lifetime errors
+        let orientation = component.orientation;
+        match &component.data {
+            ComponentData::Pin(state) => {
+                if !self.has_neighbors(&GraphNode::Component(component_id, Direction::North)) {
+                    self.simulation.free_cluster(state.cluster_index);
+                }
+            }
+            ComponentData::Flip(state) => {
+                // Move/copy out to prevent lifetime errors
+                let &FlipState {
+                    input_cluster_index,
+                    output_cluster_index,
+                    ..
+                } = state;
+
+                self.simulation
+                    .remove_flip(input_cluster_index, output_cluster_index);
+
+                if !self.has_neighbors(&GraphNode::Component(component_id, orientation.opposite()))
+                {
+                    self.simulation.free_cluster(input_cluster_index);
+                }
+                if !self.has_neighbors(&GraphNode::Component(component_id, orientation)) {
+                    self.simulation.free_cluster(output_cluster_index);
+                }
+            }
+            ComponentData::Flop(state) => {
+                // Move/copy out to prevent lifetime errors
+                let &FlopState {
+                    input_cluster_index,
+                    output_cluster_index,
+                    ..
+                } = state;
+
+                self.simulation
+                    .remove_flop(input_cluster_index, output_cluster_index);
+
+                if !self.has_neighbors(&GraphNode::Component(component_id, orientation.opposite()))
+                {
+                    self.simulation.free_cluster(input_cluster_index);
+                }
+                if !self.has_neighbors(&GraphNode::Component(component_id, orientation)) {
+                    self.simulation.free_cluster(output_cluster_index);
+                }
+            }
+            ComponentData::Switch(state) => {
+                let &SwitchState {
+                    input_cluster_index,
+                    output_cluster_index,
+                    switched,
+                    ..
+                } = state;
+
+                self.simulation
+                    .remove_flop(input_cluster_index, output_cluster_index);
+
+                if switched {
+                    self.simulation.unpower(input_cluster_index);
+                }
+
+                self.simulation.free_cluster(input_cluster_index);
+
+                if !self.has_neighbors(&GraphNode::Component(component_id, orientation)) {
+                    self.simulation.free_cluster(output_cluster_index);
+                }
+            }
+            ComponentData::Lamp(state) => {
+                if !self.has_neighbors(&GraphNode::Component(component_id, Direction::North)) {
+                    self.simulation.free_cluster(state.cluster_index);
+                }
+            }
+            ComponentData::Chip(state) => {
+                state.remove(&mut self.simulation);
+                for cluster in 0..state.num_clusters() {
+                    let ports: Vec<usize> = (0..state.definition.num_ports())
+                        .filter(|&port| state.port_cluster_index(port) == cluster)
+                        .collect();
+                    if !ports.is_empty() && self.port_wires(component, &ports).is_empty() {
+                        self.simulation.free_cluster(state.cluster(cluster));
+                    }
+                }
+            }
+        }
+
+        // Wires that were only connected through the chip, grouped by netlist cluster.
+        let mut port_wires = Vec::new();
+        if let ComponentData::Chip(state) = &component.data {
+            for cluster in 0..state.num_clusters() {
+                let ports: Vec<usize> = (0..state.definition.num_ports())
+                    .filter(|&port| state.port_cluster_index(port) == cluster)
+                    .collect();
+                port_wires.push(self.port_wires(component, &ports));
+            }
+        }
+
+        let component = self.components.remove(&component_id);
+        for position in component.tiles() {
+            let tile = self.tiles.get_mut(&position).unwrap();
+            tile.component = None;
+            self.changes.tiles.insert(position);
+        }
+        self.changes.components.insert(component_id);
+
+        match &component.data {
+            ComponentData::Pin(..) | ComponentData::Lamp(..) => {
+                let directions = [
+                    Direction::North,
+                    Direction::East,
+                    Direction::South,
+                    Direction::West,
+                ];
+                self.split_all(component.position, &directions);
+            }
+            ComponentData::Flip(..) => {
+                let input_directions = [
+                    orientation.right(),
+                    orientation.opposite(),
+                    orientation.left(),
+                ];
+                self.split_all(component.position, &input_directions);
+            }
+            ComponentData::Flop(..) => {}
+            ComponentData::Switch(..) => {}
+            ComponentData::Chip(..) => {
+                for nodes in port_wires {
+                    for i in 1..nodes.len() {
+                        for j in 0..i {
+                            self.split_clusters(nodes[i], nodes[j]);
+                        }
+                    }
+                }
+            }
+        }
+        component
+    }
+
+    /// The wires attached to the given ports of a chip.
+    fn port_wires(&self, component: &Component, ports: &[usize]) -> Vec<GraphNode> {
+        let definition = match &component.data {
+            ComponentData::Chip(state) => &state.definition,
+            _ => unreachable!(),
+        };
+        ports
+            .iter()
+            .flat_map(|&port| {
+                let (position, face) =
+                    definition.port_location(port, component.position, component.orientation);
+                self.tile(position).and_then(|tile| tile.wires.get(face))
+            })
+            .map(GraphNode::Wire)
+            .collect()
+    }
+
+    fn remove_wire(&mut self, wire_id: depot::Handle) -> Wire {
+        let wire = self.wires.get(&wire_id);
+
+        if !self.has_neighbors(&GraphNode::Wire(wire_id)) {
+            self.simulation.free_cluster(wire.cluster_index);
+        }
+
+        let wire = self.wires.remove(&wire_id);
+        self.changes.wires.insert(wire_id);
+        self.history.record(Edit::RemoveWire(PlacedWire {
+            start: wire.start,
+            end: wire.end,
+        }));
+        for tile_pos in wire.tiles() {
+            let tile = self.tiles.get_mut(&tile_pos).unwrap();
+            if tile_pos != wire.start {
+                assert_eq!(tile.wires.get(wire.direction().opposite()), Some(wire_id));
+                *tile.wires.get_mut(wire.direction().opposite()) = None;
+            }
+            if tile_pos != wire.end {
+                assert_eq!(tile.wires.get(wire.direction()), Some(wire_id));
+                *tile.wires.get_mut(wire.direction()) = None;
+            }
+            self.changes.tiles.insert(tile_pos);
+        }
+
+        let start_node = self.node_at(wire.start, wire.direction());
+        let end_node = self.node_at(wire.end, wire.direction().opposite());
+        if let (Some(start), Some(end)) = (start_node, end_node) {
+            self.split_clusters(start, end);
+        }
+
+        wire
+    }
+
+    fn toggle_switch(&mut self, position: IVec2) {
+        let handle = self
+            .tile(position)
+            .and_then(|tile| tile.component)
+            .expect("no component to toggle");
+        let component = self.components.get_mut(&handle);
+        if let ComponentData::Switch(state) = &mut component.data {
+            state.switched = !state.switched;
+            if state.switched {
+                self.simulation.power(state.input_cluster_index);
+            } else {
+                self.simulation.unpower(state.input_cluster_index);
+            }
+            self.changes.components.insert(handle);
+            self.history.record(Edit::ToggleSwitch(position));
+        }
+    }
+
+    /// Replays a single primitive edit, as recorded in the undo history.
+    fn apply(&mut self, edit: Edit) {
+        match edit {
+            Edit::InsertComponent(component) => {
+                self.insert_component(component.ty, component.position, component.orientation);
+                if component.switched {
+                    self.toggle_switch(component.position);
+                }
+            }
+            Edit::RemoveComponent(component) => {
+                let handle = self
+                    .tile(component.position)
+                    .and_then(|tile| tile.component)
+                    .expect("no component to remove");
+                self.remove_component(handle);
+            }
+            Edit::InsertWire(wire) => {
+                self.insert_wire(wire.start, wire.end);
+            }
+            Edit::RemoveWire(wire) => {
+                let handle = self
+                    .find_wire(wire.start, wire.end)
+                    .expect("no wire to remove");
+                self.remove_wire(handle);
+            }
+            Edit::ToggleSwitch(position) => {
+                self.toggle_switch(position);
+            }
+            Edit::InsertLabel(label) => {
+                self.insert_label(label);
+            }
+            Edit::RemoveLabel(label) => {
+                self.remove_label(&label);
+            }
+        }
+    }
+
+    fn insert_label(&mut self, label: PlacedLabel) {
+        self.history.record(Edit::InsertLabel(label.clone()));
+        self.labels.push(label);
+    }
+
+    fn remove_label(&mut self, label: &PlacedLabel) {
+        let index = self
+            .labels
+            .iter()
+            .position(|other| other == label)
+            .expect("no label to remove");
+        self.history
+            .record(Edit::RemoveLabel(self.labels.remove(index)));
+    }
+
+    /// The part of `wire` that lies inside the rectangle from `min` to `max`, if any.
+    ///
+    /// If the wire crosses the boundary, the cut end is moved inwards past any crossovers, since
+    /// a pin there would connect the crossing wires.
+    fn clip_wire(&self, wire: &Wire, min: IVec2, max: IVec2) -> Option<(IVec2, IVec2)> {
+        if !wire.tiles().any(|position| in_region(position, min, max)) {
+            return None;
+        }
+        let step = (wire.end - wire.start).clamp(IVec2::splat(-1), IVec2::splat(1));
+        let mut start = wire.start.clamp(min, max);
+        let mut end = wire.end.clamp(min, max);
+        if start != wire.start {
+            start = self.cut_point(start, step, end);
+        }
+        if end != wire.end {
+            end = self.cut_point(end, -step, start);
+        }
+        if start == end {
+            return None;
+        }
+        Some((start, end))
+    }
+
+    /// Walks from `position` towards `limit` in steps of `step`, until reaching a tile where a
+    /// wire can be cut without landing on a crossover.
+    fn cut_point(&self, mut position: IVec2, step: IVec2, limit: IVec2) -> IVec2 {
+        while position != limit {
+            match self.tile(position) {
+                Some(tile) if tile.component.is_none() && tile.wires.count() >= 2 => {
+                    position += step;
+                }
+                _ => break,
+            }
+        }
+        position
+    }
+
+    /// The graph node for the face of the component at `position`, if it can be connected.
+    fn node_at(&self, position: IVec2, direction: Direction) -> Option<GraphNode> {
+        let handle = self.tile(position)?.component?;
+        let component = self.components.get(&handle);
+        match &component.data {
+            ComponentData::Chip(..) => component
+                .port_at(position, direction)
+                .map(|port| GraphNode::Port(handle, port)),
+            _ => Some(GraphNode::Component(handle, direction)),
+        }
+    }
+
+    fn find_wire(&self, start: IVec2, end: IVec2) -> Option<depot::Handle> {
+        let tile = self.tiles.get(&start)?;
+        tile.wires.as_array().iter().flatten().copied().find(|id| {
+            let wire = &self.wires[id];
+            wire.start == start && wire.end == end
+        })
+    }
+
+    fn component(&self, position: IVec2) -> Option<&Component> {
+        self.tile(position)
+            .and_then(|tile| tile.component)
+            .map(|id| self.components.get(&id))
+    }
+
+    fn merge_clusters(&mut self, into: GraphNode, from: GraphNode) {
+        let into_index = self.cluster_id(&into);
+        let from_index = self.cluster_id(&from);
+        if into_index == from_index {
+            return;
+        }
+        let from_cluster = self.cluster_of(&from);
+        for node in &from_cluster {
+            match node {
+                &GraphNode::Wire(handle) => {
+                    let wire = self.wires.get_mut(&handle);
+                    wire.cluster_index = into_index;
+                    self.changes.wires.insert(handle);
+                }
+                &GraphNode::Component(handle, direction) => {
+                    let component = self.components.get_mut(&handle);
+                    match &mut component.data {
+                        ComponentData::Pin(state) => {
+                            state.cluster_index = into_index;
+                        }
+                        ComponentData::Flip(state) => {
+                            if direction == component.orientation {
+                                // Output cluster changed:
+                                self.simulation.remove_flip(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flip(state.input_cluster_index, into_index);
+                                state.output_cluster_index = into_index;
+                            } else {
+                                // Input cluster changed
+                                self.simulation.remove_flip(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flip(into_index, state.output_cluster_index);
+                                state.input_cluster_index = into_index;
+                            }
+                        }
+                        ComponentData::Flop(state) => {
+                            if direction == component.orientation {
+                                // Output cluster changed:
+                                self.simulation.remove_flop(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flop(state.input_cluster_index, into_index);
+                                state.output_cluster_index = into_index;
+                            } else if direction == component.orientation.opposite() {
+                                // Input cluster changed:
+                                self.simulation.remove_flop(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flop(into_index, state.output_cluster_index);
+                                state.input_cluster_index = into_index;
+                            } else {
+                                unreachable!()
+                            }
+                        }
+                        ComponentData::Switch(state) => {
+                            if direction == component.orientation {
+                                // Output cluster changed:
+                                self.simulation.remove_flop(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flop(state.input_cluster_index, into_index);
+                                state.output_cluster_index = into_index;
+                            } else {
+                                unreachable!()
+                            }
+                        }
+                        ComponentData::Lamp(state) => {
+                            state.cluster_index = into_index;
+                        }
+                        ComponentData::Chip(..) => unreachable!(),
+                    }
+                    self.changes.components.insert(handle);
+                }
+                &GraphNode::Port(handle, port) => {
+                    let component = self.components.get_mut(&handle);
+                    if let ComponentData::Chip(state) = &mut component.data {
+                        let cluster = state.port_cluster_index(port);
+                        state.relabel(cluster, into_index, &mut self.simulation);
+                    }
+                    self.changes.components.insert(handle);
+                }
+            }
+        }
+
+        self.simulation.merge_power(into_index, from_index);
+        // An override on either side holds the whole merged cluster.
+        if let (None, Some(value)) = (
+            self.simulation.forced(into_index),
+            self.simulation.forced(from_index),
+        ) {
+            self.simulation.force(into_index, Some(value));
+        }
+        self.simulation.free_cluster(from_index);
+    }
+
+    fn split_clusters(&mut self, keep: GraphNode, split: GraphNode) {
+        let keep_index = self.cluster_id(&keep);
+        if keep_index != self.cluster_id(&split) {
+            return;
+        }
+        let split_cluster = self.cluster_of(&split);
+        if split_cluster.contains(&keep) {
+            return;
+        }
+        let split_index = self.simulation.alloc_cluster();
+        self.simulation.copy_power(keep_index, split_index);
+        if let Some(value) = self.simulation.forced(keep_index) {
+            self.simulation.force(split_index, Some(value));
+        }
+
+        for node in &split_cluster {
+            match node {
+                &GraphNode::Wire(handle) => {
+                    let wire = self.wires.get_mut(&handle);
+                    wire.cluster_index = split_index;
+                    self.changes.wires.insert(handle);
+                }
+                &GraphNode::Component(handle, direction) => {
+                    let component = self.components.get_mut(&handle);
+                    match &mut component.data {
+                        ComponentData::Pin(state) => {
+                            state.cluster_index = split_index;
+                        }
+                        ComponentData::Flip(state) => {
+                            if direction == component.orientation {
+                                // Output cluster changed:
+                                self.simulation.remove_flip(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flip(state.input_cluster_index, split_index);
+                                state.output_cluster_index = split_index;
+                            } else {
+                                // Input cluster changed
+                                self.simulation.remove_flip(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flip(split_index, state.output_cluster_index);
+                                state.input_cluster_index = split_index;
+                            }
+                        }
+                        ComponentData::Flop(state) => {
+                            if direction == component.orientation {
+                                // Output cluster changed:
+                                self.simulation.remove_flop(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flop(state.input_cluster_index, split_index);
+                                state.output_cluster_index = split_index;
+                            } else if direction == component.orientation.opposite() {
+                                // Input cluster changed:
+                                self.simulation.remove_flop(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flop(split_index, state.output_cluster_index);
+                                state.input_cluster_index = split_index;
+                            } else {
+                                unreachable!()
+                            }
+                        }
+                        ComponentData::Switch(state) => {
+                            if direction == component.orientation {
+                                // Output cluster changed:
+                                self.simulation.remove_flop(
+                                    state.input_cluster_index,
+                                    state.output_cluster_index,
+                                );
+                                self.simulation
+                                    .add_flop(state.input_cluster_index, split_index);
+                                state.output_cluster_index = split_index;
+                            } else {
+                                unreachable!()
+                            }
+                        }
+                        ComponentData::Lamp(state) => {
+                            state.cluster_index = split_index;
+                        }
+                        ComponentData::Chip(..) => unreachable!(),
+                    }
+                    self.changes.components.insert(handle);
+                }
+                &GraphNode::Port(handle, port) => {
+                    let component = self.components.get_mut(&handle);
+                    if let ComponentData::Chip(state) = &mut component.data {
+                        let cluster = state.port_cluster_index(port);
+                        state.relabel(cluster, split_index, &mut self.simulation);
+                    }
+                    self.changes.components.insert(handle);
+                }
+            }
+        }
+    }
+
+    fn split_all(&mut self, position: IVec2, directions: &[Direction]) {
+        //TODO optimize
+        let tile = self.tile(position).unwrap().clone();
+        let nodes: Vec<GraphNode> = directions
+            .iter()
+            .flat_map(|&dir| tile.wires.get(dir))
+            .map(|wire_handle| GraphNode::Wire(wire_handle))
+            .collect();
+
+        for i in 1..nodes.len() {
+            for j in 0..i {
+                self.split_clusters(nodes[i], nodes[j]);
+            }
+        }
+    }
+
+    fn cluster_of(&self, node: &GraphNode) -> HashSet<GraphNode> {
+        let id = self.cluster_id(node);
+
+        let mut visited = HashSet::new();
+        let mut queue = Vec::new();
+        visited.insert(*node);
+        queue.push(*node);
+        while let Some(next) = queue.pop() {
+            debug_assert_eq!(self.cluster_id(&next), id);
+
+            self.neighbors(&next, |neighbor| {
+                // Only enqueue if this is the first time it has been seen.
+                if visited.insert(neighbor) {
+                    queue.push(neighbor);
+                }
+            });
+        }
+        visited
+    }
+
+    fn cluster_id(&self, node: &GraphNode) -> u32 {
+        match node {
+            &GraphNode::Wire(handle) => self.wires.get(&handle).cluster_index,
+            &GraphNode::Component(handle, direction) => {
+                let component = self.components.get(&handle);
+                match &component.data {
+                    ComponentData::Pin(state) => state.cluster_index,
+                    ComponentData::Flip(state) => {
+                        if direction == component.orientation {
+                            state.output_cluster_index
+                        } else {
+                            state.input_cluster_index
+                        }
+                    }
+                    ComponentData::Flop(state) => {
+                        if direction == component.orientation {
+                            state.output_cluster_index
+                        } else if direction == component.orientation.opposite() {
+                            state.input_cluster_index
+                        } else {
+                            unreachable!()
+                        }
+                    }
+                    ComponentData::Switch(state) => {
+                        if direction == component.orientation {
+                            state.output_cluster_index
+                        } else {
+                            unreachable!()
+                        }
+                    }
+                    ComponentData::Lamp(state) => state.cluster_index,
+                    ComponentData::Chip(..) => unreachable!("chips are connected through ports"),
+                }
+            }
+            &GraphNode::Port(handle, port) => match &self.components.get(&handle).data {
+                ComponentData::Chip(state) => state.port_cluster(port),
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    fn neighbors<V>(&self, node: &GraphNode, mut visitor: V)
+    where
+        V: FnMut(GraphNode),
+    {
+        match node {
+            &GraphNode::Wire(handle) => {
+                let wire = self.wires.get(&handle);
+                if let Some(node) = self.node_at(wire.start, wire.direction()) {
+                    visitor(node);
+                }
+                if let Some(node) = self.node_at(wire.end, wire.direction().opposite()) {
+                    visitor(node);
+                }
+            }
+            &GraphNode::Port(handle, port) => {
+                let component = self.components.get(&handle);
+                let state = match &component.data {
+                    ComponentData::Chip(state) => state,
+                    _ => unreachable!(),
+                };
+                let (position, face) =
+                    state
+                        .definition
+                        .port_location(port, component.position, component.orientation);
+                if let Some(wire_handle) = self.tile(position).unwrap().wires.get(face) {
+                    visitor(GraphNode::Wire(wire_handle));
+                }
+                // Ports on the same cluster inside the chip are connected.
+                for other in state.connected_ports(port) {
+                    if other != port {
+                        visitor(GraphNode::Port(handle, other));
+                    }
+                }
+            }
+            &GraphNode::Component(handle, direction) => {
+                let component = self.components.get(&handle);
+                let tile = self.tile(component.position).unwrap();
+                let component_relatives: &[Relative] = match component.get_type() {
+                    ComponentType::Pin | ComponentType::Lamp => {
+                        // All faces of a pin or lamp are connected.
+                        &[
+                            Relative::Same,
+                            Relative::Right,
+                            Relative::Opposite,
+                            Relative::Left,
+                        ]
+                    }
+                    ComponentType::Flip => {
+                        // Flip input faces are connected, output face is not.
+                        if direction == component.orientation {
+                            &[Relative::Same]
+                        } else {
+                            &[Relative::Right, Relative::Opposite, Relative::Left]
+                        }
+                    }
+                    ComponentType::Flop | ComponentType::Switch => {
+                        // Flops and switches have no faces connected to each other.
+                        if let Some(wire_handle) = tile.wires.get(direction) {
+                            visitor(GraphNode::Wire(wire_handle));
+                        }
+                        return;
+                    }
+                    ComponentType::Chip(..) => unreachable!("chips are connected through ports"),
+                };
+                for &rel in component_relatives {
+                    if let Some(wire_handle) = tile.wires.get(component.orientation.rotate(rel)) {
+                        visitor(GraphNode::Wire(wire_handle));
+                    }
+                }
+            }
+        }
+    }
+
+    fn has_neighbors(&self, node: &GraphNode) -> bool {
+        let mut acc = false;
+        self.neighbors(node, |_| acc = true);
+        acc
+    }
+}
+
+impl Default for Circuit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct TileDebugInfo<'a> {
+    circuit: &'a Circuit,
+    pos: IVec2,
+}
+
+impl<'a> fmt::Display for TileDebugInfo<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(tile) = self.circuit.tile(self.pos) {
+            if let Some(component_handle) = tile.component {
+                let component = self.circuit.components.get(&component_handle);
+                match &component.data {
+                    ComponentData::Pin(state) => {
+                        writeln!(f, "Component: Pin ({})", state.cluster_index)?;
+                    }
+                    ComponentData::Flip(state) => {
+                        writeln!(
+                            f,
+                            "Component: Flip ({} -> {})",
+                            state.input_cluster_index, state.output_cluster_index,
+                        )?;
+                    }
+                    ComponentData::Flop(state) => {
+                        writeln!(
+                            f,
+                            "Component: Flop ({} -> {})",
+                            state.input_cluster_index, state.output_cluster_index,
+                        )?;
+                    }
+                    ComponentData::Switch(state) => {
+                        writeln!(
+                            f,
+                            "Component: Switch ({} -> {})",
+                            state.input_cluster_index, state.output_cluster_index
+                        )?;
+                    }
+                    ComponentData::Lamp(state) => {
+                        writeln!(f, "Component: Lamp ({})", state.cluster_index)?;
+                    }
+                    ComponentData::Chip(state) => {
+                        writeln!(f, "Component: Chip {:?}", state.definition.name())?;
+                        for face in [component.orientation.opposite(), component.orientation] {
+                            if let Some(port) = component.port_at(self.pos, face) {
+                                writeln!(
+                                    f,
+                                    "Port {} ({})",
+                                    state.definition.port_name(port),
+                                    state.port_cluster(port),
+                                )?;
+                            }
+                        }
+                    }
+                }
+            }
+            let directions = [
+                Direction::East,
+                Direction::West,
+                Direction::North,
+                Direction::South,
+            ];
+            for direction in directions {
+                if let Some(wire_handle) = tile.wires.get(direction) {
+                    let wire = self.circuit.wires.get(&wire_handle);
+                    writeln!(f, "{:?} ({})", direction, wire.cluster_index)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The inputs and outputs of a circuit. See [`Circuit::ports`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ports {
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Port {
+    pub name: String,
+    /// The tile of the switch or lamp.
+    pub position: IVec2,
+}
+
+/// How a switch or lamp is picked out by name: by its port name, or by its coordinates, like
+/// `3,-2`. See [`Circuit::find_port`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PortRef {
+    Name(String),
+    Position(IVec2),
+}
+
+impl PortRef {
+    pub fn parse(word: &str) -> Self {
+        let position = word
+            .split_once(',')
+            .and_then(|(x, y)| Some(IVec2::new(x.parse().ok()?, y.parse().ok()?)));
+        match position {
+            Some(position) => Self::Position(position),
+            None => Self::Name(word.to_owned()),
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct Tile {
+    pub component: Option<depot::Handle>,
+    pub wires: TileWires,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TileWires {
+    pub east: Option<depot::Handle>,
+    pub north: Option<depot::Handle>,
+    pub west: Option<depot::Handle>,
+    pub south: Option<depot::Handle>,
+}
+
+impl TileWires {
+    pub fn count(&self) -> usize {
+        let mut sum = 0;
+        if self.east.is_some() {
+            sum += 1;
+        }
+        if self.north.is_some() {
+            sum += 1;
+        }
+        if self.west.is_some() && self.west != self.east {
+            sum += 1;
+        }
+        if self.south.is_some() && self.south != self.north {
+            sum += 1;
+        }
+        sum
+    }
+
+    pub fn get(&self, direction: Direction) -> Option<depot::Handle> {
+        match direction {
+            Direction::East => self.east,
+            Direction::North => self.north,
+            Direction::West => self.west,
+            Direction::South => self.south,
+        }
+    }
+
+    pub fn get_mut(&mut self, direction: Direction) -> &mut Option<depot::Handle> {
+        match direction {
+            Direction::East => &mut self.east,
+            Direction::North => &mut self.north,
+            Direction::West => &mut self.west,
+            Direction::South => &mut self.south,
+        }
+    }
+
+    pub fn as_array(&self) -> [Option<depot::Handle>; 4] {
+        [self.east, self.north, self.west, self.south]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ComponentType {
+    Pin,
+    Flip,
+    Flop,
+    Switch,
+    Lamp,
+    /// An instance of the chip with the given ID.
+    Chip(u32),
+}
+
+impl ComponentType {
+    pub fn chip_id(self) -> Option<u32> {
+        match self {
+            Self::Chip(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+struct Component {
+    data: ComponentData,
+    position: IVec2,
+    orientation: Direction,
+}
+
+impl Component {
+    fn get_type(&self) -> ComponentType {
+        match &self.data {
+            ComponentData::Pin(..) => ComponentType::Pin,
+            ComponentData::Flip(..) => ComponentType::Flip,
+            ComponentData::Flop(..) => ComponentType::Flop,
+            ComponentData::Switch(..) => ComponentType::Switch,
+            ComponentData::Lamp(..) => ComponentType::Lamp,
+            ComponentData::Chip(state) => ComponentType::Chip(state.id),
+        }
+    }
+
+    /// The tiles covered by the component.
+    fn tiles(&self) -> Vec<IVec2> {
+        match &self.data {
+            ComponentData::Chip(state) => state
+                .definition
+                .tiles(self.position, self.orientation)
+                .collect(),
+            _ => vec![self.position],
+        }
+    }
+
+    fn overlaps(&self, min: IVec2, max: IVec2) -> bool {
+        self.tiles()
+            .into_iter()
+            .any(|position| in_region(position, min, max))
+    }
+
+    /// The chip port on the `face` of `tile`, if there is one.
+    fn port_at(&self, tile: IVec2, face: Direction) -> Option<usize> {
+        match &self.data {
+            ComponentData::Chip(state) => {
+                state
+                    .definition
+                    .port_at(self.position, self.orientation, tile, face)
+            }
+            _ => None,
+        }
+    }
+
+    fn placed(&self) -> PlacedComponent {
+        PlacedComponent {
+            ty: self.get_type(),
+            position: self.position,
+            orientation: self.orientation,
+            switched: match &self.data {
+                ComponentData::Switch(state) => state.switched,
+                _ => false,
+            },
+        }
+    }
+
+    fn connection_type(&self, direction: Direction) -> WireConnection {
+        match self.get_type() {
+            ComponentType::Pin => WireConnection::Pin,
+            ComponentType::Flip => {
+                if direction == self.orientation {
+                    WireConnection::Output
+                } else {
+                    WireConnection::Pin
+                }
+            }
+            ComponentType::Flop => {
+                if direction == self.orientation {
+                    WireConnection::Output
+                } else {
+                    WireConnection::SidePin
+                }
+            }
+            ComponentType::Switch => WireConnection::Output,
+            ComponentType::Lamp => WireConnection::Pin,
+            ComponentType::Chip(..) => {
+                if direction == self.orientation {
+                    WireConnection::Output
+                } else {
+                    WireConnection::SidePin
+                }
+            }
+        }
+    }
+}
+
+enum ComponentData {
+    Pin(PinState),
+    Flip(FlipState),
+    Flop(FlopState),
+    Switch(SwitchState),
+    Lamp(LampState),
+    Chip(ChipState),
+}
+
+struct PinState {
+    cluster_index: u32,
+}
+
+struct FlipState {
+    input_cluster_index: u32,
+    output_cluster_index: u32,
+}
+
+struct FlopState {
+    input_cluster_index: u32,
+    output_cluster_index: u32,
+}
+
+struct SwitchState {
+    input_cluster_index: u32,
+    output_cluster_index: u32,
+    switched: bool,
+}
+
+struct LampState {
+    cluster_index: u32,
+}
+
+struct Wire {
+    start: IVec2,
+    end: IVec2,
+    start_connection: WireConnection,
+    end_connection: WireConnection,
+    cluster_index: u32,
+}
+
+impl Wire {
+    fn tiles(&self) -> impl Iterator<Item = IVec2> {
+        wire_tiles(self.start, self.end)
+    }
+
+    fn direction(&self) -> Direction {
+        wire_direction(self.start, self.end)
+    }
+}
+
+/// Everything that was edited since the last time a [`CircuitRenderer`] picked up the changes.
+///
+/// Handles in these sets may refer to items that have since been removed.
+#[derive(Default)]
+struct Changes {
+    components: HashSet<depot::Handle>,
+    wires: HashSet<depot::Handle>,
+    tiles: HashSet<IVec2>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum GraphNode {
+    Wire(depot::Handle),
+    Component(depot::Handle, Direction),
+    /// A port of a chip, by index.
+    Port(depot::Handle, usize),
+}
+
+pub fn wire_direction(start: IVec2, end: IVec2) -> Direction {
+    if start.x == end.x {
+        if start.y < end.y {
+            Direction::North
+        } else {
+            Direction::South
+        }
+    } else {
+        if start.x < end.x {
+            Direction::East
+        } else {
+            Direction::West
+        }
+    }
+}
+
+fn in_region(position: IVec2, min: IVec2, max: IVec2) -> bool {
+    position.cmpge(min).all() && position.cmple(max).all()
+}
+
+fn label_in_region(anchor: Anchor, min: IVec2, max: IVec2) -> bool {
+    match anchor {
+        Anchor::Tile(position) => in_region(position, min, max),
+        Anchor::Free(point) => in_region(point.floor().as_ivec2(), min, max),
+    }
+}
+
+fn wire_tiles(start: IVec2, end: IVec2) -> impl Iterator<Item = IVec2> {
+    let delta = end - start;
+    // Either X or Y is zero, so the "normalized" vector is clamping the
+    // non-zero element, and length is the nonzero element + 0.
+    let ray = delta.clamp(IVec2::splat(-1), IVec2::splat(1));
+    let len = delta.x.abs() + delta.y.abs();
+
+    (0..=len).map(move |i| start + ray * i)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{Circuit, ComponentType, GraphNode, Port};
+    use crate::direction::{Direction, Relative, Transform};
+    use crate::schematic::{Anchor, PlacedWire};
+    use glam::{IVec2, Vec2};
+    use std::io::{self, BufWriter, Write};
+
+    fn cluster_at(circuit: &Circuit, x: i32, y: i32, direction: Direction) -> u32 {
+        let handle = circuit
+            .tile(IVec2::new(x, y))
+            .and_then(|tile| tile.component)
+            .expect("no component at position");
+        circuit.cluster_id(&GraphNode::Component(handle, direction))
+    }
+
+    fn pin_cluster(circuit: &Circuit, x: i32, y: i32) -> u32 {
+        cluster_at(circuit, x, y, Direction::North)
+    }
+
+    #[test]
+    fn wire_merges_pins() {
+        let mut circuit = Circuit::new();
+        assert!(circuit.place_wire(IVec2::new(0, 0), IVec2::new(4, 0)));
+        assert_eq!(pin_cluster(&circuit, 0, 0), pin_cluster(&circuit, 4, 0));
+
+        // A pin placed on the wire splits it, but stays in the same cluster.
+        assert!(circuit.place_component(ComponentType::Pin, IVec2::new(2, 0), Direction::East));
+        assert_eq!(circuit.wires.len(), 2);
+        assert_eq!(pin_cluster(&circuit, 0, 0), pin_cluster(&circuit, 2, 0));
+        assert_eq!(pin_cluster(&circuit, 2, 0), pin_cluster(&circuit, 4, 0));
+
+        // Removing the pin and its wires splits the cluster.
+        circuit.delete_all_at(IVec2::new(2, 0));
+        assert_eq!(circuit.wires.len(), 0);
+        assert_ne!(pin_cluster(&circuit, 0, 0), pin_cluster(&circuit, 4, 0));
+    }
+
+    #[test]
+    fn delete_pin_merges_wires() {
+        let mut circuit = Circuit::new();
+        assert!(circuit.place_wire(IVec2::new(0, 0), IVec2::new(2, 0)));
+        assert!(circuit.place_wire(IVec2::new(2, 0), IVec2::new(4, 0)));
+        assert_eq!(circuit.wires.len(), 2);
+
+        // The pin becomes a plain tile that the wire crosses.
+        circuit.delete_component(IVec2::new(2, 0));
+        assert_eq!(circuit.wires.len(), 1);
+        assert_eq!(circuit.component_at(IVec2::new(2, 0)), None);
+        assert_eq!(pin_cluster(&circuit, 0, 0), pin_cluster(&circuit, 4, 0));
+    }
+
+    #[test]
+    fn crossing_wires_stay_separate() {
+        let mut circuit = Circuit::new();
+        assert!(circuit.place_wire(IVec2::new(0, 0), IVec2::new(4, 0)));
+        assert!(circuit.place_wire(IVec2::new(2, -2), IVec2::new(2, 2)));
+        assert_eq!(circuit.component_at(IVec2::new(2, 0)), None);
+        assert_ne!(pin_cluster(&circuit, 0, 0), pin_cluster(&circuit, 2, 2));
+        assert_eq!(pin_cluster(&circuit, 2, -2), pin_cluster(&circuit, 2, 2));
+    }
+
+    #[test]
+    fn flip_placement_rules() {
+        let mut circuit = Circuit::new();
+        assert!(circuit.place_component(ComponentType::Flip, IVec2::ZERO, Direction::East));
+
+        // Wires may cross the input pin, but not connect the input and output.
+        assert!(!circuit.can_place_wire(IVec2::new(-2, 0), IVec2::new(2, 0)));
+        assert!(circuit.can_place_wire(IVec2::new(0, -2), IVec2::new(0, 2)));
+        assert!(circuit.can_place_wire(IVec2::new(0, 0), IVec2::new(2, 0)));
+
+        // Flips can't be placed with a wire on their output side.
+        assert!(circuit.place_wire(IVec2::new(3, 2), IVec2::new(5, 2)));
+        assert!(!circuit.can_place_component(
+            ComponentType::Flip,
+            IVec2::new(4, 2),
+            Direction::East
+        ));
+        assert!(circuit.can_place_component(
+            ComponentType::Flip,
+            IVec2::new(4, 2),
+            Direction::North
+        ));
+    }
+
+    #[test]
+    fn flop_placement_rules() {
+        let mut circuit = Circuit::new();
+        assert!(circuit.place_component(ComponentType::Flop, IVec2::ZERO, Direction::East));
+
+        assert!(!circuit.can_place_wire(IVec2::new(-2, 0), IVec2::new(2, 0)));
+        assert!(!circuit.can_place_wire(IVec2::new(0, 0), IVec2::new(0, 2)));
+        assert!(circuit.can_place_wire(IVec2::new(0, 0), IVec2::new(2, 0)));
+        assert!(circuit.can_place_wire(IVec2::new(-2, 0), IVec2::new(0, 0)));
+
+        assert!(circuit.place_wire(IVec2::new(3, 2), IVec2::new(5, 2)));
+        assert!(!circuit.can_place_component(
+            ComponentType::Flop,
+            IVec2::new(4, 2),
+            Direction::North
+        ));
+    }
+
+    #[test]
+    fn switch_powers_lamp() {
+        let mut circuit = Circuit::new();
+        assert!(circuit.place_component(ComponentType::Switch, IVec2::ZERO, Direction::East));
+        assert!(circuit.place_component(ComponentType::Lamp, IVec2::new(3, 0), Direction::East));
+        assert!(!circuit.can_place_wire(IVec2::new(0, 0), IVec2::new(0, 3)));
+        assert!(circuit.place_wire(IVec2::new(0, 0), IVec2::new(3, 0)));
+
+        let lamp = pin_cluster(&circuit, 3, 0);
+        assert_eq!(cluster_at(&circuit, 0, 0, Direction::East), lamp);
+
+        circuit.interact(IVec2::ZERO);
+        circuit.tick();
+        circuit.tick();
+        assert!(circuit.simulation().is_powered(lamp));
+
+        circuit.interact(IVec2::ZERO);
+        circuit.tick();
+        circuit.tick();
+        assert!(!circuit.simulation().is_powered(lamp));
+    }
+
+    #[test]
+    fn forced_clusters_survive_merge_and_split() {
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Switch, IVec2::ZERO, Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(4, 0), Direction::North);
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(4, 0));
+        circuit.interact(IVec2::ZERO);
+        assert!(circuit.force(IVec2::new(4, 0), Some(false)));
+        circuit.tick();
+        circuit.tick();
+        assert!(!circuit.simulation().is_powered(pin_cluster(&circuit, 4, 0)));
+
+        // Splitting the wire leaves both halves forced.
+        circuit.place_component(ComponentType::Pin, IVec2::new(2, 0), Direction::East);
+        circuit.delete_all_at(IVec2::new(2, 0));
+        assert_eq!(circuit.forced(IVec2::new(0, 0)), Some(false));
+        assert_eq!(circuit.forced(IVec2::new(4, 0)), Some(false));
+        assert_eq!(circuit.forced_tiles().len(), 2);
+
+        // Joining a forced cluster to one that isn't forces all of it.
+        circuit.force(IVec2::new(4, 0), None);
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(4, 0));
+        assert_eq!(circuit.forced(IVec2::new(4, 0)), Some(false));
+        let forced = circuit.forced_tiles();
+        assert_eq!(forced.len(), 1);
+        assert_eq!(forced[0].2, IVec2::new(0, 0));
+
+        circuit.release_all();
+        circuit.tick();
+        circuit.tick();
+        assert!(circuit.simulation().is_powered(pin_cluster(&circuit, 4, 0)));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Switch, IVec2::ZERO, Direction::East);
+        circuit.place_component(ComponentType::Flip, IVec2::new(3, 0), Direction::North);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(3, 3), Direction::North);
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(3, 0));
+        circuit.place_wire(IVec2::new(3, 0), IVec2::new(3, 3));
+        circuit.place_wire(IVec2::new(3, -2), IVec2::new(3, 0));
+        circuit.interact(IVec2::ZERO);
+
+        let mut buffer = Vec::new();
+        circuit.save(&mut buffer).unwrap();
+        let loaded = Circuit::load(&buffer[..]).unwrap();
+        assert_eq!(loaded.schematic(), circuit.schematic());
+        assert_eq!(
+            pin_cluster(&loaded, 3, 3),
+            cluster_at(&loaded, 3, 0, Direction::North)
+        );
+    }
+
+    /// Takes everything that is written to it, but fails to flush it.
+    struct FailsOnFlush;
+
+    impl Write for FailsOnFlush {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+    }
+
+    #[test]
+    fn save_reports_flush_errors() {
+        let mut circuit = Circuit::new();
+        place_chain(&mut circuit, ComponentType::Switch, 0);
+        // Like saving to a file, where the buffer is only written out at the end.
+        let err = circuit.save(BufWriter::new(FailsOnFlush)).unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Switch, IVec2::ZERO, Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(4, 0), Direction::North);
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(4, 0));
+        circuit.interact(IVec2::ZERO);
+        let before = circuit.schematic();
+
+        // Deleting the pin in the middle of the wire splits it and disconnects the lamp.
+        circuit.place_component(ComponentType::Pin, IVec2::new(2, 0), Direction::East);
+        circuit.delete_all_at(IVec2::new(2, 0));
+        let after = circuit.schematic();
+        assert_ne!(
+            cluster_at(&circuit, 0, 0, Direction::East),
+            pin_cluster(&circuit, 4, 0)
+        );
+
+        assert!(circuit.undo());
+        assert!(circuit.undo());
+        assert_eq!(circuit.schematic(), before);
+        assert_eq!(
+            cluster_at(&circuit, 0, 0, Direction::East),
+            pin_cluster(&circuit, 4, 0)
+        );
+
+        assert!(circuit.redo());
+        assert!(circuit.redo());
+        assert!(!circuit.redo());
+        assert_eq!(circuit.schematic(), after);
+
+        // Undoing everything leaves an empty circuit, including the switch state.
+        while circuit.undo() {}
+        assert!(circuit.schematic().is_empty());
+        assert!(circuit.redo());
+        assert!(circuit.redo());
+        assert!(circuit.redo());
+        assert!(circuit.redo());
+        assert_eq!(circuit.schematic(), before);
+
+        // A new edit discards the redo stack.
+        circuit.undo();
+        circuit.delete_component(IVec2::ZERO);
+        assert!(!circuit.redo());
+    }
+
+    #[test]
+    fn copy_and_paste_region() {
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Switch, IVec2::ZERO, Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(3, 0), Direction::North);
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(3, 0));
+        // Crosses the selection, so it is cut at its edge.
+        circuit.place_wire(IVec2::new(1, -3), IVec2::new(1, 3));
+
+        let schematic = circuit.copy_region(IVec2::new(0, -1), IVec2::new(3, 1));
+        assert_eq!(schematic.components.len(), 4);
+        assert_eq!(schematic.wires.len(), 2);
+
+        circuit.paste(&schematic, IVec2::new(0, 9)).unwrap();
+        assert_eq!(
+            cluster_at(&circuit, 0, 10, Direction::East),
+            pin_cluster(&circuit, 3, 10)
+        );
+        assert_ne!(
+            cluster_at(&circuit, 0, 10, Direction::East),
+            pin_cluster(&circuit, 1, 9)
+        );
+
+        // Pasting on top of the original fails without changing anything.
+        let before = circuit.schematic();
+        assert!(circuit.paste(&schematic, IVec2::new(0, -1)).is_err());
+        assert_eq!(circuit.schematic(), before);
+    }
+
+    #[test]
+    fn delete_region_cuts_wires() {
+        let mut circuit = Circuit::new();
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(6, 0));
+        circuit.place_wire(IVec2::new(2, -2), IVec2::new(2, 2));
+
+        circuit.delete_region(IVec2::new(3, -1), IVec2::new(4, 1));
+        let schematic = circuit.schematic();
+        // The cut next to the crossover at (2, 0) is moved out to (1, 0).
+        assert!(schematic.wires.contains(&PlacedWire {
+            start: IVec2::new(0, 0),
+            end: IVec2::new(1, 0),
+        }));
+        assert!(schematic.wires.contains(&PlacedWire {
+            start: IVec2::new(5, 0),
+            end: IVec2::new(6, 0),
+        }));
+        assert_eq!(circuit.component_at(IVec2::new(2, 0)), None);
+        assert_ne!(pin_cluster(&circuit, 1, 0), pin_cluster(&circuit, 2, 2));
+        assert_ne!(pin_cluster(&circuit, 1, 0), pin_cluster(&circuit, 5, 0));
+
+        assert!(circuit.undo());
+        assert_eq!(pin_cluster(&circuit, 0, 0), pin_cluster(&circuit, 6, 0));
+    }
+
+    #[test]
+    fn move_region() {
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Switch, IVec2::ZERO, Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(2, 0), Direction::North);
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(2, 0));
+        circuit.place_component(ComponentType::Flip, IVec2::new(2, 5), Direction::North);
+
+        // Moving onto the flip fails and leaves the circuit as it was.
+        let before = circuit.schematic();
+        assert!(circuit
+            .move_region(IVec2::new(0, 0), IVec2::new(2, 0), IVec2::new(0, 5))
+            .is_err());
+        assert_eq!(circuit.schematic(), before);
+
+        circuit
+            .move_region(IVec2::new(0, 0), IVec2::new(2, 0), IVec2::new(0, 2))
+            .unwrap();
+        assert_eq!(circuit.component_at(IVec2::ZERO), None);
+        assert_eq!(
+            cluster_at(&circuit, 0, 2, Direction::East),
+            pin_cluster(&circuit, 2, 2)
+        );
+
+        // The move is undone in one step.
+        assert!(circuit.undo());
+        assert_eq!(circuit.schematic(), before);
+    }
+
+    #[test]
+    fn transform_component() {
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Switch, IVec2::ZERO, Direction::East);
+        circuit.place_component(ComponentType::Flip, IVec2::new(2, 0), Direction::North);
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(2, 0));
+        circuit.place_wire(IVec2::new(2, 0), IVec2::new(2, 2));
+
+        // The switch's output would no longer face its wire.
+        let before = circuit.schematic();
+        assert!(circuit
+            .transform_component(IVec2::ZERO, Transform::Rotate(Relative::Right))
+            .is_err());
+        assert_eq!(circuit.schematic(), before);
+
+        // Mirrored, the flip faces south, so both of its wires now drive its input.
+        assert!(circuit
+            .transform_component(IVec2::new(2, 0), Transform::MirrorVertical)
+            .is_ok());
+        assert_eq!(
+            cluster_at(&circuit, 0, 0, Direction::East),
+            cluster_at(&circuit, 2, 0, Direction::North)
+        );
+        assert_ne!(
+            cluster_at(&circuit, 2, 0, Direction::North),
+            cluster_at(&circuit, 2, 0, Direction::South)
+        );
+    }
+
+    #[test]
+    fn transform_region() {
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Switch, IVec2::ZERO, Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(2, 0), Direction::North);
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(2, 0));
+
+        let (min, max) = circuit
+            .transform_region(
+                IVec2::new(0, -1),
+                IVec2::new(2, 1),
+                Transform::Rotate(Relative::Left),
+            )
+            .unwrap();
+        assert_eq!((min, max), (IVec2::new(0, -1), IVec2::new(2, 1)));
+        assert_eq!(
+            circuit.component_at(IVec2::new(1, -1)),
+            Some(ComponentType::Switch)
+        );
+        assert_eq!(
+            circuit.component_at(IVec2::new(1, 1)),
+            Some(ComponentType::Lamp)
+        );
+        assert_eq!(
+            cluster_at(&circuit, 1, -1, Direction::North),
+            pin_cluster(&circuit, 1, 1)
+        );
+
+        circuit
+            .transform_region(min, max, Transform::MirrorVertical)
+            .unwrap();
+        assert_eq!(
+            circuit.component_at(IVec2::new(1, 1)),
+            Some(ComponentType::Switch)
+        );
+        assert_eq!(
+            cluster_at(&circuit, 1, 1, Direction::South),
+            pin_cluster(&circuit, 1, -1)
+        );
+    }
+
+    /// Places `first` at (0, y), followed by a flip, a flop and a lamp, all wired in a row.
+    pub(crate) fn place_chain(circuit: &mut Circuit, first: ComponentType, y: i32) {
+        circuit.place_component(first, IVec2::new(0, y), Direction::East);
+        circuit.place_component(ComponentType::Flip, IVec2::new(2, y), Direction::East);
+        circuit.place_component(ComponentType::Flop, IVec2::new(4, y), Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(6, y), Direction::East);
+        for x in [0, 2, 4] {
+            assert!(circuit.place_wire(IVec2::new(x, y), IVec2::new(x + 2, y)));
+        }
+    }
+
+    /// Places a switch at (0, y), a flip and a lamp at (4, y), all wired in a row. The lamp is
+    /// lit while the switch is off.
+    pub(crate) fn place_inverter(circuit: &mut Circuit, y: i32) {
+        for (x, ty) in [
+            (0, ComponentType::Switch),
+            (2, ComponentType::Flip),
+            (4, ComponentType::Lamp),
+        ] {
+            assert!(circuit.place_component(ty, IVec2::new(x, y), Direction::East));
+        }
+        for x in [0, 2] {
+            assert!(circuit.place_wire(IVec2::new(x, y), IVec2::new(x + 2, y)));
+        }
+    }
+
+    /// Places a chip at (0, 10) with a switch on its input and a lamp on its output.
+    pub(crate) fn place_chip_instance(circuit: &mut Circuit, id: u32) {
+        let chip = ComponentType::Chip(id);
+        assert!(circuit.place_component(chip, IVec2::new(0, 10), Direction::East));
+        circuit.place_component(ComponentType::Switch, IVec2::new(-2, 10), Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(2, 10), Direction::East);
+        assert!(circuit.place_wire(IVec2::new(-2, 10), IVec2::new(0, 10)));
+        assert!(circuit.place_wire(IVec2::new(0, 10), IVec2::new(2, 10)));
+    }
+
+    #[test]
+    fn chip_matches_flat_timing() {
+        let mut circuit = Circuit::new();
+        place_chain(&mut circuit, ComponentType::Switch, 0);
+        let id = circuit
+            .define_chip(IVec2::new(0, 0), IVec2::new(6, 0))
+            .unwrap();
+        place_chip_instance(&mut circuit, id);
+
+        // The same thing, flat, with a pin in place of the chip's switch.
+        place_chain(&mut circuit, ComponentType::Pin, 20);
+        circuit.place_component(ComponentType::Switch, IVec2::new(-2, 20), Direction::East);
+        assert!(circuit.place_wire(IVec2::new(-2, 20), IVec2::new(0, 20)));
+
+        let chip_lamp = pin_cluster(&circuit, 2, 10);
+        let flat_lamp = pin_cluster(&circuit, 6, 20);
+        let mut trace = Vec::new();
+        for tick in 0..30 {
+            if tick % 10 == 5 {
+                circuit.interact(IVec2::new(-2, 10));
+                circuit.interact(IVec2::new(-2, 20));
+            }
+            circuit.tick();
+            let powered = circuit.simulation().is_powered(chip_lamp);
+            assert_eq!(powered, circuit.simulation().is_powered(flat_lamp));
+            trace.push(powered);
+        }
+        assert!(trace.contains(&true) && trace.contains(&false));
+    }
+
+    #[test]
+    fn chip_ports_share_clusters() {
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Switch, IVec2::new(0, 0), Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(2, 0), Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(2, -2), Direction::East);
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(2, 0));
+        circuit.place_wire(IVec2::new(2, -2), IVec2::new(2, 0));
+        let id = circuit
+            .define_chip(IVec2::new(0, -2), IVec2::new(2, 0))
+            .unwrap();
+        assert_eq!(circuit.chips()[&id].inputs(), 1);
+        assert_eq!(circuit.chips()[&id].outputs(), 2);
+        assert_eq!(circuit.chips()[&id].port_name(0), "in0");
+        assert_eq!(circuit.chips()[&id].port_name(2), "out1");
+
+        // Both outputs are on the same wire inside the chip.
+        let chip = ComponentType::Chip(id);
+        assert!(circuit.place_component(chip, IVec2::new(0, 10), Direction::East));
+        assert_eq!(circuit.component_at(IVec2::new(0, 9)), Some(chip));
+        assert!(!circuit.can_place_wire(IVec2::new(0, 8), IVec2::new(0, 11)));
+        assert!(!circuit.can_place_wire(IVec2::new(0, 9), IVec2::new(-2, 9)));
+        assert!(circuit.place_wire(IVec2::new(0, 10), IVec2::new(2, 10)));
+        assert!(circuit.place_wire(IVec2::new(0, 9), IVec2::new(2, 9)));
+        assert_eq!(pin_cluster(&circuit, 2, 10), pin_cluster(&circuit, 2, 9));
+
+        // Removing the chip takes its wires with it.
+        let wires = circuit.wires.len();
+        circuit.delete_component(IVec2::new(0, 9));
+        assert_eq!(circuit.component_at(IVec2::new(0, 10)), None);
+        assert_eq!(circuit.wires.len(), wires - 2);
+        assert_ne!(pin_cluster(&circuit, 2, 10), pin_cluster(&circuit, 2, 9));
+    }
+
+    #[test]
+    fn labels_name_ports() {
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Switch, IVec2::new(0, 0), Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(2, 0), Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(2, -2), Direction::East);
+        circuit.set_label(Anchor::Tile(IVec2::new(0, 0)), " A ");
+        circuit.set_label(Anchor::Tile(IVec2::new(2, -2)), "Sum  bit");
+        circuit.set_label(Anchor::Free(Vec2::new(1.25, 0.5)), "adder");
+        let ports = circuit.ports();
+        let names = |ports: &[Port]| ports.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&ports.inputs), ["A"]);
+        assert_eq!(names(&ports.outputs), ["out0", "Sum bit"]);
+
+        let id = circuit
+            .define_chip(IVec2::new(0, -2), IVec2::new(2, 0))
+            .unwrap();
+        assert_eq!(circuit.chips()[&id].port_name(0), "A");
+        assert_eq!(circuit.chips()[&id].port_name(1), "out0");
+        assert_eq!(circuit.chips()[&id].port_name(2), "Sum bit");
+
+        // Labels move with the region they are in, and are saved.
+        circuit
+            .move_region(IVec2::new(0, -2), IVec2::new(2, 0), IVec2::new(0, 5))
+            .unwrap();
+        assert_eq!(circuit.label_at(IVec2::new(0, 0)), None);
+        assert_eq!(circuit.label_at(IVec2::new(0, 5)), Some("A"));
+        assert_eq!(
+            circuit.free_label_near(Vec2::new(1.0, 5.5), 0.5),
+            Some(Vec2::new(1.25, 5.5))
+        );
+        let mut buffer = Vec::new();
+        circuit.save(&mut buffer).unwrap();
+        let loaded = Circuit::load(&buffer[..]).unwrap();
+        assert_eq!(loaded.schematic(), circuit.schematic());
+        assert_eq!(loaded.label_at(IVec2::new(2, 3)), Some("Sum bit"));
+
+        assert!(circuit.undo());
+        assert_eq!(circuit.label_at(IVec2::new(0, 0)), Some("A"));
+        circuit.set_label(Anchor::Tile(IVec2::new(0, 0)), "");
+        assert_eq!(circuit.label_at(IVec2::new(0, 0)), None);
+        assert!(circuit.undo());
+        assert_eq!(circuit.label_at(IVec2::new(0, 0)), Some("A"));
+    }
+
+    #[test]
+    fn chip_save_load_and_undo() {
+        let mut circuit = Circuit::new();
+        place_chain(&mut circuit, ComponentType::Switch, 0);
+        let id = circuit
+            .define_chip(IVec2::new(0, 0), IVec2::new(6, 0))
+            .unwrap();
+        place_chip_instance(&mut circuit, id);
+
+        let mut buffer = Vec::new();
+        circuit.save(&mut buffer).unwrap();
+        let mut loaded = Circuit::load(&buffer[..]).unwrap();
+        assert_eq!(loaded.schematic(), circuit.schematic());
+        assert_eq!(loaded.chips()[&id].name(), circuit.chips()[&id].name());
+
+        loaded.interact(IVec2::new(-2, 10));
+        for _ in 0..10 {
+            loaded.tick();
+        }
+        assert!(!loaded.simulation().is_powered(pin_cluster(&loaded, 2, 10)));
+
+        // Undo the wires, the lamp, the switch and then the chip itself.
+        for _ in 0..5 {
+            assert!(circuit.undo());
+        }
+        assert_eq!(circuit.component_at(IVec2::new(0, 10)), None);
+        assert!(circuit.redo());
+        assert_eq!(
+            circuit.component_at(IVec2::new(0, 10)),
+            Some(ComponentType::Chip(id))
+        );
+    }
+
+    #[test]
+    fn edit_chip() {
+        let mut circuit = Circuit::new();
+        place_chain(&mut circuit, ComponentType::Switch, 0);
+        let id = circuit
+            .define_chip(IVec2::new(0, 0), IVec2::new(6, 0))
+            .unwrap();
+        place_chip_instance(&mut circuit, id);
+
+        // Replace the flip with a pin, so the chip no longer inverts.
+        let mut inner = circuit.edit_chip(id).unwrap();
+        inner.delete_all_at(IVec2::new(2, 0));
+        assert!(inner.place_wire(IVec2::new(0, 0), IVec2::new(4, 0)));
+        let new_id = circuit.finish_chip_edit(id, &inner).unwrap();
+        assert_ne!(new_id, id);
+        assert_eq!(
+            circuit.component_at(IVec2::new(0, 10)),
+            Some(ComponentType::Chip(new_id))
+        );
+
+        circuit.interact(IVec2::new(-2, 10));
+        for _ in 0..10 {
+            circuit.tick();
+        }
+        assert!(circuit
+            .simulation()
+            .is_powered(pin_cluster(&circuit, 2, 10)));
+
+        // Edits that leave the instances without ports for their wires are refused.
+        let mut inner = circuit.edit_chip(new_id).unwrap();
+        inner.delete_all_at(IVec2::new(6, 0));
+        assert!(circuit.finish_chip_edit(new_id, &inner).is_err());
+        assert_eq!(
+            circuit.component_at(IVec2::new(0, 10)),
+            Some(ComponentType::Chip(new_id))
+        );
+    }
+}
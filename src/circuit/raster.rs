@@ -0,0 +1,141 @@
+//! Drawing a circuit into an image on the CPU, for exporting it without a window.
+//!
+//! The circuit is made of the same rects as on screen, colored by the power states of a
+//! snapshot and laid over the board texture. Labels and forced clusters are not shown.
+
+use super::renderer::{component_rects, needs_crossover, wire_rect};
+use super::Circuit;
+use crate::rect::{self, Rect};
+use crate::simulation::Snapshot;
+use glam::{IVec2, Vec4};
+use image::{Rgba, RgbaImage};
+
+/// The width and height of a tile in pixels, the same as the board texture's.
+const TILE_SIZE: u32 = 16;
+// Empty tiles around the circuit.
+const MARGIN: i32 = 1;
+// The tint of the background board renderer.
+const BOARD_TINT: f32 = 0.1;
+
+/// Draws `circuit` with the power states of `snapshot`.
+pub fn render_image(circuit: &Circuit, snapshot: &Snapshot) -> RgbaImage {
+    let mut rects: Vec<Rect> = Vec::new();
+    for (_, component) in circuit.components.iter() {
+        rects.extend(component_rects(component));
+    }
+    for (_, wire) in circuit.wires.iter() {
+        rects.push(wire_rect(wire));
+    }
+    for &position in circuit.tiles.keys() {
+        if needs_crossover(circuit, position) {
+            rects.push(rect::Crossover { position }.into());
+        }
+    }
+    // Stable, so that rects on the same level are drawn in order like on screen.
+    rects.sort_by_key(|rect| rect.z_index);
+
+    let (min, max) = circuit
+        .tiles
+        .keys()
+        .fold(None, |bounds: Option<(IVec2, IVec2)>, &position| {
+            Some(match bounds {
+                Some((min, max)) => (min.min(position), max.max(position)),
+                None => (position, position),
+            })
+        })
+        .unwrap_or((IVec2::ZERO, IVec2::ZERO));
+    let (min, max) = (min - MARGIN, max + MARGIN);
+    let size = (max - min + 1).as_uvec2() * TILE_SIZE;
+
+    let board = image::load_from_memory(include_bytes!("../board/board.png"))
+        .expect("failed to load board texture")
+        .into_rgba8();
+    // Colors are blended in linear space, like on the GPU.
+    let mut pixels: Vec<Vec4> = (0..size.y)
+        .flat_map(|y| (0..size.x).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let texel = board.get_pixel(x % board.width(), y % board.height());
+            to_linear(texel) * Vec4::new(BOARD_TINT, BOARD_TINT, BOARD_TINT, 1.0)
+        })
+        .collect();
+
+    // Pixels count from the top left, tiles from the bottom left.
+    let to_pixels = |x: f32, y: f32| {
+        let x = (x - min.x as f32) * TILE_SIZE as f32;
+        let y = ((max.y + 1) as f32 - y) * TILE_SIZE as f32;
+        (
+            (x.round().max(0.0) as u32).min(size.x),
+            (y.round().max(0.0) as u32).min(size.y),
+        )
+    };
+    for rect in &rects {
+        let color = rect.color.resolve(snapshot);
+        // Rects that are turned to face another way can have a negative size.
+        let (a, b) = (rect.position, rect.position + rect.size);
+        let (left, top) = to_pixels(a.x.min(b.x), a.y.max(b.y));
+        let (right, bottom) = to_pixels(a.x.max(b.x), a.y.min(b.y));
+        for y in top..bottom {
+            let row = (y * size.x) as usize;
+            pixels[row + left as usize..row + right as usize].fill(color);
+        }
+    }
+
+    let mut pixels = pixels.into_iter();
+    RgbaImage::from_fn(size.x, size.y, |_, _| {
+        to_srgb(pixels.next().expect("one color for each pixel"))
+    })
+}
+
+fn to_linear(pixel: &Rgba<u8>) -> Vec4 {
+    let [r, g, b, a] = pixel.0.map(|channel| channel as f32 / 255.0);
+    let linear = |c: f32| match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+    };
+    Vec4::new(linear(r), linear(g), linear(b), a)
+}
+
+fn to_srgb(color: Vec4) -> Rgba<u8> {
+    let srgb = |c: f32| match c <= 0.0031308 {
+        true => c * 12.92,
+        false => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+    };
+    let [r, g, b, a] = color.to_array();
+    Rgba([srgb(r), srgb(g), srgb(b), a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_image, TILE_SIZE};
+    use crate::circuit::{Circuit, ComponentType};
+    use crate::direction::Direction;
+    use crate::simulation::Snapshot;
+    use glam::IVec2;
+    use image::Rgba;
+
+    #[test]
+    fn draws_power_states() {
+        // A flip into a lamp, which lights up.
+        let mut circuit = Circuit::new();
+        circuit.place_component(ComponentType::Flip, IVec2::new(0, 0), Direction::East);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(2, 0), Direction::East);
+        assert!(circuit.place_wire(IVec2::new(0, 0), IVec2::new(2, 0)));
+        circuit.run_until_stable(10);
+        let mut snapshot = Snapshot::default();
+        circuit.simulation().write_snapshot(&mut snapshot);
+
+        let image = render_image(&circuit, &snapshot);
+        // Three tiles, with one around them.
+        assert_eq!(image.dimensions(), (5 * TILE_SIZE, 3 * TILE_SIZE));
+        let center = |x: u32, y: u32| *image.get_pixel(x * TILE_SIZE + 8, y * TILE_SIZE + 8);
+        assert_eq!(center(3, 1), Rgba([255, 255, 0, 255]));
+        // The wire is on, between the flip and the lamp.
+        assert_eq!(center(2, 1), Rgba([255, 0, 0, 255]));
+        // The flip's body is white, and its input unpowered.
+        assert_eq!(
+            *image.get_pixel(TILE_SIZE + 4, TILE_SIZE + 8),
+            Rgba([255; 4])
+        );
+        assert_eq!(center(1, 1), Rgba([0, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,276 @@
+use super::{Circuit, Component, ComponentData, Wire};
+use crate::board::background::BackgroundBoardRenderer;
+use crate::board::BoardRenderer;
+use crate::depot;
+use crate::rect::{self, Color, Rect, RectRenderer, LAMP_PALETTE, WIRE_PALETTE};
+use crate::simulation::Snapshot;
+use crate::viewport::Viewport;
+use crate::GraphicsContext;
+use glam::{IVec2, Vec4};
+use std::collections::HashMap;
+
+/// Draws a [`Circuit`], keeping a sprite for every component, wire and crossover in it.
+pub struct CircuitRenderer {
+    background_board_renderer: BackgroundBoardRenderer,
+    board_renderer: BoardRenderer,
+    rect_renderer: RectRenderer,
+    circuit_id: Option<u64>,
+    // The rects of each component, in the order of `component_rects`.
+    components: HashMap<depot::Handle, Vec<rect::Handle>>,
+    wires: HashMap<depot::Handle, rect::Handle>,
+    crossovers: HashMap<IVec2, rect::Handle>,
+}
+
+impl CircuitRenderer {
+    pub fn new(gfx: &GraphicsContext, viewport: &Viewport) -> Self {
+        Self {
+            background_board_renderer: BackgroundBoardRenderer::new(gfx, viewport),
+            board_renderer: BoardRenderer::new(gfx, viewport),
+            rect_renderer: RectRenderer::new(gfx, viewport),
+            circuit_id: None,
+            components: HashMap::new(),
+            wires: HashMap::new(),
+            crossovers: HashMap::new(),
+        }
+    }
+
+    /// Brings the sprites up to date with everything that changed in the circuit since the last
+    /// update.
+    ///
+    /// If this is a different circuit than the one that was last seen, every sprite is rebuilt.
+    /// Wires are colored from `snapshot`, which may come from a simulation running elsewhere.
+    pub fn update(&mut self, circuit: &mut Circuit, snapshot: &Snapshot) {
+        let changes = std::mem::take(&mut circuit.changes);
+
+        if self.circuit_id != Some(circuit.id) {
+            self.circuit_id = Some(circuit.id);
+            self.components.clear();
+            self.wires.clear();
+            self.crossovers.clear();
+
+            for (handle, component) in circuit.components.iter() {
+                self.update_component(handle, Some(component));
+            }
+            for (handle, wire) in circuit.wires.iter() {
+                self.update_wire(handle, Some(wire));
+            }
+            for &position in circuit.tiles.keys() {
+                self.update_crossover(circuit, position);
+            }
+        } else {
+            for handle in changes.components {
+                let component = circuit.components.try_get(&handle);
+                self.update_component(handle, component);
+            }
+            for handle in changes.wires {
+                let wire = circuit.wires.try_get(&handle);
+                self.update_wire(handle, wire);
+            }
+            for position in changes.tiles {
+                self.update_crossover(circuit, position);
+            }
+        }
+
+        let forced = circuit.simulation.forced_clusters().map(|(id, _)| id);
+        self.rect_renderer.update_cluster_states(snapshot, forced);
+    }
+
+    pub fn draw(
+        &mut self,
+        viewport: &Viewport,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+    ) {
+        self.background_board_renderer
+            .draw(viewport, encoder, frame_view);
+        self.board_renderer
+            .draw(viewport, encoder, frame_view, depth_view);
+        self.rect_renderer
+            .draw(viewport, encoder, frame_view, depth_view);
+    }
+
+    fn update_component(&mut self, handle: depot::Handle, component: Option<&Component>) {
+        let component = match component {
+            Some(x) => x,
+            None => {
+                self.components.remove(&handle);
+                return;
+            }
+        };
+        let rects = component_rects(component);
+        let rect_renderer = &mut self.rect_renderer;
+        let sprite = self.components.entry(handle).or_insert_with(|| {
+            rects
+                .iter()
+                .map(|rect| rect_renderer.insert(rect))
+                .collect()
+        });
+        for (instance, rect) in sprite.iter().zip(&rects) {
+            instance.set(rect);
+        }
+    }
+
+    fn update_wire(&mut self, handle: depot::Handle, wire: Option<&Wire>) {
+        let wire = match wire {
+            Some(x) => x,
+            None => {
+                self.wires.remove(&handle);
+                return;
+            }
+        };
+        let rect_renderer = &mut self.rect_renderer;
+        let instance = self
+            .wires
+            .entry(handle)
+            .or_insert_with(|| rect_renderer.insert(&Default::default()));
+        instance.set(&wire_rect(wire));
+    }
+
+    fn update_crossover(&mut self, circuit: &Circuit, position: IVec2) {
+        if !needs_crossover(circuit, position) {
+            self.crossovers.remove(&position);
+        } else if !self.crossovers.contains_key(&position) {
+            let handle = self
+                .rect_renderer
+                .insert(&rect::Crossover { position }.into());
+            self.crossovers.insert(position, handle);
+        }
+    }
+}
+
+/// Whether wires cross on the tile at `position` without connecting.
+pub(super) fn needs_crossover(circuit: &Circuit, position: IVec2) -> bool {
+    match circuit.tile(position) {
+        Some(tile) => tile.component.is_none() && tile.wires.count() >= 2,
+        None => false,
+    }
+}
+
+pub(super) fn wire_rect(wire: &Wire) -> Rect {
+    rect::Wire {
+        start: wire.start,
+        end: wire.end,
+        start_connection: wire.start_connection,
+        end_connection: wire.end_connection,
+        color: wire_color(wire.cluster_index),
+    }
+    .into()
+}
+
+fn wire_color(cluster_index: u32) -> Color {
+    Color::Wire {
+        cluster_index,
+        delayed: false,
+        inverted: false,
+        palette_index: WIRE_PALETTE,
+    }
+}
+
+/// The rects that make up a component. There are always as many for a type of component (and
+/// chip), so that they can be updated in place.
+pub(super) fn component_rects(component: &Component) -> Vec<Rect> {
+    let position = component.position;
+    let orientation = component.orientation;
+    // Outputs show the input that they are about to follow.
+    let output = |cluster_index: u32, inverted: bool| -> Rect {
+        rect::Output {
+            position,
+            orientation,
+            color: Color::Wire {
+                cluster_index,
+                delayed: true,
+                inverted,
+                palette_index: WIRE_PALETTE,
+            },
+        }
+        .into()
+    };
+    match &component.data {
+        ComponentData::Pin(state) => vec![rect::Pin {
+            position,
+            color: wire_color(state.cluster_index),
+        }
+        .into()],
+        ComponentData::Flip(state) => vec![
+            rect::Body { position }.into(),
+            rect::Pin {
+                position,
+                color: wire_color(state.input_cluster_index),
+            }
+            .into(),
+            output(state.input_cluster_index, true),
+        ],
+        ComponentData::Flop(state) => vec![
+            rect::Body { position }.into(),
+            rect::SidePin {
+                position,
+                orientation: orientation.opposite(),
+                color: wire_color(state.input_cluster_index),
+            }
+            .into(),
+            output(state.input_cluster_index, false),
+        ],
+        ComponentData::Switch(state) => {
+            const SWITCH_HANDLE: Vec4 = Vec4::new(0.5, 0.1, 0.0, 1.0);
+            let indicator = if state.switched {
+                rect::Pin {
+                    position,
+                    color: Color::Fixed(SWITCH_HANDLE),
+                }
+                .into()
+            } else {
+                rect::SidePin {
+                    position,
+                    orientation: orientation.opposite(),
+                    color: Color::Fixed(SWITCH_HANDLE),
+                }
+                .into()
+            };
+            vec![
+                rect::Body { position }.into(),
+                output(state.input_cluster_index, false),
+                indicator,
+            ]
+        }
+        ComponentData::Lamp(state) => vec![rect::Lamp {
+            position,
+            color: Color::Wire {
+                cluster_index: state.cluster_index,
+                delayed: false,
+                inverted: false,
+                palette_index: LAMP_PALETTE,
+            },
+        }
+        .into()],
+        ComponentData::Chip(state) => {
+            let definition = &state.definition;
+            let body = rect::ChipBody {
+                position,
+                orientation,
+                length: definition.length(),
+            };
+            let mut rects = vec![body.into()];
+            for port in 0..definition.num_ports() {
+                let (position, face) = definition.port_location(port, position, orientation);
+                let color = wire_color(state.port_cluster(port));
+                rects.push(if port < definition.inputs() {
+                    rect::SidePin {
+                        position,
+                        orientation: face,
+                        color,
+                    }
+                    .into()
+                } else {
+                    rect::Output {
+                        position,
+                        orientation: face,
+                        color,
+                    }
+                    .into()
+                });
+            }
+            rects
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Commands that run without opening a window, for scripts and batch jobs. See [`USAGE`].
+
+use crate::circuit::{self, Circuit, ComponentType, PortRef};
+use crate::dot::ClusterGraph;
+use crate::netlist::Netlist;
+use crate::simulation::Snapshot;
+use crate::statistics::Statistics;
+use crate::test_vectors::TestVectors;
+use crate::truth_table::{self, TruthTable};
+use anyhow::{bail, ensure, Context};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+pub const USAGE: &str = "\
+Usage:
+  flipflop [<circuit>]                Open a circuit in a window
+  flipflop run <circuit> [<step>...]  Run a circuit, then print its lamps
+  flipflop test <circuit> <vectors>   Check a circuit against a file of test vectors,
+                                      and fail if it doesn't pass
+  flipflop stats <circuit>            Print what a circuit is made of
+  flipflop help                       Print this
+
+Steps of `run`, which are taken in order:
+  --ticks <n>        Run for n ticks
+  --stable           Run until the simulation settles, and fail if it doesn't
+  --toggle <switch>  Toggle a switch, named by its label or coordinates, like 3,-2
+  --export <file>    Write the circuit as it is now, in the format of the extension:
+                       .v or .blif    Verilog or BLIF netlist
+                       .dot           graph of clusters
+                       .png           image
+                       .csv or .md    truth table of every switch and lamp
+                       .txt           statistics
+                       .flipflop      the circuit itself, with its switches
+";
+
+const COMMANDS: [&str; 4] = ["run", "test", "stats", "help"];
+
+/// A command line, without the program name.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run { circuit: PathBuf, steps: Vec<Step> },
+    Test { circuit: PathBuf, vectors: PathBuf },
+    Stats { circuit: PathBuf },
+    Help,
+}
+
+/// A step of `run`.
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Ticks(u64),
+    Stable,
+    Toggle(PortRef),
+    Export(PathBuf),
+}
+
+/// Whether `args` (without the program name) are a command rather than a circuit to open.
+pub fn is_command(args: &[OsString]) -> bool {
+    args.first()
+        .is_some_and(|arg| COMMANDS.iter().any(|&command| arg == command))
+}
+
+pub fn run(args: &[OsString]) -> anyhow::Result<ExitCode> {
+    parse(args)?.execute(io::stdout().lock())
+}
+
+pub fn parse(args: &[OsString]) -> anyhow::Result<Command> {
+    let args = args
+        .iter()
+        .map(|arg| arg.to_str().context("arguments must be valid UTF-8"))
+        .collect::<anyhow::Result<Vec<&str>>>()?;
+    Ok(match args[..] {
+        ["run", circuit, ref steps @ ..] => Command::Run {
+            circuit: circuit.into(),
+            steps: parse_steps(steps)?,
+        },
+        ["test", circuit, vectors] => Command::Test {
+            circuit: circuit.into(),
+            vectors: vectors.into(),
+        },
+        ["stats", circuit] => Command::Stats {
+            circuit: circuit.into(),
+        },
+        ["help"] => Command::Help,
+        _ => bail!("invalid arguments\n\n{}", USAGE),
+    })
+}
+
+fn parse_steps(args: &[&str]) -> anyhow::Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    let mut args = args.iter();
+    while let Some(&step) = args.next() {
+        let mut argument = || {
+            args.next()
+                .copied()
+                .with_context(|| format!("`{}` needs an argument", step))
+        };
+        steps.push(match step {
+            "--ticks" => Step::Ticks(
+                argument()?
+                    .parse()
+                    .context("`--ticks` needs a number of ticks")?,
+            ),
+            "--stable" => Step::Stable,
+            "--toggle" => Step::Toggle(PortRef::parse(argument()?)),
+            "--export" => Step::Export(argument()?.into()),
+            _ => bail!("unknown step `{}`\n\n{}", step, USAGE),
+        });
+    }
+    Ok(steps)
+}
+
+impl Command {
+    /// Carries out the command, printing to `output`.
+    pub fn execute<W: Write>(&self, mut output: W) -> anyhow::Result<ExitCode> {
+        match self {
+            Self::Run { circuit, steps } => run_steps(&mut load(circuit)?, steps, output),
+            Self::Test { circuit, vectors } => test(&load(circuit)?, vectors, output),
+            Self::Stats { circuit } => {
+                let statistics = Statistics::new(&load(circuit)?, crate::SETTLE_BUDGET)?;
+                writeln!(output, "{}", statistics)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Self::Help => {
+                write!(output, "{}", USAGE)?;
+                Ok(ExitCode::SUCCESS)
+            }
+        }
+    }
+}
+
+fn load(path: &Path) -> anyhow::Result<Circuit> {
+    File::open(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| Circuit::load(BufReader::new(file)))
+        .with_context(|| format!("failed to open {}", path.display()))
+}
+
+/// Takes `steps` on `circuit`, then prints the tick and every lamp.
+fn run_steps<W: Write>(
+    circuit: &mut Circuit,
+    steps: &[Step],
+    mut output: W,
+) -> anyhow::Result<ExitCode> {
+    for step in steps {
+        match step {
+            Step::Ticks(ticks) => {
+                for _ in 0..*ticks {
+                    circuit.tick();
+                }
+            }
+            Step::Stable => {
+                let (_, settled) = circuit.run_until_stable(crate::SETTLE_BUDGET);
+                ensure!(
+                    settled,
+                    "the circuit did not settle within {} ticks",
+                    crate::SETTLE_BUDGET
+                );
+            }
+            Step::Toggle(reference) => {
+                let switch = circuit.find_port(reference, ComponentType::Switch)?;
+                circuit.interact(switch.position);
+            }
+            Step::Export(path) => {
+                export(circuit, path)
+                    .with_context(|| format!("failed to export {}", path.display()))?;
+            }
+        }
+    }
+
+    let simulation = circuit.simulation();
+    writeln!(output, "tick {}", simulation.tick_count())?;
+    for lamp in circuit.ports().outputs {
+        let cluster = circuit
+            .tile_cluster(lamp.position)
+            .context("lamp without a cluster")?;
+        writeln!(
+            output,
+            "{} = {}",
+            lamp.name,
+            simulation.is_powered(cluster) as u8
+        )?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Writes `circuit` to `path`, in the format that goes with its extension. Netlists and graphs
+/// are named after the file.
+fn export(circuit: &Circuit, path: &Path) -> anyhow::Result<()> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    let name = path
+        .file_stem()
+        .map_or("circuit".into(), |stem| stem.to_string_lossy());
+    // Flushes the file once `write` is done, so that an error writing the end of it isn't lost.
+    let create = |write: &dyn Fn(&mut BufWriter<File>) -> io::Result<()>| {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write(&mut writer)?;
+        writer.flush()
+    };
+    let mut snapshot = Snapshot::default();
+    circuit.simulation().write_snapshot(&mut snapshot);
+    match extension {
+        "v" => create(&|writer| Netlist::new(circuit).write_verilog(writer, &name))?,
+        "blif" => create(&|writer| Netlist::new(circuit).write_blif(writer, &name))?,
+        "dot" => {
+            create(&|writer| ClusterGraph::new(circuit).write_dot(writer, &name, Some(&snapshot)))?
+        }
+        "png" => circuit::render_image(circuit, &snapshot).save(path)?,
+        "csv" => {
+            let table = truth_table(circuit)?;
+            create(&|writer| table.write(writer, truth_table::Format::Csv))?;
+        }
+        "md" => {
+            let table = truth_table(circuit)?;
+            create(&|writer| table.write(writer, truth_table::Format::Markdown))?;
+        }
+        "txt" => {
+            let statistics = Statistics::new(circuit, crate::SETTLE_BUDGET)?;
+            create(&|writer| writeln!(writer, "{}", statistics))?;
+        }
+        "flipflop" => create(&|writer| circuit.save(writer))?,
+        _ => bail!("unknown format `.{}`", extension),
+    }
+    Ok(())
+}
+
+fn truth_table(circuit: &Circuit) -> anyhow::Result<TruthTable> {
+    let ports = circuit.ports();
+    TruthTable::generate(
+        circuit,
+        &ports.inputs,
+        &ports.outputs,
+        crate::TRUTH_TABLE_BUDGET,
+    )
+}
+
+fn test<W: Write>(circuit: &Circuit, vectors: &Path, mut output: W) -> anyhow::Result<ExitCode> {
+    let text = std::fs::read_to_string(vectors)
+        .with_context(|| format!("failed to read {}", vectors.display()))?;
+    let report = TestVectors::parse(&text)
+        .and_then(|vectors| vectors.run(circuit))
+        .with_context(|| format!("in {}", vectors.display()))?;
+    writeln!(output, "{}", report)?;
+    Ok(match report.passed() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Command, Step};
+    use crate::circuit::tests::place_inverter;
+    use crate::circuit::{Circuit, PortRef};
+    use crate::schematic::Anchor;
+    use glam::IVec2;
+    use std::ffi::OsString;
+    use std::fs::File;
+    use std::process::ExitCode;
+
+    fn args(line: &str) -> Vec<OsString> {
+        line.split_whitespace().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            parse(&args(
+                "run a.flipflop --stable --toggle 3,-2 --ticks 5 --export a.png"
+            ))
+            .unwrap(),
+            Command::Run {
+                circuit: "a.flipflop".into(),
+                steps: vec![
+                    Step::Stable,
+                    Step::Toggle(PortRef::Position(IVec2::new(3, -2))),
+                    Step::Ticks(5),
+                    Step::Export("a.png".into()),
+                ],
+            }
+        );
+        assert_eq!(
+            parse(&args("test a.flipflop a.txt")).unwrap(),
+            Command::Test {
+                circuit: "a.flipflop".into(),
+                vectors: "a.txt".into(),
+            }
+        );
+        assert_eq!(parse(&args("help")).unwrap(), Command::Help);
+
+        for line in [
+            "",
+            "run",
+            "test a.flipflop",
+            "stats",
+            "stats a.flipflop b.flipflop",
+            "help me",
+            "frobnicate a.flipflop",
+            "run a.flipflop --ticks",
+            "run a.flipflop --ticks many",
+            "run a.flipflop --ticks -1",
+            "run a.flipflop --toggle",
+            "run a.flipflop --fast",
+        ] {
+            assert!(parse(&args(line)).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn runs_circuit() {
+        let mut circuit = Circuit::new();
+        place_inverter(&mut circuit, 0);
+        circuit.set_label(Anchor::Tile(IVec2::new(0, 0)), "a");
+        circuit.set_label(Anchor::Tile(IVec2::new(4, 0)), "not a");
+        let directory = std::env::temp_dir().join(format!("flipflop-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("inverter.flipflop");
+        circuit.save(File::create(&path).unwrap()).unwrap();
+
+        let run = |steps: &str| -> anyhow::Result<String> {
+            let line = format!("run {} {}", path.display(), steps);
+            let mut output = Vec::new();
+            let code = parse(&args(&line)).unwrap().execute(&mut output)?;
+            assert_eq!(code, ExitCode::SUCCESS);
+            Ok(String::from_utf8(output).unwrap())
+        };
+        assert_eq!(run("--ticks 5").unwrap(), "tick 5\nnot a = 1\n");
+        // The lamp goes out three ticks after the switch is toggled.
+        assert_eq!(
+            run("--stable --toggle a --ticks 2").unwrap(),
+            "tick 3\nnot a = 1\n"
+        );
+        let stats = directory.join("inverter.txt");
+        assert_eq!(
+            run(&format!(
+                "--stable --toggle 0,0 --ticks 3 --export {}",
+                stats.display()
+            ))
+            .unwrap(),
+            "tick 4\nnot a = 0\n"
+        );
+        assert!(std::fs::read_to_string(&stats)
+            .unwrap()
+            .starts_with("components: 1 flips"));
+
+        assert!(run("--toggle b").is_err());
+        assert!(run("--export inverter.gif").is_err());
+        let missing = directory.join("missing.flipflop");
+        let command = parse(&args(&format!("stats {}", missing.display()))).unwrap();
+        assert!(command.execute(Vec::new()).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
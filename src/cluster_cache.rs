@@ -0,0 +1,66 @@
+//! Things that the editor keeps on tiles, but the simulation needs on clusters.
+//!
+//! Clusters are renumbered by edits, so the tiles are looked up again whenever the circuit
+//! changes, and the result is only handed to the simulation when it is different.
+
+use crate::circuit::Circuit;
+use glam::IVec2;
+
+pub struct ClusterCache<T> {
+    // What was last handed to the simulation, and for which circuit.
+    sent: Option<(u64, T)>,
+}
+
+impl<T: Clone + PartialEq> ClusterCache<T> {
+    pub fn new() -> Self {
+        Self { sent: None }
+    }
+
+    /// Calls `resolve` with a lookup of the cluster on a tile of `circuit`. Returns what it
+    /// made of them if that differs from what was returned last time, or if it was for
+    /// another circuit.
+    pub fn resolve<F>(&mut self, circuit: &Circuit, resolve: F) -> Option<T>
+    where
+        F: FnOnce(&dyn Fn(IVec2) -> Option<u32>) -> T,
+    {
+        let resolved = resolve(&|position| circuit.tile_cluster(position));
+        match &self.sent {
+            Some((id, sent)) if *id == circuit.id() && *sent == resolved => None,
+            _ => {
+                self.sent = Some((circuit.id(), resolved.clone()));
+                Some(resolved)
+            }
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> Default for ClusterCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClusterCache;
+    use crate::circuit::Circuit;
+    use glam::IVec2;
+
+    #[test]
+    fn only_returns_changes() {
+        let mut circuit = Circuit::new();
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(2, 0));
+        let mut cache = ClusterCache::new();
+        let lookup = |cluster: &dyn Fn(IVec2) -> Option<u32>| cluster(IVec2::new(2, 0));
+        let cluster = cache.resolve(&circuit, lookup).unwrap();
+        assert_eq!(cluster, circuit.tile_cluster(IVec2::new(2, 0)));
+        assert_eq!(cache.resolve(&circuit, lookup), None);
+
+        // Another circuit has a simulation of its own, even if the cluster is the same.
+        let mut other = Circuit::new();
+        other.place_wire(IVec2::new(0, 0), IVec2::new(2, 0));
+        assert!(cache.resolve(&other, lookup).is_some());
+        circuit.delete_all_at(IVec2::new(2, 0));
+        assert_eq!(cache.resolve(&circuit, lookup), Some(None));
+    }
+}
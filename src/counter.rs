@@ -18,6 +18,11 @@ impl Counter {
     }
 
     pub fn tick(&mut self) {
+        self.add(1);
+    }
+
+    /// Records `count` events at once.
+    pub fn add(&mut self, count: usize) {
         let now = Instant::now();
         let interval = now - self.since;
         if interval >= UPDATE_INTERVAL {
@@ -25,7 +30,7 @@ impl Counter {
             self.rate = (self.count as f32) / interval.as_secs_f32();
             self.count = 0;
         }
-        self.count += 1;
+        self.count += count;
     }
 
     pub fn rate(&self) -> f32 {
@@ -1,26 +1,53 @@
 mod outline;
 
 use self::outline::OutlineRenderer;
-use crate::circuit::{wire_direction, Circuit, ComponentType};
+use crate::circuit::{wire_direction, ChipLibrary, Circuit, ComponentType};
 use crate::direction::Direction;
-use crate::rect::{self, Color, RectRenderer};
+use crate::rect::{self, Color, Rect, RectRenderer};
+use crate::schematic::Schematic;
 use crate::viewport::Viewport;
 use crate::GraphicsContext;
 use glam::{IVec2, Vec2, Vec3, Vec4};
 
+const SELECTION_BORDER_WIDTH: f32 = 1.0 / 16.0;
+const SELECTION_BORDER_Z_INDEX: u8 = 7;
+const SELECTION_COLOR: Vec4 = Vec4::new(0.0, 0.0, 1.0, 1.0);
+const HIGHLIGHT_COLOR: Vec4 = Vec4::new(1.0, 0.5, 0.0, 1.0);
+
 pub struct CursorManager {
     rect_renderer: RectRenderer,
     outline_renderer: OutlineRenderer,
     current_state: CursorState,
     place_sprite: Sprite,
     place_orientation: Direction,
+    selection: Option<(IVec2, IVec2)>,
+    selection_border: [rect::Handle; 4],
+    // A tile that needs attention, drawn separately so it gets an outline of its own color.
+    highlight_renderer: RectRenderer,
+    highlight_outline_renderer: OutlineRenderer,
+    highlight_border: [rect::Handle; 4],
 }
 
 impl CursorManager {
     pub fn new(gfx: &GraphicsContext, viewport: &Viewport) -> Self {
         let mut rect_renderer = RectRenderer::new(gfx, viewport);
-        let place_sprite = Sprite::new(ComponentType::Pin, &mut rect_renderer);
+        let place_sprite = Sprite::new(ComponentType::Pin, &ChipLibrary::new(), &mut rect_renderer);
         let outline_renderer = OutlineRenderer::new(gfx, viewport);
+        let selection_border = [
+            rect_renderer.insert(&Default::default()),
+            rect_renderer.insert(&Default::default()),
+            rect_renderer.insert(&Default::default()),
+            rect_renderer.insert(&Default::default()),
+        ];
+        let mut highlight_renderer = RectRenderer::new(gfx, viewport);
+        let mut highlight_outline_renderer = OutlineRenderer::new(gfx, viewport);
+        highlight_outline_renderer.set_outline_color(HIGHLIGHT_COLOR.truncate());
+        let highlight_border = [
+            highlight_renderer.insert(&Default::default()),
+            highlight_renderer.insert(&Default::default()),
+            highlight_renderer.insert(&Default::default()),
+            highlight_renderer.insert(&Default::default()),
+        ];
 
         Self {
             rect_renderer,
@@ -28,6 +55,11 @@ impl CursorManager {
             outline_renderer,
             current_state: CursorState::Normal,
             place_orientation: Direction::North,
+            selection: None,
+            selection_border,
+            highlight_renderer,
+            highlight_outline_renderer,
+            highlight_border,
         }
     }
 
@@ -39,10 +71,34 @@ impl CursorManager {
         self.place_sprite.update(
             viewport.cursor().tile(),
             self.place_orientation,
-            &self.current_state,
+            matches!(self.current_state, CursorState::Normal),
         );
+        let mut valid_selection = None;
         match &mut self.current_state {
             CursorState::Normal => {}
+            CursorState::Select {
+                start_position,
+                end_position,
+            } => {
+                *end_position = viewport.cursor().tile();
+                self.selection = Some((
+                    start_position.min(*end_position),
+                    start_position.max(*end_position),
+                ));
+            }
+            CursorState::Move {
+                start_position,
+                preview,
+            } => {
+                if let Some((min, _)) = self.selection {
+                    preview.update(min + viewport.cursor().tile() - *start_position);
+                }
+            }
+            CursorState::Paste { preview } => {
+                let offset = viewport.cursor().tile();
+                preview.update(offset);
+                valid_selection = Some(preview.can_place(circuit, offset));
+            }
             CursorState::Pan { last_position } => {
                 let position = viewport.cursor().screen_position;
                 let delta = (position - *last_position) * Vec2::new(1.0, -1.0);
@@ -108,12 +164,32 @@ impl CursorManager {
             }
         }
 
+        match self.selection {
+            Some((min, max)) => {
+                for (handle, rect) in
+                    self.selection_border
+                        .iter()
+                        .zip(border_rects(min, max, SELECTION_COLOR))
+                {
+                    handle.set(&rect);
+                }
+            }
+            None => {
+                for handle in &self.selection_border {
+                    handle.set(&Default::default());
+                }
+            }
+        }
+
         let valid_place = match &self.current_state {
             &CursorState::PlaceWire {
                 start_position,
                 end_position,
                 ..
             } => circuit.can_place_wire(start_position, end_position),
+            CursorState::Select { .. } | CursorState::Move { .. } | CursorState::Paste { .. } => {
+                valid_selection.unwrap_or(true)
+            }
             _ => match self.place_type() {
                 ComponentType::Pin => true,
                 other_type => circuit.can_place_component(
@@ -143,6 +219,28 @@ impl CursorManager {
             .draw(viewport, encoder, frame_view, depth_view);
         self.outline_renderer
             .draw(viewport, encoder, frame_view, depth_view);
+        self.highlight_renderer
+            .draw(viewport, encoder, frame_view, depth_view);
+        self.highlight_outline_renderer
+            .draw(viewport, encoder, frame_view, depth_view);
+    }
+
+    /// Draws a border around the tile at `position`, such as the one that made a breakpoint
+    /// fire, or removes it.
+    pub fn set_highlight(&mut self, position: Option<IVec2>) {
+        match position {
+            Some(position) => {
+                let rects = border_rects(position, position, HIGHLIGHT_COLOR);
+                for (handle, rect) in self.highlight_border.iter().zip(rects) {
+                    handle.set(&rect);
+                }
+            }
+            None => {
+                for handle in &self.highlight_border {
+                    handle.set(&Default::default());
+                }
+            }
+        }
     }
 
     pub fn start_pan(&mut self, viewport: &Viewport) {
@@ -186,10 +284,60 @@ impl CursorManager {
         })
     }
 
+    pub fn start_select(&mut self, viewport: &Viewport) {
+        let start_position = viewport.cursor().tile();
+        self.selection = Some((start_position, start_position));
+        self.replace(CursorState::Select {
+            start_position,
+            end_position: start_position,
+        });
+    }
+
+    /// Starts dragging the current selection, previewing the contents of the selected area
+    /// wherever it is dropped.
+    pub fn start_move(&mut self, viewport: &Viewport, circuit: &Circuit) {
+        let (min, max) = match self.selection {
+            Some(x) => x,
+            None => return,
+        };
+        let preview = Preview::new(
+            circuit.copy_region(min, max),
+            circuit.chips(),
+            &mut self.rect_renderer,
+        );
+        self.replace(CursorState::Move {
+            start_position: viewport.cursor().tile(),
+            preview,
+        });
+    }
+
+    /// Previews `schematic` at the cursor until the state is ended.
+    pub fn start_paste(&mut self, schematic: Schematic, circuit: &Circuit) {
+        self.selection = None;
+        let preview = Preview::new(schematic, circuit.chips(), &mut self.rect_renderer);
+        self.replace(CursorState::Paste { preview });
+    }
+
     pub fn end(&mut self) {
         self.replace(CursorState::Normal);
     }
 
+    /// The selected area, as its minimum and maximum tile (inclusive).
+    pub fn selection(&self) -> Option<(IVec2, IVec2)> {
+        self.selection
+    }
+
+    pub fn set_selection(&mut self, selection: Option<(IVec2, IVec2)>) {
+        self.selection = selection;
+    }
+
+    pub fn selection_contains(&self, position: IVec2) -> bool {
+        match self.selection {
+            Some((min, max)) => position.cmpge(min).all() && position.cmple(max).all(),
+            None => false,
+        }
+    }
+
     pub fn place_type(&self) -> ComponentType {
         self.place_sprite.component_type()
     }
@@ -198,9 +346,9 @@ impl CursorManager {
         self.place_orientation
     }
 
-    pub fn set_place_type(&mut self, ty: ComponentType) {
+    pub fn set_place_type(&mut self, ty: ComponentType, circuit: &Circuit) {
         if ty != self.place_sprite.component_type() {
-            self.place_sprite = Sprite::new(ty, &mut self.rect_renderer);
+            self.place_sprite = Sprite::new(ty, circuit.chips(), &mut self.rect_renderer);
         }
     }
 
@@ -225,6 +373,111 @@ pub enum CursorState {
         end_pin: rect::Handle,
         wire: rect::Handle,
     },
+    Select {
+        start_position: IVec2,
+        end_position: IVec2,
+    },
+    Move {
+        start_position: IVec2,
+        preview: Preview,
+    },
+    Paste {
+        preview: Preview,
+    },
+}
+
+/// Sprites for every component and wire of a [`Schematic`] that is about to be placed.
+pub struct Preview {
+    schematic: Schematic,
+    components: Vec<Sprite>,
+    wires: Vec<rect::Handle>,
+}
+
+impl Preview {
+    fn new(schematic: Schematic, chips: &ChipLibrary, renderer: &mut RectRenderer) -> Self {
+        let components = schematic
+            .components
+            .iter()
+            .map(|component| Sprite::new(component.ty, chips, renderer))
+            .collect();
+        let wires = schematic
+            .wires
+            .iter()
+            .map(|_| renderer.insert(&Default::default()))
+            .collect();
+        Self {
+            schematic,
+            components,
+            wires,
+        }
+    }
+
+    pub fn schematic(&self) -> &Schematic {
+        &self.schematic
+    }
+
+    fn update(&self, offset: IVec2) {
+        for (component, sprite) in self.schematic.components.iter().zip(&self.components) {
+            sprite.update(component.position + offset, component.orientation, true);
+        }
+        for (wire, handle) in self.schematic.wires.iter().zip(&self.wires) {
+            handle.set(
+                &rect::Wire {
+                    start: wire.start + offset,
+                    end: wire.end + offset,
+                    start_connection: Default::default(),
+                    end_connection: Default::default(),
+                    color: Default::default(),
+                }
+                .into(),
+            );
+        }
+    }
+
+    // Only checks against what is already in the circuit, not between parts of the preview.
+    fn can_place(&self, circuit: &Circuit, offset: IVec2) -> bool {
+        let components_fit = self.schematic.components.iter().all(|component| {
+            let position = component.position + offset;
+            match component.ty {
+                ComponentType::Pin => matches!(
+                    circuit.component_at(position),
+                    None | Some(ComponentType::Pin)
+                ),
+                ty => circuit.can_place_component(ty, position, component.orientation),
+            }
+        });
+        let wires_fit = self
+            .schematic
+            .wires
+            .iter()
+            .all(|wire| circuit.can_place_wire(wire.start + offset, wire.end + offset));
+        components_fit && wires_fit
+    }
+}
+
+fn border_rects(min: IVec2, max: IVec2, color: Vec4) -> [Rect; 4] {
+    let min = min.as_vec2() - Vec2::splat(SELECTION_BORDER_WIDTH);
+    let max = (max + IVec2::ONE).as_vec2();
+    let size = max - min;
+    let color = Color::Fixed(color);
+    let edge = |position: Vec2, size: Vec2| Rect {
+        position,
+        z_index: SELECTION_BORDER_Z_INDEX,
+        size,
+        color,
+    };
+    [
+        edge(min, Vec2::new(size.x, SELECTION_BORDER_WIDTH)),
+        edge(
+            Vec2::new(min.x, max.y),
+            Vec2::new(size.x + SELECTION_BORDER_WIDTH, SELECTION_BORDER_WIDTH),
+        ),
+        edge(min, Vec2::new(SELECTION_BORDER_WIDTH, size.y)),
+        edge(
+            Vec2::new(max.x, min.y),
+            Vec2::new(SELECTION_BORDER_WIDTH, size.y),
+        ),
+    ]
 }
 
 enum Sprite {
@@ -249,10 +502,15 @@ enum Sprite {
     Lamp {
         lamp: rect::Handle,
     },
+    Chip {
+        id: u32,
+        length: i32,
+        body: rect::Handle,
+    },
 }
 
 impl Sprite {
-    fn new(ty: ComponentType, renderer: &mut RectRenderer) -> Self {
+    fn new(ty: ComponentType, chips: &ChipLibrary, renderer: &mut RectRenderer) -> Self {
         match ty {
             ComponentType::Pin => Self::Pin {
                 pin: renderer.insert(&Default::default()),
@@ -275,6 +533,11 @@ impl Sprite {
             ComponentType::Lamp => Self::Lamp {
                 lamp: renderer.insert(&Default::default()),
             },
+            ComponentType::Chip(id) => Self::Chip {
+                id,
+                length: chips.get(&id).map_or(1, |definition| definition.length()),
+                body: renderer.insert(&Default::default()),
+            },
         }
     }
 
@@ -285,15 +548,11 @@ impl Sprite {
             Self::Flop { .. } => ComponentType::Flop,
             Self::Switch { .. } => ComponentType::Switch,
             Self::Lamp { .. } => ComponentType::Lamp,
+            Self::Chip { id, .. } => ComponentType::Chip(*id),
         }
     }
 
-    fn update(&self, position: IVec2, orientation: Direction, current_state: &CursorState) {
-        let visible = match current_state {
-            CursorState::Normal => true,
-            CursorState::Pan { .. } => false,
-            CursorState::PlaceWire { .. } => false,
-        };
+    fn update(&self, position: IVec2, orientation: Direction, visible: bool) {
         match self {
             Self::Pin { pin } => {
                 if visible {
@@ -407,6 +666,20 @@ impl Sprite {
                     lamp.set(&Default::default());
                 }
             }
+            Self::Chip { length, body, .. } => {
+                if visible {
+                    body.set(
+                        &rect::ChipBody {
+                            position,
+                            orientation,
+                            length: *length,
+                        }
+                        .into(),
+                    );
+                } else {
+                    body.set(&Default::default());
+                }
+            }
         }
     }
 }
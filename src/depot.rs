@@ -41,6 +41,14 @@ impl<T> Depot<T> {
             .expect("handle is invalid for this depot")
     }
 
+    pub fn try_get(&self, handle: &Handle) -> Option<&T> {
+        self.items.get(handle)
+    }
+
+    pub fn contains(&self, handle: &Handle) -> bool {
+        self.items.contains_key(handle)
+    }
+
     pub fn get_mut(&mut self, handle: &Handle) -> &mut T {
         self.items
             .get_mut(handle)
@@ -56,6 +64,10 @@ impl<T> Depot<T> {
     pub fn len(&self) -> usize {
         self.items.len()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> {
+        self.items.iter().map(|(&handle, item)| (handle, item))
+    }
 }
 
 impl<'a, T> ops::Index<&'a Handle> for Depot<T> {
@@ -1,4 +1,4 @@
-use glam::{mat2, Mat2, Vec2};
+use glam::{mat2, IVec2, Mat2, Vec2};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Direction {
@@ -21,6 +21,16 @@ impl Direction {
         self.rotate(Relative::Opposite)
     }
 
+    /// The vector from a tile to its neighbor in this direction.
+    pub fn offset(self) -> IVec2 {
+        match self {
+            Self::East => IVec2::X,
+            Self::North => IVec2::Y,
+            Self::West => -IVec2::X,
+            Self::South => -IVec2::Y,
+        }
+    }
+
     pub fn rotate(self, relative: Relative) -> Self {
         match (self, relative) {
             (Self::East, Relative::Same) => Self::East,
@@ -88,3 +98,40 @@ impl Relative {
         }
     }
 }
+
+/// A rotation or reflection of the tile grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transform {
+    Rotate(Relative),
+    /// Mirror along the vertical axis, swapping east and west.
+    MirrorHorizontal,
+    /// Mirror along the horizontal axis, swapping north and south.
+    MirrorVertical,
+}
+
+impl Transform {
+    pub fn direction(self, direction: Direction) -> Direction {
+        match (self, direction) {
+            (Self::Rotate(relative), _) => direction.rotate(relative),
+            (Self::MirrorHorizontal, Direction::East | Direction::West)
+            | (Self::MirrorVertical, Direction::North | Direction::South) => direction.opposite(),
+            _ => direction,
+        }
+    }
+
+    pub fn vector(self, vector: IVec2) -> IVec2 {
+        match self {
+            Self::Rotate(relative) => (relative.transform() * vector.as_vec2()).round().as_ivec2(),
+            Self::MirrorHorizontal => IVec2::new(-vector.x, vector.y),
+            Self::MirrorVertical => IVec2::new(vector.x, -vector.y),
+        }
+    }
+
+    pub fn point(self, point: Vec2) -> Vec2 {
+        match self {
+            Self::Rotate(relative) => relative.transform() * point,
+            Self::MirrorHorizontal => Vec2::new(-point.x, point.y),
+            Self::MirrorVertical => Vec2::new(point.x, -point.y),
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Exporting the logical graph of a circuit in the DOT language of Graphviz.
+//!
+//! Each cluster is a node and each flip, flop and switch is an edge from its input cluster to
+//! its output cluster, so the graph is what the simulation sees rather than how the circuit is
+//! laid out. Nodes are named after the tile of the cluster that is nearest the top left, and
+//! the labels on any of its tiles. Flips, which invert, are drawn with a circle at the head.
+
+use crate::circuit::{Circuit, ComponentType};
+use crate::schematic::Anchor;
+use crate::simulation::Snapshot;
+use glam::IVec2;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+const POWERED_COLOR: &str = "#66dd66";
+
+pub struct ClusterGraph {
+    nodes: BTreeMap<u32, Node>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Default)]
+struct Node {
+    // Every tile of the cluster, from top to bottom and left to right.
+    tiles: Vec<IVec2>,
+    labels: Vec<String>,
+    // For clusters without tiles of their own.
+    note: Option<String>,
+}
+
+struct Edge {
+    input: u32,
+    output: u32,
+    kind: Kind,
+    // Where the components are, if they aren't inside chips.
+    positions: Vec<IVec2>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Flip,
+    Flop,
+    Switch,
+}
+
+impl ClusterGraph {
+    /// Reads the graph out of the simulation of `circuit`.
+    pub fn new(circuit: &Circuit) -> Self {
+        let mut nodes: BTreeMap<u32, Node> = BTreeMap::new();
+        let mut components: BTreeMap<(u32, u32, Kind), Vec<IVec2>> = BTreeMap::new();
+        for (position, cluster) in circuit.cluster_tiles() {
+            nodes.entry(cluster).or_default().tiles.push(position);
+            let kind = match circuit.component_at(position) {
+                Some(ComponentType::Flip) => Kind::Flip,
+                Some(ComponentType::Flop) => Kind::Flop,
+                Some(ComponentType::Switch) => Kind::Switch,
+                _ => continue,
+            };
+            if let Some((input, output)) = circuit.component_clusters(position) {
+                components
+                    .entry((input, output, kind))
+                    .or_default()
+                    .push(position);
+                if kind == Kind::Switch {
+                    let node = nodes.entry(input).or_default();
+                    node.note = Some(format!("input of switch {}", format_position(position)));
+                }
+            }
+        }
+        for label in circuit.labels() {
+            if let Anchor::Tile(position) = label.anchor {
+                if let Some(cluster) = circuit.tile_cluster(position) {
+                    let node = nodes.entry(cluster).or_default();
+                    node.labels.push(label.text.clone());
+                }
+            }
+        }
+
+        // The simulation has every flip and flop, even those inside chips. A switch is a flop
+        // in it.
+        let simulation = circuit.simulation();
+        let flips = simulation.flips().map(|clusters| (clusters, Kind::Flip));
+        let flops = simulation.flops().map(|clusters| (clusters, Kind::Flop));
+        let mut edges: Vec<Edge> = flips
+            .chain(flops)
+            .map(|((input, output), kind)| {
+                let switch = components.get(&(input, output, Kind::Switch));
+                let (kind, positions) = match switch {
+                    Some(positions) if kind == Kind::Flop => (Kind::Switch, positions.clone()),
+                    _ => {
+                        let positions = components.get(&(input, output, kind));
+                        (kind, positions.cloned().unwrap_or_default())
+                    }
+                };
+                Edge {
+                    input,
+                    output,
+                    kind,
+                    positions,
+                }
+            })
+            .collect();
+        edges.sort_by_key(|edge| (edge.input, edge.output, edge.kind));
+        for edge in &edges {
+            nodes.entry(edge.input).or_default();
+            nodes.entry(edge.output).or_default();
+        }
+
+        Self { nodes, edges }
+    }
+
+    /// Writes the graph as a `digraph` named `name`, and flushes `writer`. With a `snapshot`,
+    /// powered clusters are filled in.
+    pub fn write_dot<W: Write>(
+        &self,
+        mut writer: W,
+        name: &str,
+        snapshot: Option<&Snapshot>,
+    ) -> io::Result<()> {
+        writeln!(
+            writer,
+            "// Exported from FlipFlop {}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(writer, "digraph {} {{", quote(name))?;
+        writeln!(writer, "  rankdir=LR;")?;
+        writeln!(writer, "  node [shape=box, fontname=monospace];")?;
+        writeln!(writer, "  edge [fontname=monospace, fontsize=10];")?;
+
+        for (&cluster, node) in &self.nodes {
+            let mut lines = vec![format!("cluster {}", cluster)];
+            match node.tiles.first() {
+                Some(&position) => lines.push(format_position(position)),
+                None => lines.extend(node.note.clone()),
+            }
+            lines.extend(node.labels.iter().cloned());
+            let mut attributes = vec![format!("label={}", quote(&lines.join("\n")))];
+            if !node.tiles.is_empty() {
+                let tiles: Vec<String> = node.tiles.iter().map(|&p| format_position(p)).collect();
+                attributes.push(format!("tooltip={}", quote(&tiles.join(" "))));
+            }
+            if let Some(snapshot) = snapshot {
+                let powered = cluster < snapshot.num_clusters() && snapshot.is_powered(cluster);
+                if powered {
+                    attributes.push(format!("style=filled, fillcolor={}", quote(POWERED_COLOR)));
+                }
+            }
+            writeln!(writer, "  c{} [{}];", cluster, attributes.join(", "))?;
+        }
+
+        for edge in &self.edges {
+            let kind = match edge.kind {
+                Kind::Flip => "flip",
+                Kind::Flop => "flop",
+                Kind::Switch => "switch",
+            };
+            let label = match &edge.positions[..] {
+                [] => format!("{} in chip", kind),
+                positions => {
+                    let positions: Vec<String> =
+                        positions.iter().map(|&p| format_position(p)).collect();
+                    format!("{} {}", kind, positions.join(" "))
+                }
+            };
+            let mut attributes = vec![format!("label={}", quote(&label))];
+            match edge.kind {
+                Kind::Flip => attributes.push("arrowhead=odot".to_owned()),
+                Kind::Flop => {}
+                Kind::Switch => attributes.push("style=bold".to_owned()),
+            }
+            writeln!(
+                writer,
+                "  c{} -> c{} [{}];",
+                edge.input,
+                edge.output,
+                attributes.join(", ")
+            )?;
+        }
+        writeln!(writer, "}}")?;
+        writer.flush()
+    }
+}
+
+fn format_position(position: IVec2) -> String {
+    format!("({}, {})", position.x, position.y)
+}
+
+/// A DOT string, in quotes.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClusterGraph;
+    use crate::circuit::tests::{place_chip_instance, place_inverter};
+    use crate::circuit::Circuit;
+    use crate::schematic::Anchor;
+    use crate::simulation::Snapshot;
+    use glam::IVec2;
+
+    fn dot(circuit: &Circuit) -> String {
+        let mut dot = Vec::new();
+        ClusterGraph::new(circuit)
+            .write_dot(&mut dot, "circuit", None)
+            .unwrap();
+        String::from_utf8(dot).unwrap()
+    }
+
+    #[test]
+    fn writes_graph() {
+        let mut circuit = Circuit::new();
+        place_inverter(&mut circuit, 0);
+        circuit.set_label(Anchor::Tile(IVec2::new(4, 0)), "say \"hi\"");
+        circuit.run_until_stable(10);
+
+        let switch = circuit.tile_cluster(IVec2::new(0, 0)).unwrap();
+        let lamp = circuit.tile_cluster(IVec2::new(4, 0)).unwrap();
+        let input = circuit.switch_input_cluster(IVec2::new(0, 0)).unwrap();
+        let mut snapshot = Snapshot::default();
+        circuit.simulation().write_snapshot(&mut snapshot);
+
+        let mut dot = Vec::new();
+        ClusterGraph::new(&circuit)
+            .write_dot(&mut dot, "my circuit", Some(&snapshot))
+            .unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains("digraph \"my circuit\" {"));
+        assert!(dot.contains(&format!(
+            "c{} -> c{} [label=\"switch (0, 0)\", style=bold];",
+            input, switch
+        )));
+        assert!(dot.contains(&format!(
+            "c{} -> c{} [label=\"flip (2, 0)\", arrowhead=odot];",
+            switch, lamp
+        )));
+        // The lamp is on, since the switch is off.
+        assert!(dot.contains(&format!(
+            "c{} [label=\"cluster {}\\n(2, 0)\\nsay \\\"hi\\\"\", tooltip=\"(2, 0) (3, 0) (4, 0)\", \
+             style=filled, fillcolor=\"#66dd66\"];",
+            lamp, lamp
+        )));
+        assert!(dot.contains(&format!(
+            "c{} [label=\"cluster {}\\ninput of switch (0, 0)\"];",
+            input, input
+        )));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn writes_empty_graph() {
+        let dot = dot(&Circuit::new());
+        assert!(dot.contains("digraph \"circuit\" {"));
+        assert!(!dot.contains(" c"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn writes_chips() {
+        let mut circuit = Circuit::new();
+        place_inverter(&mut circuit, 0);
+        let id = circuit
+            .define_chip(IVec2::new(0, 0), IVec2::new(4, 0))
+            .unwrap();
+        place_chip_instance(&mut circuit, id);
+
+        let dot = dot(&circuit);
+        assert_eq!(dot.matches(" -> ").count(), 4);
+        assert_eq!(dot.matches("[label=\"switch ").count(), 2);
+        assert!(dot.contains("[label=\"flip (2, 0)\", arrowhead=odot];"));
+        // The chip's flip joins the clusters of the switch and lamp around it.
+        let switch = circuit.tile_cluster(IVec2::new(-2, 10)).unwrap();
+        let lamp = circuit.tile_cluster(IVec2::new(2, 10)).unwrap();
+        assert!(dot.contains(&format!(
+            "c{} -> c{} [label=\"flip in chip\", arrowhead=odot];",
+            switch, lamp
+        )));
+    }
+}
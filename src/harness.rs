@@ -0,0 +1,122 @@
+//! Driving a circuit without a window: toggling its switches and reading its lamps, for truth
+//! tables and test vectors.
+
+use crate::circuit::{Circuit, ComponentType, Port};
+use anyhow::{ensure, Context};
+use glam::IVec2;
+
+/// A copy of a circuit with some of its switches as inputs and some of its lamps as outputs.
+///
+/// Switches are toggled just as if they were clicked. The copy starts out as the circuit was
+/// saved, with every wire unpowered, and the circuit itself is left alone.
+pub struct Harness {
+    circuit: Circuit,
+    switches: Vec<IVec2>,
+    lamps: Vec<u32>,
+}
+
+impl Harness {
+    pub fn new(circuit: &Circuit, inputs: &[Port], outputs: &[Port]) -> anyhow::Result<Self> {
+        for port in inputs {
+            ensure!(
+                circuit.component_at(port.position) == Some(ComponentType::Switch),
+                "input `{}` at {} is not a switch",
+                port.name,
+                port.position
+            );
+        }
+        // Clusters are numbered anew in the copy, so the lamps are looked up in it.
+        let circuit = circuit.duplicate()?;
+        let lamps = outputs
+            .iter()
+            .map(|port| {
+                ensure!(
+                    circuit.component_at(port.position) == Some(ComponentType::Lamp),
+                    "output `{}` at {} is not a lamp",
+                    port.name,
+                    port.position
+                );
+                circuit
+                    .tile_cluster(port.position)
+                    .with_context(|| format!("output `{}` has no cluster", port.name))
+            })
+            .collect::<anyhow::Result<Vec<u32>>>()?;
+        Ok(Self {
+            circuit,
+            switches: inputs.iter().map(|port| port.position).collect(),
+            lamps,
+        })
+    }
+
+    /// Toggles each input that isn't the value given for it. Inputs without a value are left
+    /// as they are.
+    pub fn set_inputs<I>(&mut self, values: I)
+    where
+        I: IntoIterator<Item = Option<bool>>,
+    {
+        for (&position, value) in self.switches.iter().zip(values) {
+            if value.is_some_and(|value| self.circuit.is_switched(position) != Some(value)) {
+                self.circuit.interact(position);
+            }
+        }
+    }
+
+    pub fn tick(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.circuit.tick();
+        }
+    }
+
+    /// See [`Circuit::run_until_stable`].
+    pub fn run_until_stable(&mut self, max_ticks: u64) -> (u64, bool) {
+        self.circuit.run_until_stable(max_ticks)
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.circuit.simulation().tick_count()
+    }
+
+    /// Whether each output is lit.
+    pub fn outputs(&self) -> Vec<bool> {
+        let simulation = self.circuit.simulation();
+        self.lamps
+            .iter()
+            .map(|&id| simulation.is_powered(id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Harness;
+    use crate::circuit::tests::place_inverter;
+    use crate::circuit::Circuit;
+    use glam::IVec2;
+
+    #[test]
+    fn drives_a_copy() {
+        let mut circuit = Circuit::new();
+        place_inverter(&mut circuit, 0);
+        let ports = circuit.ports();
+        let mut harness = Harness::new(&circuit, &ports.inputs, &ports.outputs).unwrap();
+        harness.run_until_stable(10);
+        assert_eq!(harness.outputs(), [true]);
+
+        harness.set_inputs([Some(true)]);
+        harness.tick(2);
+        assert_eq!(harness.outputs(), [true]);
+        harness.tick(1);
+        assert_eq!(harness.outputs(), [false]);
+        // Leaving the input out, or giving it the value it has, doesn't toggle it.
+        harness.set_inputs([None]);
+        harness.set_inputs([Some(true)]);
+        assert_eq!(harness.run_until_stable(10), (0, true));
+        assert_eq!(harness.tick_count(), 4);
+
+        assert_eq!(circuit.is_switched(IVec2::new(0, 0)), Some(false));
+        assert_eq!(circuit.simulation().tick_count(), 0);
+        // The ports have to be a switch and a lamp.
+        assert!(Harness::new(&circuit, &ports.outputs, &[]).is_err());
+        assert!(Harness::new(&circuit, &[], &ports.inputs).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! Reads the first model of a BLIF file.
+
+use super::{Cover, Gate, GateNetlist, Register};
+use anyhow::{bail, ensure, Context};
+use std::collections::HashMap;
+
+pub(super) fn read(text: &str) -> anyhow::Result<GateNetlist> {
+    let mut reader = Reader {
+        netlist: GateNetlist::default(),
+        nets: HashMap::new(),
+    };
+    // The cover that is being read, with the line it started on.
+    let mut names: Option<(usize, Vec<String>, Vec<String>)> = None;
+
+    for (number, line) in logical_lines(text) {
+        let result = (|| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some(&first) = words.first() else {
+                return Ok(false);
+            };
+            if !first.starts_with('.') {
+                let Some((_, _, rows)) = &mut names else {
+                    bail!("unexpected `{}`", first);
+                };
+                rows.push(line.clone());
+                return Ok(false);
+            }
+            if let Some((start, signals, rows)) = names.take() {
+                reader
+                    .names(&signals, &rows)
+                    .with_context(|| format!("in the cover on line {}", start))?;
+            }
+            let arguments = words[1..].iter().map(|&word| word.to_owned());
+            match first {
+                ".model" => {}
+                ".inputs" => {
+                    for name in arguments {
+                        let net = reader.net(&name);
+                        reader.netlist.inputs.push((name, net));
+                    }
+                }
+                ".outputs" => {
+                    for name in arguments {
+                        let net = reader.net(&name);
+                        reader.netlist.outputs.push((name, net));
+                    }
+                }
+                ".names" => {
+                    ensure!(words.len() >= 2, "`.names` needs an output");
+                    names = Some((number, arguments.collect(), Vec::new()));
+                }
+                ".latch" => reader.latch(&words[1..])?,
+                ".end" => return Ok(true),
+                ".clock" | ".area" | ".delay" | ".wire_load_slope" | ".default_input_arrival" => {}
+                _ => bail!("unsupported `{}`", first),
+            }
+            Ok(false)
+        })();
+        match result.with_context(|| format!("on line {}", number))? {
+            true => break,
+            false => continue,
+        }
+    }
+    if let Some((start, signals, rows)) = names {
+        reader
+            .names(&signals, &rows)
+            .with_context(|| format!("in the cover on line {}", start))?;
+    }
+
+    let mut netlist = reader.netlist;
+    netlist.finish()?;
+    Ok(netlist)
+}
+
+/// Joins lines that end in `\` and drops comments, numbering each line from 1 by where it
+/// starts.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let (line, continued) = match line.trim_end().strip_suffix('\\') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let (_, joined) = current.get_or_insert_with(|| (index + 1, String::new()));
+        joined.push_str(line);
+        joined.push(' ');
+        if !continued {
+            lines.extend(current.take());
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+struct Reader {
+    netlist: GateNetlist,
+    nets: HashMap<String, usize>,
+}
+
+impl Reader {
+    fn net(&mut self, name: &str) -> usize {
+        if let Some(&net) = self.nets.get(name) {
+            return net;
+        }
+        let net = self.netlist.new_net();
+        self.nets.insert(name.to_owned(), net);
+        net
+    }
+
+    fn names(&mut self, signals: &[String], rows: &[String]) -> anyhow::Result<()> {
+        let (output, inputs) = signals.split_last().context("no output")?;
+        let mut cubes = Vec::new();
+        let mut value = None;
+        for row in rows {
+            let words: Vec<&str> = row.split_whitespace().collect();
+            // A cover without inputs has rows of just the output value.
+            let (cube, row_value) = match words[..] {
+                [cube, row_value] if !inputs.is_empty() => (cube, row_value),
+                [row_value] if inputs.is_empty() => ("", row_value),
+                _ => bail!("invalid row `{}`", row.trim()),
+            };
+            ensure!(
+                cube.len() == inputs.len(),
+                "row `{}` does not match the inputs",
+                row.trim()
+            );
+            let row_value = match row_value {
+                "0" => false,
+                "1" => true,
+                _ => bail!("invalid output value `{}`", row_value),
+            };
+            ensure!(
+                value.is_none_or(|value| value == row_value),
+                "rows must all have the same output value"
+            );
+            value = Some(row_value);
+            cubes.push(cube);
+        }
+        // Without any rows, the output is always 0.
+        let cover = Cover::parse(&cubes, value.unwrap_or(true))?;
+        let inputs = inputs.iter().map(|name| self.net(name)).collect();
+        let output = self.net(output);
+        self.netlist.gates.push(Gate {
+            inputs,
+            cover,
+            output,
+        });
+        Ok(())
+    }
+
+    /// `.latch <input> <output> [<type> <control>] [<init>]`
+    fn latch(&mut self, arguments: &[&str]) -> anyhow::Result<()> {
+        let (signals, rest) = match arguments {
+            [d, q, rest @ ..] => ([*d, *q], rest),
+            _ => bail!("`.latch` needs an input and an output"),
+        };
+        let (control, init) = match rest {
+            [] => (None, None),
+            [init] => (None, Some(*init)),
+            [kind, control] => (Some((*kind, *control)), None),
+            [kind, control, init] => (Some((*kind, *control)), Some(*init)),
+            _ => bail!("too many arguments to `.latch`"),
+        };
+        ensure!(!matches!(init, Some("1")), "registers can only start at 0");
+        let clock = match control {
+            None | Some((_, "NIL")) => None,
+            Some(("re", control)) => Some(self.net(control)),
+            Some((kind, _)) => bail!("unsupported latch type `{}`; only `re` is", kind),
+        };
+        let [d, q] = signals.map(|name| self.net(name));
+        self.netlist.registers.push(Register { d, q, clock });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read;
+
+    #[test]
+    fn reads_covers_and_latches() {
+        let netlist = read(
+            "# A toggle with a carry.\n\
+             .model toggle\n\
+             .inputs clk en\n\
+             .outputs q \\\n carry\n\
+             .names en q next\n\
+             10 1\n\
+             01 1\n\
+             .names en q carry\n\
+             11 1\n\
+             .latch next q re clk 0\n\
+             .end\n",
+        )
+        .unwrap();
+        // The clock is not an input of the logic.
+        let inputs: Vec<&str> = netlist.inputs.iter().map(|(name, _)| &**name).collect();
+        assert_eq!(inputs, ["en"]);
+        assert_eq!(netlist.outputs.len(), 2);
+        assert_eq!(netlist.gates.len(), 2);
+        assert_eq!(netlist.registers.len(), 1);
+        assert_eq!(
+            netlist.evaluate(&[true], &[true]),
+            (vec![true, true], vec![false])
+        );
+        assert_eq!(
+            netlist.evaluate(&[false], &[true]),
+            (vec![false, false], vec![true])
+        );
+
+        assert!(read(".model m\n.outputs y\n.end\n").is_err());
+        assert!(read(
+            ".model m\n.inputs c a\n.outputs y z\n.names c a z\n11 1\n.latch a y re c 0\n"
+        )
+        .is_err());
+        assert!(read(".model m\n.inputs a\n.outputs y\n.names a y\n1 1\n0 0\n").is_err());
+    }
+}
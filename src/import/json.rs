@@ -0,0 +1,268 @@
+//! Just enough of a JSON parser to read the netlists that Yosys writes.
+
+use anyhow::{anyhow, bail, ensure, Context};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in the order they were written.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member called `key`, if this is an object that has one.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse(text: &str) -> anyhow::Result<Value> {
+    let mut parser = Parser {
+        text,
+        position: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    ensure!(
+        parser.position == text.len(),
+        "unexpected text after the end at byte {}",
+        parser.position
+    );
+    Ok(value)
+}
+
+// Deeper nesting than this is rejected rather than overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> anyhow::Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => self.string().map(Value::String),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => bail!("unexpected `{}` at byte {}", c, self.position),
+            None => bail!("unexpected end of input"),
+        }
+    }
+
+    fn nested<F>(&mut self, f: F) -> anyhow::Result<Value>
+    where
+        F: FnOnce(&mut Self) -> anyhow::Result<Value>,
+    {
+        self.depth += 1;
+        ensure!(self.depth <= MAX_DEPTH, "nested too deeply");
+        let value = f(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> anyhow::Result<Value> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            members.push((key, value));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Object(members)),
+                _ => bail!("expected `,` or `}}` at byte {}", self.position),
+            }
+        }
+    }
+
+    fn array(&mut self) -> anyhow::Result<Value> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(values)),
+                _ => bail!("expected `,` or `]` at byte {}", self.position),
+            }
+        }
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next().context("unterminated string")? {
+                '"' => return Ok(string),
+                '\\' => {
+                    let c = match self.next().context("unterminated string")? {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        other => bail!("unknown escape `\\{}`", other),
+                    };
+                    string.push(c);
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> anyhow::Result<char> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).context("invalid \\u escape");
+        }
+        // A surrogate pair.
+        ensure!(
+            self.next() == Some('\\') && self.next() == Some('u'),
+            "unpaired surrogate in \\u escape"
+        );
+        let low = self.hex4()?;
+        ensure!((0xdc00..0xe000).contains(&low), "invalid surrogate pair");
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+            .context("invalid \\u escape")
+    }
+
+    fn hex4(&mut self) -> anyhow::Result<u32> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .context("unterminated \\u escape")?;
+        self.position += 4;
+        u32::from_str_radix(digits, 16).map_err(|_| anyhow!("invalid \\u escape"))
+    }
+
+    fn number(&mut self) -> anyhow::Result<Value> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || "+-.eE".contains(c) {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+        let text = &self.text[start..self.position];
+        let number = text
+            .parse()
+            .with_context(|| format!("invalid number `{}`", text))?;
+        Ok(Value::Number(number))
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> anyhow::Result<Value> {
+        ensure!(
+            self.text[self.position..].starts_with(keyword),
+            "unexpected text at byte {}",
+            self.position
+        );
+        self.position += keyword.len();
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> anyhow::Result<()> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => bail!("expected `{}` at byte {}", expected, self.position),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Value};
+
+    #[test]
+    fn parses_values() {
+        let value = parse(r#" {"a": [1, -2.5e1, true, null], "bé\n": {"c": "d\"e"}} "#).unwrap();
+        assert_eq!(
+            value.get("a"),
+            Some(&Value::Array(vec![
+                Value::Number(1.0),
+                Value::Number(-25.0),
+                Value::Bool(true),
+                Value::Null,
+            ]))
+        );
+        let inner = value.get("b\u{e9}\n").unwrap();
+        assert_eq!(inner.get("c").and_then(Value::as_str), Some("d\"e"));
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("{} {}").is_err());
+        assert!(parse(&"[".repeat(1000)).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! Places a [`Network`] on the grid.
+//!
+//! Clusters are laid out in columns by how far they are from the inputs. Each cluster gets a
+//! vertical wire of its own, and each flip or flop a horizontal row from the vertical wire of
+//! its input to the one of its output, passing over anything in between. Crossing wires don't
+//! connect, so nothing has to be routed around; rows that don't overlap share a line.
+//!
+//! Flips and flops face east, in a column just before the layer of their output, except in
+//! loops: those that lead back to an earlier layer face west, just after it.
+
+use super::synthesis::{Network, Node};
+use crate::circuit::{Circuit, ComponentType};
+use crate::direction::Direction;
+use crate::schematic::Anchor;
+use anyhow::{ensure, Context};
+use glam::IVec2;
+
+// The column of the switches, and the empty columns around each column of components.
+const SWITCH_X: i32 = 0;
+const GAP: i32 = 1;
+
+struct Edge {
+    source: usize,
+    target: usize,
+    inverted: bool,
+}
+
+enum Item {
+    Switch(usize),
+    Lamp(usize),
+    Edge(usize),
+}
+
+/// Something that takes up a stretch of a row, from `min` to `max` inclusive.
+struct Row {
+    item: Item,
+    min: i32,
+    max: i32,
+}
+
+pub(super) fn place(network: &Network, circuit: &mut Circuit) -> anyhow::Result<()> {
+    let mut edges = Vec::new();
+    for (target, node) in network.nodes.iter().enumerate() {
+        if let Node::Or(literals) = node {
+            for literal in literals {
+                ensure!(literal.node != target, "a cluster cannot drive itself");
+                edges.push(Edge {
+                    source: literal.node,
+                    target,
+                    inverted: literal.inverted,
+                });
+            }
+        }
+    }
+    let backward = backward_edges(network.nodes.len(), &edges);
+    let layers = layers(network.nodes.len(), &edges, &backward);
+
+    // Only clusters that connect to something are placed.
+    let mut used = vec![false; network.nodes.len()];
+    for edge in &edges {
+        used[edge.source] = true;
+        used[edge.target] = true;
+    }
+    let ports = network.inputs.iter().chain(&network.outputs);
+    for &(_, node) in ports {
+        used[node] = true;
+    }
+
+    // The columns of the clusters, layer by layer, each followed by a column for the
+    // components that go into the next layer.
+    let num_layers = layers.iter().max().map_or(0, |&max| max + 1);
+    let mut column = vec![0; network.nodes.len()];
+    let mut component_column = vec![0; num_layers];
+    let mut x = SWITCH_X + GAP + 1;
+    for (layer, component_x) in component_column.iter_mut().enumerate() {
+        for node in 0..network.nodes.len() {
+            if used[node] && layers[node] == layer {
+                column[node] = x;
+                x += 1;
+            }
+        }
+        *component_x = x + GAP;
+        x += 2 * GAP + 1;
+    }
+    // Nothing goes into the last layer's components' column.
+    let lamp_x = component_column.last().copied().unwrap_or(x);
+
+    let mut rows: Vec<Row> = Vec::new();
+    for &(_, node) in &network.inputs {
+        rows.push(Row {
+            item: Item::Switch(node),
+            min: SWITCH_X,
+            max: column[node],
+        });
+    }
+    for (index, edge) in edges.iter().enumerate() {
+        let (source, target) = (column[edge.source], column[edge.target]);
+        rows.push(Row {
+            item: Item::Edge(index),
+            min: source.min(target),
+            max: source.max(target),
+        });
+    }
+    for &(_, node) in &network.outputs {
+        rows.push(Row {
+            item: Item::Lamp(node),
+            min: column[node],
+            max: lamp_x,
+        });
+    }
+    let lines = pack(&rows);
+    // Just before the target's layer, or just after it for the edges that go back.
+    let component_x = |index: usize| {
+        let layer = layers[edges[index].target];
+        match backward[index] {
+            true => component_column[layer],
+            false => component_column[layer - 1],
+        }
+    };
+
+    // Components first, so that wires are split where they meet them.
+    for (row, &line) in rows.iter().zip(&lines) {
+        let (ty, x, orientation) = match row.item {
+            Item::Switch(..) => (ComponentType::Switch, SWITCH_X, Direction::East),
+            Item::Lamp(..) => (ComponentType::Lamp, lamp_x, Direction::East),
+            Item::Edge(index) => {
+                let edge = &edges[index];
+                let ty = match edge.inverted {
+                    true => ComponentType::Flip,
+                    false => ComponentType::Flop,
+                };
+                let orientation = match backward[index] {
+                    true => Direction::West,
+                    false => Direction::East,
+                };
+                (ty, component_x(index), orientation)
+            }
+        };
+        let position = IVec2::new(x, line);
+        ensure!(
+            circuit.place_component(ty, position, orientation),
+            "cannot place a component at {}",
+            position
+        );
+    }
+
+    // The lines that each cluster's column has to reach.
+    let mut extents: Vec<Option<(i32, i32)>> = vec![None; network.nodes.len()];
+    let mut reach = |node: usize, line: i32| {
+        let extent = extents[node].get_or_insert((line, line));
+        *extent = (extent.0.min(line), extent.1.max(line));
+    };
+    for (row, &line) in rows.iter().zip(&lines) {
+        let segments = match row.item {
+            Item::Switch(node) => {
+                reach(node, line);
+                vec![(SWITCH_X, column[node])]
+            }
+            Item::Lamp(node) => {
+                reach(node, line);
+                vec![(column[node], lamp_x)]
+            }
+            Item::Edge(index) => {
+                let edge = &edges[index];
+                reach(edge.source, line);
+                reach(edge.target, line);
+                let x = component_x(index);
+                vec![(column[edge.source], x), (x, column[edge.target])]
+            }
+        };
+        for (start, end) in segments {
+            place_wire(circuit, IVec2::new(start, line), IVec2::new(end, line))?;
+        }
+    }
+    for (node, extent) in extents.iter().enumerate() {
+        if let Some((bottom, top)) = *extent {
+            if bottom < top {
+                let x = column[node];
+                place_wire(circuit, IVec2::new(x, top), IVec2::new(x, bottom))?;
+            }
+        }
+    }
+
+    label_ports(network, &rows, &lines, lamp_x, circuit)
+}
+
+fn place_wire(circuit: &mut Circuit, start: IVec2, end: IVec2) -> anyhow::Result<()> {
+    ensure!(
+        circuit.place_wire(start, end),
+        "cannot place a wire from {} to {}",
+        start,
+        end
+    );
+    Ok(())
+}
+
+/// Names each switch and lamp after its port.
+fn label_ports(
+    network: &Network,
+    rows: &[Row],
+    lines: &[i32],
+    lamp_x: i32,
+    circuit: &mut Circuit,
+) -> anyhow::Result<()> {
+    let mut inputs = network.inputs.iter();
+    let mut outputs = network.outputs.iter();
+    for (row, &line) in rows.iter().zip(lines) {
+        let (port, x) = match row.item {
+            Item::Switch(..) => (inputs.next(), SWITCH_X),
+            Item::Lamp(..) => (outputs.next(), lamp_x),
+            Item::Edge(..) => continue,
+        };
+        let (name, _) = port.context("more switches and lamps than ports")?;
+        circuit.set_label(Anchor::Tile(IVec2::new(x, line)), name);
+    }
+    Ok(())
+}
+
+/// Finds edges to leave out so that the rest have no loops, by searching depth first.
+fn backward_edges(num_nodes: usize, edges: &[Edge]) -> Vec<bool> {
+    let mut outgoing = vec![Vec::new(); num_nodes];
+    for (index, edge) in edges.iter().enumerate() {
+        outgoing[edge.source].push(index);
+    }
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        Open,
+        Done,
+    }
+    let mut state = vec![State::New; num_nodes];
+    let mut backward = vec![false; edges.len()];
+    for start in 0..num_nodes {
+        if state[start] != State::New {
+            continue;
+        }
+        state[start] = State::Open;
+        let mut stack = vec![(start, 0)];
+        while let Some((node, next)) = stack.last_mut() {
+            let Some(&edge) = outgoing[*node].get(*next) else {
+                state[*node] = State::Done;
+                stack.pop();
+                continue;
+            };
+            *next += 1;
+            let target = edges[edge].target;
+            match state[target] {
+                State::New => {
+                    state[target] = State::Open;
+                    stack.push((target, 0));
+                }
+                State::Open => backward[edge] = true,
+                State::Done => {}
+            }
+        }
+    }
+    backward
+}
+
+/// The layer of each node: the longest path to it from a node without inputs, leaving out the
+/// backward edges.
+fn layers(num_nodes: usize, edges: &[Edge], backward: &[bool]) -> Vec<usize> {
+    let forward = || {
+        edges
+            .iter()
+            .zip(backward)
+            .filter(|(_, &backward)| !backward)
+            .map(|(edge, _)| edge)
+    };
+    let mut outgoing = vec![Vec::new(); num_nodes];
+    let mut incoming = vec![0; num_nodes];
+    for edge in forward() {
+        outgoing[edge.source].push(edge.target);
+        incoming[edge.target] += 1;
+    }
+    let mut layers = vec![0; num_nodes];
+    let mut ready: Vec<usize> = (0..num_nodes).filter(|&node| incoming[node] == 0).collect();
+    while let Some(node) = ready.pop() {
+        for &target in &outgoing[node] {
+            layers[target] = layers[target].max(layers[node] + 1);
+            incoming[target] -= 1;
+            if incoming[target] == 0 {
+                ready.push(target);
+            }
+        }
+    }
+    layers
+}
+
+/// Assigns each row a line, top to bottom, so that the rows on a line don't overlap. Rows are
+/// taken from left to right, each on the first line with room for it.
+fn pack(rows: &[Row]) -> Vec<i32> {
+    let mut order: Vec<usize> = (0..rows.len()).collect();
+    order.sort_by_key(|&index| rows[index].min);
+    // The rightmost column taken on each line so far.
+    let mut ends: Vec<i32> = Vec::new();
+    let mut lines = vec![0; rows.len()];
+    for index in order {
+        let row = &rows[index];
+        let line = match ends.iter().position(|&end| end < row.min) {
+            Some(line) => line,
+            None => {
+                ends.push(0);
+                ends.len() - 1
+            }
+        };
+        ends[line] = row.max;
+        lines[index] = -(line as i32);
+    }
+    lines
+}
@@ -0,0 +1,467 @@
+//! Builds circuits out of gate-level netlists from other tools: the JSON that Yosys writes with
+//! `write_json`, or BLIF.
+//!
+//! The netlist must be made of single-bit gates (`$_AND_`, `$_MUX_`, ... or BLIF `.names`)
+//! and positive-edge flip-flops on one clock (`$_DFF_P_`, `$_DFFE_PP_`, `$_DFFE_PN_` or BLIF
+//! `.latch`), such as what `synth; abc -g simple,MUX` leaves. Every gate becomes one or two
+//! levels of flips and flops feeding a wired-OR, and the result is placed on a grid through
+//! [`Circuit::place_component`] and [`Circuit::place_wire`]. Inputs become labelled switches and
+//! outputs labelled lamps.
+//!
+//! Logic takes time here, one tick per level, so the imported circuit is slower than the
+//! netlist by a number of ticks, given by its [`Timing`]:
+//!
+//! - Outputs settle `depth` ticks after the inputs or registers change, plus the two ticks that a
+//!   switch takes.
+//! - Flip-flops are built as latches that load on a pulse from a clock generator that is built
+//!   along with them, once every `period` ticks. Its period is chosen so that the logic settles
+//!   well within a cycle.
+//! - Registers are held at 0 for the first `reset` ticks after the circuit is built. The first
+//!   clock edge is `period` ticks after that, and the registers take their new values then.
+//! - Inputs must change at least `setup` ticks before an edge to be seen at it. Changing them
+//!   right after an edge is always safe.
+//!
+//! So each cycle of the netlist, with the registers starting at 0, is one `period` of the
+//! circuit.
+
+mod blif;
+mod json;
+mod layout;
+mod synthesis;
+mod yosys;
+
+use crate::circuit::Circuit;
+use crate::schematic::Schematic;
+use anyhow::{bail, ensure, Context};
+use std::path::Path;
+
+/// The kinds of netlists that can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    YosysJson,
+    Blif,
+}
+
+impl Format {
+    /// Guesses the format from the extension of a file name.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::YosysJson),
+            "blif" => Some(Format::Blif),
+            _ => None,
+        }
+    }
+}
+
+pub struct Imported {
+    pub schematic: Schematic,
+    pub timing: Timing,
+}
+
+/// How the imported circuit lags behind the netlist, in ticks. See the [module
+/// documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub depth: u64,
+    pub clock: Option<Clock>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    pub period: u64,
+    pub reset: u64,
+    pub setup: u64,
+}
+
+impl Timing {
+    /// A one-line summary for the status bar.
+    pub fn describe(&self) -> String {
+        match self.clock {
+            Some(clock) => format!(
+                "clock period {} ticks, first edge at tick {}, outputs settle {} ticks after an edge",
+                clock.period,
+                clock.reset + clock.period,
+                self.depth,
+            ),
+            None => format!(
+                "outputs settle {} ticks after a switch is flipped",
+                self.depth + 2
+            ),
+        }
+    }
+}
+
+/// Reads a netlist and lays it out as a schematic, ready to be pasted into a circuit.
+pub fn import(text: &str, format: Format) -> anyhow::Result<Imported> {
+    let netlist = match format {
+        Format::YosysJson => yosys::read(text)?,
+        Format::Blif => blif::read(text)?,
+    };
+    let (network, timing) = synthesis::synthesize(&netlist)?;
+    let mut circuit = Circuit::new();
+    layout::place(&network, &mut circuit)?;
+    Ok(Imported {
+        schematic: circuit.schematic(),
+        timing,
+    })
+}
+
+/// A netlist of single-bit gates and flip-flops, as read from a file. Nets are numbered from 0.
+#[derive(Debug, Default)]
+struct GateNetlist {
+    inputs: Vec<(String, usize)>,
+    outputs: Vec<(String, usize)>,
+    gates: Vec<Gate>,
+    registers: Vec<Register>,
+    num_nets: usize,
+}
+
+#[derive(Debug)]
+struct Gate {
+    inputs: Vec<usize>,
+    cover: Cover,
+    output: usize,
+}
+
+#[derive(Debug)]
+struct Register {
+    d: usize,
+    q: usize,
+    // `None` for the implied global clock of BLIF.
+    clock: Option<usize>,
+}
+
+/// A function given as a list of cubes, like in BLIF: the output is `value` when any cube
+/// matches the inputs, and the opposite otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cover {
+    // For each input, the value it must have for the cube to match, or `None` for any.
+    cubes: Vec<Vec<Option<bool>>>,
+    value: bool,
+}
+
+impl Cover {
+    /// Parses cubes written in BLIF, such as `1-0`.
+    fn parse(cubes: &[&str], value: bool) -> anyhow::Result<Self> {
+        let cubes = cubes
+            .iter()
+            .map(|cube| {
+                cube.chars()
+                    .map(|c| match c {
+                        '0' => Ok(Some(false)),
+                        '1' => Ok(Some(true)),
+                        '-' => Ok(None),
+                        _ => bail!("invalid cube `{}`", cube),
+                    })
+                    .collect()
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { cubes, value })
+    }
+
+    fn constant(value: bool) -> Self {
+        Self {
+            cubes: Vec::new(),
+            value: !value,
+        }
+    }
+
+    #[cfg(test)]
+    fn evaluate(&self, inputs: &[bool]) -> bool {
+        let matched = self.cubes.iter().any(|cube| {
+            cube.iter()
+                .zip(inputs)
+                .all(|(want, &input)| want.is_none_or(|want| want == input))
+        });
+        matched == self.value
+    }
+}
+
+impl GateNetlist {
+    fn new_net(&mut self) -> usize {
+        self.num_nets += 1;
+        self.num_nets - 1
+    }
+
+    /// Checks that every net has exactly one driver, and takes the clock out of the inputs.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let mut drivers = vec![0; self.num_nets];
+        let inputs = self.inputs.iter().map(|&(_, net)| net);
+        let gates = self.gates.iter().map(|gate| gate.output);
+        let registers = self.registers.iter().map(|register| register.q);
+        for net in inputs.chain(gates).chain(registers) {
+            drivers[net] += 1;
+        }
+        if let Some(net) = drivers.iter().position(|&count| count > 1) {
+            bail!("net {} has more than one driver", self.net_name(net));
+        }
+
+        let mut clocks: Vec<usize> = self
+            .registers
+            .iter()
+            .filter_map(|register| register.clock)
+            .collect();
+        clocks.sort_unstable();
+        clocks.dedup();
+        ensure!(clocks.len() <= 1, "only one clock is supported");
+        if let Some(&clock) = clocks.first() {
+            let index = self
+                .inputs
+                .iter()
+                .position(|&(_, net)| net == clock)
+                .with_context(|| {
+                    format!(
+                        "the clock {} must be an input of the module",
+                        self.net_name(clock)
+                    )
+                })?;
+            let used = self.gates.iter().any(|gate| gate.inputs.contains(&clock))
+                || self.registers.iter().any(|register| register.d == clock)
+                || self.outputs.iter().any(|&(_, net)| net == clock);
+            ensure!(
+                !used,
+                "the clock {} is also used as a signal",
+                self.net_name(clock)
+            );
+            self.inputs.remove(index);
+            drivers[clock] = 0;
+        }
+
+        let gates = self.gates.iter().flat_map(|gate| &gate.inputs);
+        let registers = self.registers.iter().map(|register| &register.d);
+        let outputs = self.outputs.iter().map(|(_, net)| net);
+        for &net in gates.chain(registers).chain(outputs) {
+            ensure!(
+                drivers[net] == 1,
+                "net {} has no driver",
+                self.net_name(net)
+            );
+        }
+        Ok(())
+    }
+
+    /// Names a net after a port, if it is on one, for messages.
+    fn net_name(&self, net: usize) -> String {
+        let ports = self.inputs.iter().chain(&self.outputs);
+        match ports.clone().find(|&&(_, n)| n == net) {
+            Some((name, _)) => format!("`{}`", name),
+            None => net.to_string(),
+        }
+    }
+
+    #[cfg(test)]
+    fn evaluate(&self, registers: &[bool], inputs: &[bool]) -> (Vec<bool>, Vec<bool>) {
+        let mut values: Vec<Option<bool>> = vec![None; self.num_nets];
+        for (&(_, net), &value) in self.inputs.iter().zip(inputs) {
+            values[net] = Some(value);
+        }
+        for (register, &value) in self.registers.iter().zip(registers) {
+            values[register.q] = Some(value);
+        }
+        // The gates are in no particular order, so go over them until nothing is left.
+        while self.gates.iter().any(|gate| values[gate.output].is_none()) {
+            for gate in &self.gates {
+                let inputs: Option<Vec<bool>> =
+                    gate.inputs.iter().map(|&net| values[net]).collect();
+                if let Some(inputs) = inputs {
+                    values[gate.output] = Some(gate.cover.evaluate(&inputs));
+                }
+            }
+        }
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|&(_, net)| values[net].unwrap())
+            .collect();
+        let next = self
+            .registers
+            .iter()
+            .map(|register| values[register.d].unwrap())
+            .collect();
+        (outputs, next)
+    }
+}
+
+/// Names the bits of a port `name[0]`, `name[1]`, ..., or just `name` if there is one.
+fn bit_names(name: &str, width: usize) -> Vec<String> {
+    if width == 1 {
+        vec![name.to_owned()]
+    } else {
+        (0..width).map(|bit| format!("{}[{}]", name, bit)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blif, import, yosys, Format, GateNetlist, Timing};
+    use crate::circuit::Circuit;
+    use glam::IVec2;
+
+    const FULL_ADDER: &str = "\
+.model adder
+.inputs a b cin
+.outputs sum cout
+.names a b cin sum
+100 1
+010 1
+001 1
+111 1
+.names a b cin cout
+11- 1
+1-1 1
+-11 1
+.end
+";
+
+    // A two-bit counter that counts while `en` is on, with a constant output.
+    const COUNTER: &str = r#"{
+  "modules": {
+    "counter": {
+      "attributes": { "top": "00000000000000000000000000000001" },
+      "ports": {
+        "clk": { "direction": "input", "bits": [ 2 ] },
+        "en": { "direction": "input", "bits": [ 3 ] },
+        "q": { "direction": "output", "bits": [ 4, 5 ] },
+        "all": { "direction": "output", "bits": [ 6 ] },
+        "one": { "direction": "output", "bits": [ "1" ] }
+      },
+      "cells": {
+        "scope": { "type": "$scopeinfo", "connections": { } },
+        "ff0": {
+          "type": "$_DFFE_PP_",
+          "connections": { "C": [ 2 ], "D": [ 7 ], "E": [ 3 ], "Q": [ 4 ] }
+        },
+        "not0": { "type": "$_NOT_", "connections": { "A": [ 4 ], "Y": [ 7 ] } },
+        "ff1": {
+          "type": "$_DFF_P_",
+          "connections": { "C": [ 2 ], "D": [ 9 ], "Q": [ 5 ] }
+        },
+        "carry": { "type": "$_AND_", "connections": { "A": [ 4 ], "B": [ 3 ], "Y": [ 8 ] } },
+        "xor1": { "type": "$_XOR_", "connections": { "A": [ 5 ], "B": [ 8 ], "Y": [ 9 ] } },
+        "and": { "type": "$_AND_", "connections": { "A": [ 4 ], "B": [ 5 ], "Y": [ 6 ] } }
+      }
+    },
+    "other": { "ports": { }, "cells": { } }
+  }
+}"#;
+
+    struct Harness {
+        circuit: Circuit,
+        switches: Vec<IVec2>,
+        lamps: Vec<IVec2>,
+        inputs: Vec<bool>,
+    }
+
+    impl Harness {
+        fn new(netlist: &GateNetlist, text: &str, format: Format) -> (Self, Timing) {
+            let imported = import(text, format).unwrap();
+            let mut circuit = Circuit::new();
+            circuit.paste(&imported.schematic, IVec2::ZERO).unwrap();
+            // Ports are found by their labels.
+            let ports = circuit.ports();
+            let find = |ports: &[crate::circuit::Port], name: &str| {
+                ports
+                    .iter()
+                    .find(|port| port.name == name)
+                    .unwrap()
+                    .position
+            };
+            let switches = netlist
+                .inputs
+                .iter()
+                .map(|(name, _)| find(&ports.inputs, name))
+                .collect();
+            let lamps = netlist
+                .outputs
+                .iter()
+                .map(|(name, _)| find(&ports.outputs, name))
+                .collect();
+            let harness = Self {
+                circuit,
+                switches,
+                lamps,
+                inputs: vec![false; netlist.inputs.len()],
+            };
+            (harness, imported.timing)
+        }
+
+        fn set_inputs(&mut self, inputs: &[bool]) {
+            for (index, &value) in inputs.iter().enumerate() {
+                if self.inputs[index] != value {
+                    self.circuit.interact(self.switches[index]);
+                    self.inputs[index] = value;
+                }
+            }
+        }
+
+        fn outputs(&self) -> Vec<bool> {
+            self.lamps
+                .iter()
+                .map(|&lamp| {
+                    let cluster = self.circuit.tile_cluster(lamp).unwrap();
+                    self.circuit.simulation().is_powered(cluster)
+                })
+                .collect()
+        }
+
+        fn run_until(&mut self, tick: u64) {
+            while self.circuit.simulation().tick_count() < tick {
+                self.circuit.tick();
+            }
+        }
+    }
+
+    fn bits(value: usize, count: usize) -> Vec<bool> {
+        (0..count).map(|bit| value >> bit & 1 != 0).collect()
+    }
+
+    #[test]
+    fn combinational() {
+        let netlist = blif::read(FULL_ADDER).unwrap();
+        let (mut harness, timing) = Harness::new(&netlist, FULL_ADDER, Format::Blif);
+        assert_eq!(timing.clock, None);
+        for value in 0..8 {
+            let inputs = bits(value, 3);
+            harness.set_inputs(&inputs);
+            let tick = harness.circuit.simulation().tick_count();
+            harness.run_until(tick + timing.depth + 2);
+            let (expected, _) = netlist.evaluate(&[], &inputs);
+            assert_eq!(harness.outputs(), expected, "inputs {:?}", inputs);
+            assert!(harness.circuit.simulation().is_settled());
+        }
+    }
+
+    #[test]
+    fn sequential() {
+        let netlist = yosys::read(COUNTER).unwrap();
+        let (mut harness, timing) = Harness::new(&netlist, COUNTER, Format::YosysJson);
+        let clock = timing.clock.unwrap();
+        assert!(clock.period >= clock.setup);
+
+        let mut registers = vec![false; netlist.registers.len()];
+        let mut edge = clock.reset;
+        for cycle in 0..16 {
+            // Inputs change right after an edge, and are taken at the next one.
+            harness.run_until(edge);
+            let inputs = [cycle % 5 != 2];
+            harness.set_inputs(&inputs);
+            edge += clock.period;
+            harness.run_until(edge - 1);
+            let (outputs, next) = netlist.evaluate(&registers, &inputs);
+            assert_eq!(harness.outputs(), outputs, "cycle {}", cycle);
+            registers = next;
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_netlists() {
+        let looped = ".model m\n.inputs a\n.outputs y\n.names a z y\n11 1\n.names y z\n0 1\n";
+        assert!(import(looped, Format::Blif).is_err());
+        let two_clocks = ".model m\n.inputs c d a\n.outputs y z\n\
+                          .latch a y re c 0\n.latch a z re d 0\n";
+        assert!(import(two_clocks, Format::Blif).is_err());
+        let word_level = r#"{"modules": {"m": {"ports": {}, "cells": {
+            "add": {"type": "$add", "connections": {}}}}}}"#;
+        assert!(import(word_level, Format::YosysJson).is_err());
+    }
+}
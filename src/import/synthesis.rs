@@ -0,0 +1,259 @@
+//! Turns a [`GateNetlist`] into clusters, each the OR of flips and flops from other clusters,
+//! and builds the clock that its flip-flops run on.
+
+use super::{Clock, Cover, GateNetlist, Timing};
+use anyhow::bail;
+
+/// Clusters to be built, and which of them are the ports.
+pub(super) struct Network {
+    pub nodes: Vec<Node>,
+    pub inputs: Vec<(String, usize)>,
+    pub outputs: Vec<(String, usize)>,
+}
+
+pub(super) enum Node {
+    /// The output of a switch.
+    Input,
+    /// A cluster driven by a flip (if inverted) or a flop from each literal. Without any, it is
+    /// always off.
+    Or(Vec<Literal>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Literal {
+    pub node: usize,
+    pub inverted: bool,
+}
+
+impl Literal {
+    fn new(node: usize, inverted: bool) -> Self {
+        Self { node, inverted }
+    }
+
+    fn invert(self) -> Self {
+        Self::new(self.node, !self.inverted)
+    }
+}
+
+// The undriven cluster that every constant is made from.
+const ZERO: usize = 0;
+
+// Ticks between the reset ending and the first pulse of the clock, and between a pulse and
+// the registers changing.
+const RESET_TICKS: u64 = 5;
+const LOAD_TICKS: u64 = 2;
+
+pub(super) fn synthesize(netlist: &GateNetlist) -> anyhow::Result<(Network, Timing)> {
+    let mut builder = Builder {
+        nodes: vec![Node::Or(Vec::new())],
+        signals: vec![None; netlist.num_nets],
+        visiting: vec![false; netlist.num_nets],
+    };
+    let mut network = Network {
+        nodes: Vec::new(),
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+    };
+    for (name, net) in &netlist.inputs {
+        let node = builder.push(Node::Input);
+        builder.signals[*net] = Some(node);
+        network.inputs.push((name.clone(), node));
+    }
+    // Registers are filled in once their inputs are known.
+    let registers: Vec<usize> = netlist
+        .registers
+        .iter()
+        .map(|register| {
+            let node = builder.push(Node::Or(Vec::new()));
+            builder.signals[register.q] = Some(node);
+            node
+        })
+        .collect();
+
+    let mut drivers = vec![None; netlist.num_nets];
+    for (index, gate) in netlist.gates.iter().enumerate() {
+        drivers[gate.output] = Some(index);
+    }
+    for (name, net) in &netlist.outputs {
+        let node = builder.resolve(netlist, &drivers, *net)?;
+        network.outputs.push((name.clone(), node));
+    }
+    let mut inputs = Vec::new();
+    for register in &netlist.registers {
+        inputs.push(builder.resolve(netlist, &drivers, register.d)?);
+    }
+
+    // Nodes only use ones before them so far, so the depth of each is found in one go.
+    let mut depths: Vec<u64> = vec![0; builder.nodes.len()];
+    for (index, node) in builder.nodes.iter().enumerate() {
+        if let Node::Or(literals) = node {
+            depths[index] = literals
+                .iter()
+                .map(|literal| depths[literal.node] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+    }
+    let register_depth = inputs.iter().map(|&node| depths[node]).max();
+    let register_depth = register_depth.unwrap_or(0);
+    let output_depth = network.outputs.iter().map(|&(_, node)| depths[node]).max();
+    let output_depth = output_depth.unwrap_or(0);
+    let depth = register_depth.max(output_depth);
+
+    let clock = if registers.is_empty() {
+        None
+    } else {
+        // Long enough for everything to settle, with inputs changed just after an edge.
+        let half_period = ((depth + 6) / 2).max(3);
+        builder.build_registers(&registers, &inputs, half_period as usize);
+        Some(Clock {
+            period: half_period * 2,
+            reset: RESET_TICKS,
+            setup: register_depth + LOAD_TICKS + 2,
+        })
+    };
+
+    network.nodes = builder.nodes;
+    Ok((network, Timing { depth, clock }))
+}
+
+struct Builder {
+    nodes: Vec<Node>,
+    // The node that carries each net, once it is built.
+    signals: Vec<Option<usize>>,
+    // The nets whose gates are being built, to find loops.
+    visiting: Vec<bool>,
+}
+
+impl Builder {
+    fn push(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// The OR of `literals`, or just the node if it is the only one and not inverted.
+    fn or(&mut self, mut literals: Vec<Literal>) -> usize {
+        literals.sort_by_key(|literal| (literal.node, literal.inverted));
+        literals.dedup();
+        match literals[..] {
+            [] => ZERO,
+            [literal] if !literal.inverted => literal.node,
+            _ => self.push(Node::Or(literals)),
+        }
+    }
+
+    /// Builds the net and the gates it depends on, depth first.
+    fn resolve(
+        &mut self,
+        netlist: &GateNetlist,
+        drivers: &[Option<usize>],
+        net: usize,
+    ) -> anyhow::Result<usize> {
+        let mut stack = vec![(net, false)];
+        while let Some((net, expanded)) = stack.pop() {
+            if self.signals[net].is_some() {
+                continue;
+            }
+            // Inputs and registers are already built, so this is a gate.
+            let gate = &netlist.gates[drivers[net].expect("undriven net")];
+            if expanded {
+                let inputs: Vec<usize> = gate
+                    .inputs
+                    .iter()
+                    .map(|&input| self.signals[input].expect("unbuilt input"))
+                    .collect();
+                self.signals[net] = Some(self.cover(&inputs, &gate.cover));
+                self.visiting[net] = false;
+                continue;
+            }
+            self.visiting[net] = true;
+            stack.push((net, true));
+            for &input in &gate.inputs {
+                if self.visiting[input] {
+                    bail!("combinational loop through {}", netlist.net_name(input));
+                }
+                if self.signals[input].is_none() {
+                    stack.push((input, false));
+                }
+            }
+        }
+        Ok(self.signals[net].unwrap())
+    }
+
+    /// Builds a cover as an OR of its cubes, where each cube with more than one literal is the
+    /// inverted OR of its inverted literals.
+    fn cover(&mut self, inputs: &[usize], cover: &Cover) -> usize {
+        let mut literals = Vec::new();
+        for cube in &cover.cubes {
+            let cube: Vec<Literal> = cube
+                .iter()
+                .zip(inputs)
+                .filter_map(|(value, &node)| Some(Literal::new(node, !(*value)?)))
+                .collect();
+            literals.push(match cube[..] {
+                // Always matches.
+                [] => Literal::new(ZERO, true),
+                [literal] => literal,
+                _ => {
+                    let inverted = cube.into_iter().map(Literal::invert).collect();
+                    Literal::new(self.or(inverted), true)
+                }
+            });
+        }
+        let on = self.or(literals);
+        if cover.value {
+            on
+        } else {
+            self.push(Node::Or(vec![Literal::new(on, true)]))
+        }
+    }
+
+    /// Turns each register into a latch, which loads its input while the clock pulses and holds
+    /// its value otherwise. Each latch is a loop of two ticks, so it holds two values that
+    /// take turns, but the pulse is long enough to load both.
+    ///
+    /// For the first ticks, while the clock starts up, the latches are held at 0 by a reset.
+    fn build_registers(&mut self, registers: &[usize], inputs: &[usize], half_period: usize) {
+        // A ring of flops with one flip in it, which turns on and off every half period.
+        let ring: Vec<usize> = (0..half_period)
+            .map(|_| self.push(Node::Or(Vec::new())))
+            .collect();
+        for (index, &node) in ring.iter().enumerate() {
+            let literal = match index {
+                0 => Literal::new(ring[half_period - 1], true),
+                _ => Literal::new(ring[index - 1], false),
+            };
+            self.nodes[node] = Node::Or(vec![literal]);
+        }
+        // On for two ticks whenever the ring turns on.
+        let not_pulse = self.or(vec![
+            Literal::new(ring[0], true),
+            Literal::new(ring[2], false),
+        ]);
+        let pulse = self.push(Node::Or(vec![Literal::new(not_pulse, true)]));
+
+        // The constant 1 comes on at tick 1, and the reset is on from then until it is through
+        // the flops, for ticks 1 to `RESET_TICKS - 1`. That holds the latches at 0 until tick
+        // `RESET_TICKS`.
+        let mut delayed = self.push(Node::Or(vec![Literal::new(ZERO, true)]));
+        for _ in 0..RESET_TICKS - 2 {
+            delayed = self.push(Node::Or(vec![Literal::new(delayed, false)]));
+        }
+        let reset = self.push(Node::Or(vec![Literal::new(delayed, true)]));
+
+        for (&register, &input) in registers.iter().zip(inputs) {
+            let hold = self.or(vec![
+                Literal::new(register, true),
+                Literal::new(pulse, false),
+                Literal::new(reset, false),
+            ]);
+            let load = self.or(vec![
+                Literal::new(input, true),
+                Literal::new(pulse, true),
+                Literal::new(reset, false),
+            ]);
+            self.nodes[register] =
+                Node::Or(vec![Literal::new(hold, true), Literal::new(load, true)]);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Reads the JSON that Yosys writes with `write_json`.
+
+use super::json::{self, Value};
+use super::{bit_names, Cover, Gate, GateNetlist, Register};
+use anyhow::{bail, Context};
+use std::collections::HashMap;
+
+// Input pins and on-set cubes (or off-set, if the flag is false) of the gate cells.
+type CellFunction = (&'static [&'static str], &'static [&'static str], bool);
+
+fn cell_function(cell_type: &str) -> Option<CellFunction> {
+    Some(match cell_type {
+        "$_BUF_" => (&["A"], &["1"], true),
+        "$_NOT_" => (&["A"], &["0"], true),
+        "$_AND_" => (&["A", "B"], &["11"], true),
+        "$_NAND_" => (&["A", "B"], &["0-", "-0"], true),
+        "$_OR_" => (&["A", "B"], &["1-", "-1"], true),
+        "$_NOR_" => (&["A", "B"], &["00"], true),
+        "$_XOR_" => (&["A", "B"], &["10", "01"], true),
+        "$_XNOR_" => (&["A", "B"], &["00", "11"], true),
+        "$_ANDNOT_" => (&["A", "B"], &["10"], true),
+        "$_ORNOT_" => (&["A", "B"], &["1-", "-0"], true),
+        "$_MUX_" => (&["A", "B", "S"], &["1-0", "-11"], true),
+        "$_NMUX_" => (&["A", "B", "S"], &["1-0", "-11"], false),
+        "$_AOI3_" => (&["A", "B", "C"], &["11-", "--1"], false),
+        "$_OAI3_" => (&["A", "B", "C"], &["00-", "--0"], true),
+        "$_AOI4_" => (&["A", "B", "C", "D"], &["11--", "--11"], false),
+        "$_OAI4_" => (&["A", "B", "C", "D"], &["00--", "--00"], true),
+        _ => return None,
+    })
+}
+
+pub(super) fn read(text: &str) -> anyhow::Result<GateNetlist> {
+    let root = json::parse(text).context("invalid JSON")?;
+    let modules = root
+        .get("modules")
+        .and_then(Value::as_object)
+        .context("no modules")?;
+    let (name, module) = match modules {
+        [module] => module,
+        _ => modules
+            .iter()
+            .find(|(_, module)| is_top(module))
+            .context("more than one module, and none is marked as the top one")?,
+    };
+    read_module(module).with_context(|| format!("in module `{}`", name))
+}
+
+fn is_top(module: &Value) -> bool {
+    let top = module.get("attributes").and_then(|a| a.get("top"));
+    match top {
+        Some(Value::String(value)) => value.contains('1'),
+        Some(Value::Number(value)) => *value != 0.0,
+        _ => false,
+    }
+}
+
+struct Reader {
+    netlist: GateNetlist,
+    // Yosys's bit numbers, and the constants by their names.
+    nets: HashMap<String, usize>,
+}
+
+impl Reader {
+    fn net(&mut self, bit: &Value) -> anyhow::Result<usize> {
+        let key = match bit {
+            Value::Number(number) => number.to_string(),
+            // Undefined bits are taken to be 0.
+            Value::String(name) if matches!(name.as_str(), "0" | "x" | "z") => "0".to_owned(),
+            Value::String(name) if name == "1" => name.clone(),
+            _ => bail!("invalid bit {:?}", bit),
+        };
+        if let Some(&net) = self.nets.get(&key) {
+            return Ok(net);
+        }
+        let net = self.netlist.new_net();
+        if let Value::String(name) = bit {
+            self.netlist.gates.push(Gate {
+                inputs: Vec::new(),
+                cover: Cover::constant(name == "1"),
+                output: net,
+            });
+        }
+        self.nets.insert(key, net);
+        Ok(net)
+    }
+
+    /// The one bit connected to `pin` of a cell.
+    fn pin(&mut self, connections: &Value, pin: &str) -> anyhow::Result<usize> {
+        match connections.get(pin).and_then(Value::as_array) {
+            Some([bit]) => self.net(bit),
+            _ => bail!("pin {} must be connected to one bit", pin),
+        }
+    }
+}
+
+fn read_module(module: &Value) -> anyhow::Result<GateNetlist> {
+    let mut reader = Reader {
+        netlist: GateNetlist::default(),
+        nets: HashMap::new(),
+    };
+
+    let ports = module
+        .get("ports")
+        .and_then(Value::as_object)
+        .unwrap_or_default();
+    for (name, port) in ports {
+        let bits = port
+            .get("bits")
+            .and_then(Value::as_array)
+            .with_context(|| format!("port `{}` has no bits", name))?;
+        let names = bit_names(name, bits.len());
+        for (bit, name) in bits.iter().zip(names) {
+            let net = reader.net(bit)?;
+            match port.get("direction").and_then(Value::as_str) {
+                Some("input") => reader.netlist.inputs.push((name, net)),
+                Some("output") => reader.netlist.outputs.push((name, net)),
+                _ => bail!("port `{}` must be an input or an output", name),
+            }
+        }
+    }
+
+    let cells = module
+        .get("cells")
+        .and_then(Value::as_object)
+        .unwrap_or_default();
+    for (name, cell) in cells {
+        read_cell(&mut reader, cell).with_context(|| format!("in cell `{}`", name))?;
+    }
+
+    let mut netlist = reader.netlist;
+    netlist.finish()?;
+    Ok(netlist)
+}
+
+fn read_cell(reader: &mut Reader, cell: &Value) -> anyhow::Result<()> {
+    let cell_type = cell
+        .get("type")
+        .and_then(Value::as_str)
+        .context("no cell type")?;
+    let connections = cell.get("connections").context("no connections")?;
+    if let Some((pins, cubes, value)) = cell_function(cell_type) {
+        let inputs = pins
+            .iter()
+            .map(|pin| reader.pin(connections, pin))
+            .collect::<anyhow::Result<_>>()?;
+        let output = reader.pin(connections, "Y")?;
+        reader.netlist.gates.push(Gate {
+            inputs,
+            cover: Cover::parse(cubes, value)?,
+            output,
+        });
+        return Ok(());
+    }
+
+    let enable = match cell_type {
+        "$_DFF_P_" => None,
+        "$_DFFE_PP_" => Some(true),
+        "$_DFFE_PN_" => Some(false),
+        // Debug information from newer versions of Yosys.
+        "$scopeinfo" => return Ok(()),
+        _ => bail!(
+            "unsupported cell type `{}`; map the design to single-bit gates and `$_DFF_P_` first",
+            cell_type
+        ),
+    };
+    let clock = reader.pin(connections, "C")?;
+    let q = reader.pin(connections, "Q")?;
+    let mut d = reader.pin(connections, "D")?;
+    if let Some(active) = enable {
+        // The register takes in D while enabled, and keeps Q otherwise.
+        let enable = reader.pin(connections, "E")?;
+        let cubes: &[&str] = if active {
+            &["11-", "-01"]
+        } else {
+            &["10-", "-11"]
+        };
+        let next = reader.netlist.new_net();
+        reader.netlist.gates.push(Gate {
+            inputs: vec![d, enable, q],
+            cover: Cover::parse(cubes, true)?,
+            output: next,
+        });
+        d = next;
+    }
+    reader.netlist.registers.push(Register {
+        d,
+        q,
+        clock: Some(clock),
+    });
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! Drawing the labels of a circuit on the board, and typing into them.
+//!
+//! Labels are text drawn in world space: they are sized in tiles, so they grow and shrink with
+//! the zoom of the camera like everything else on the board.
+
+use crate::circuit::Circuit;
+use crate::schematic::Anchor;
+use crate::viewport::Viewport;
+use glam::Vec2;
+use wgpu_glyph::{GlyphBrush, HorizontalAlign, Layout, Section, Text, VerticalAlign};
+
+// The height of the text, in tiles.
+const TEXT_SIZE: f32 = 0.6;
+
+const LABEL_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const EDITING_COLOR: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+
+/// A label that is being typed into. Nothing changes in the circuit until it is finished.
+pub struct LabelEditor {
+    anchor: Anchor,
+    text: String,
+}
+
+impl LabelEditor {
+    /// Starts editing the label at `anchor`, beginning with its current text.
+    pub fn new(circuit: &Circuit, anchor: Anchor) -> Self {
+        let text = circuit
+            .labels()
+            .iter()
+            .find(|label| label.anchor == anchor)
+            .map(|label| label.text.clone())
+            .unwrap_or_default();
+        Self { anchor, text }
+    }
+
+    pub fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Adds a typed character. Control characters, such as the ones that come with Ctrl and
+    /// Backspace, are left out.
+    pub fn type_char(&mut self, c: char) {
+        if !c.is_control() {
+            self.text.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.text.pop();
+    }
+
+    /// Saves the text to the label, as one undoable edit.
+    pub fn finish(self, circuit: &mut Circuit) {
+        circuit.set_label(self.anchor, &self.text);
+    }
+}
+
+/// Queues every label of `circuit`, as seen through `viewport`. The label that `editor` is
+/// editing is drawn with the text typed so far instead.
+///
+/// Labels on tiles sit centered above their tile. Free labels start at their point, centered
+/// on it vertically.
+pub fn queue_labels(
+    glyph_brush: &mut GlyphBrush<()>,
+    circuit: &Circuit,
+    viewport: &Viewport,
+    editor: Option<&LabelEditor>,
+) {
+    let scale = TEXT_SIZE * viewport.camera().zoom;
+    let editing = editor.map(|editor| editor.anchor);
+    let labels = circuit
+        .labels()
+        .iter()
+        .filter(|label| Some(label.anchor) != editing)
+        .map(|label| (label.anchor, label.text.as_str(), LABEL_COLOR));
+    let edited = editor.map(|editor| (editor.anchor, editor.text.as_str(), EDITING_COLOR));
+
+    for (anchor, text, color) in labels.chain(edited) {
+        let (point, layout) = match anchor {
+            Anchor::Tile(position) => (
+                position.as_vec2() + Vec2::new(0.5, 1.0),
+                Layout::default_single_line()
+                    .h_align(HorizontalAlign::Center)
+                    .v_align(VerticalAlign::Bottom),
+            ),
+            Anchor::Free(point) => (
+                point,
+                Layout::default_single_line().v_align(VerticalAlign::Center),
+            ),
+        };
+        // A caret shows where typing goes.
+        let caret = if Some(anchor) == editing { "_" } else { "" };
+        let screen_position = viewport.world_to_screen(point);
+        glyph_brush.queue(Section {
+            screen_position: screen_position.into(),
+            layout,
+            text: vec![
+                Text::new(text).with_color(color).with_scale(scale),
+                Text::new(caret).with_color(color).with_scale(scale),
+            ],
+            ..Default::default()
+        });
+    }
+}
@@ -1,30 +1,65 @@
 pub mod board;
+pub mod breakpoints;
 pub mod circuit;
+pub mod cli;
+pub mod cluster_cache;
 pub mod counter;
 pub mod cursor;
 pub mod depot;
 pub mod direction;
+pub mod dot;
+pub mod harness;
+pub mod import;
 pub mod instance;
+pub mod labels;
+pub mod netlist;
+pub mod probes;
 pub mod rect;
+pub mod scheduler;
+pub mod schematic;
 pub mod screen_vertex;
 pub mod simulation;
+pub mod statistics;
+pub mod test_vectors;
+pub mod timing;
+pub mod truth_table;
+pub mod vcd;
 pub mod viewport;
+pub mod worker;
 
-use crate::circuit::Circuit;
+use crate::breakpoints::Breakpoints;
 use crate::circuit::ComponentType;
+use crate::circuit::{Circuit, CircuitRenderer, Port, Ports};
 use crate::counter::Counter;
 use crate::cursor::{CursorManager, CursorState};
-use crate::direction::Direction;
+use crate::direction::{Direction, Relative, Transform};
+use crate::dot::ClusterGraph;
+use crate::labels::LabelEditor;
+use crate::netlist::Netlist;
+use crate::probes::Probes;
+use crate::scheduler::{TickMode, TickScheduler};
+use crate::schematic::{Anchor, Schematic};
+use crate::simulation::{Activity, Hit};
+use crate::timing::TimingDiagram;
+use crate::truth_table::TruthTable;
 use crate::viewport::Viewport;
+use crate::worker::SimulationWorker;
 use anyhow::Context;
 use futures_executor::block_on;
 use glam::Vec2;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
 use std::sync::Arc;
+use std::thread;
 use std::time::Instant;
 use wgpu_glyph::ab_glyph::FontArc;
 use wgpu_glyph::{GlyphBrushBuilder, Section, Text};
 use winit::event::{
-    ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    ElementState, Event, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
 };
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{CursorIcon, Window, WindowBuilder};
@@ -38,12 +73,62 @@ Place Component - Left click
 Place Wire - Left click and drag
 Remove Component/Wire - Right click
 Rotate Component - R
+Rotate Placed Component/Selection - Ctrl+R
+    (with Shift: counter-clockwise)
+Turn Placed Component/Selection Around - Ctrl+T
+Mirror Placed Component/Selection - Ctrl+M
+    (with Shift: vertically)
+Select Area - Shift+Left click and drag
+Move Selection - Left click and drag inside it
+Copy/Cut/Paste - Ctrl+C/Ctrl+X/Ctrl+V
+Delete Selection - Delete
+Make Chip from Selection - Ctrl+G
+Edit Chip - Enter
+Finish Editing Chip - Shift+Enter
+Cancel Selection/Paste - Escape
 Interact with Component - E
+Label Tile - Ctrl+L, type, then Enter
+    (Escape to cancel, empty to remove)
+Free Label - Ctrl+Shift+L
+Save Circuit - Ctrl+S
+Export Verilog and BLIF Netlists - Ctrl+N
+Export Cluster Graph as DOT - Ctrl+Shift+N
+Export Truth Table of Selection - Ctrl+Shift+T
+    (or of every switch and lamp)
+Import Yosys JSON or BLIF Netlist - Ctrl+I
+    (then click to place it)
+Open Circuit - Ctrl+O
+Undo - Ctrl+Z
+Redo - Ctrl+Shift+Z or Ctrl+Y
+Pause/Resume Simulation - Space
+Single Tick - Period (.)
+    (forward through history while rewound)
+Run Until Settled - Ctrl+Period (.)
+Step Back in Time - Comma (,)
+    (with Shift: 100 ticks, also for Period)
+Scrub Through History - Ctrl+Scroll
+Back to the Present - End
+Breakpoint on Tile - B (press again to change
+    rises/falls/changes/is high/is low/off)
+Add Tile to Last Breakpoint - Ctrl+B
+Last Breakpoint AND/OR - Shift+B
+Clear Breakpoints - Ctrl+Shift+B
+Force Tile High/Low/Release - H
+Release All Forced Tiles - Ctrl+H
+Add/Remove Probe on Tile - P
+Export Probes as VCD - Ctrl+P
+Show/Hide Probe in Timing Diagram - Shift+P
+Remove All Probes - Ctrl+Shift+P
+Timing Diagram - F2 (with Shift: move it to
+    the other edge), click in it to go to a tick
+Slower/Faster - Minus/Equals
+Unlimited Tick Rate - U
 1 - Pin/Wire
 2 - Flip
 3 - Flop
 4 - Switch
 5 - Lamp
+6 - Chip (press again for the next one)
 ";
 
 pub type GraphicsContext = Arc<GraphicsContextInner>;
@@ -119,13 +204,44 @@ struct State {
     staging_belt: wgpu::util::StagingBelt,
     viewport: Viewport,
     frame_counter: Counter,
+    tick_counter: Counter,
+    tick_scheduler: TickScheduler,
+    /// The tick count of the last snapshot that was counted by `tick_counter`.
+    last_tick_count: u64,
+    worker: SimulationWorker,
     should_close: bool,
     last_update: Instant,
     circuit: Circuit,
+    circuit_renderer: CircuitRenderer,
     cursor_manager: CursorManager,
     draw_help: bool,
+    timing_diagram: TimingDiagram,
+    modifiers: ModifiersState,
+    file_path: PathBuf,
+    status: Option<String>,
+    /// Receives the outcome of running until settled, while that is going on.
+    settling: Option<Receiver<(u64, bool)>>,
+    /// Receives the status once a truth table has been made and exported, while that is going on.
+    tabulating: Option<Receiver<String>>,
+    breakpoints: Breakpoints,
+    /// The breakpoint hit that was last seen in a snapshot.
+    breakpoint_hit: Option<Hit>,
+    probes: Probes,
+    /// The label being typed into, if any. It takes every key press until it is finished.
+    label_editor: Option<LabelEditor>,
+    clipboard: Option<Schematic>,
+    /// The circuits that contain the chips being edited, innermost last, along with the ID of
+    /// the chip that was entered.
+    chip_stack: Vec<(Circuit, u32)>,
 }
 
+const DEFAULT_FILE_PATH: &str = "circuit.flipflop";
+
+// The most ticks that running until settled goes on for.
+const SETTLE_BUDGET: u64 = 100_000;
+// The same, for each row of a truth table.
+const TRUTH_TABLE_BUDGET: u64 = 10_000;
+
 fn create_depth_texture(gfx: &GraphicsContext) -> wgpu::Texture {
     gfx.device.create_texture(&wgpu::TextureDescriptor {
         label: Some("depth_texture"),
@@ -143,7 +259,7 @@ fn create_depth_texture(gfx: &GraphicsContext) -> wgpu::Texture {
 }
 
 impl State {
-    async fn new(window: Window) -> anyhow::Result<Self> {
+    async fn new(window: Window, file_path: PathBuf) -> anyhow::Result<Self> {
         let gfx = Arc::new(GraphicsContextInner::new(window).await?);
         gfx.reconfigure();
         let depth_texture = create_depth_texture(&gfx);
@@ -156,8 +272,10 @@ impl State {
 
         let viewport = Viewport::new(&gfx);
 
-        let circuit = Circuit::new(&gfx, &viewport);
+        let circuit = Circuit::new();
+        let circuit_renderer = CircuitRenderer::new(&gfx, &viewport);
         let cursor_manager = CursorManager::new(&gfx, &viewport);
+        let timing_diagram = TimingDiagram::new(&gfx);
 
         Ok(Self {
             gfx,
@@ -167,11 +285,28 @@ impl State {
             staging_belt,
             viewport,
             frame_counter: Counter::new(),
+            tick_counter: Counter::new(),
+            tick_scheduler: TickScheduler::new(),
+            last_tick_count: 0,
+            worker: SimulationWorker::new(),
             should_close: false,
             last_update: Instant::now(),
             circuit,
+            circuit_renderer,
             cursor_manager,
             draw_help: true,
+            timing_diagram,
+            modifiers: ModifiersState::empty(),
+            file_path,
+            status: None,
+            settling: None,
+            tabulating: None,
+            breakpoints: Breakpoints::new(),
+            breakpoint_hit: None,
+            probes: Probes::new(),
+            label_editor: None,
+            clipboard: None,
+            chip_stack: Vec::new(),
         })
     }
 
@@ -183,6 +318,9 @@ impl State {
             WindowEvent::Resized(..) | WindowEvent::ScaleFactorChanged { .. } => {
                 self.reconfigure();
             }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+            }
             WindowEvent::CursorMoved { position, .. } => {
                 let position = Vec2::new(position.x as f32, position.y as f32);
                 self.viewport.cursor_moved(position);
@@ -202,6 +340,28 @@ impl State {
                     }
                 }
                 (MouseButton::Left, ElementState::Pressed) => {
+                    let screen_position = self.viewport.cursor().screen_position;
+                    if self.timing_diagram.contains(screen_position) {
+                        self.go_to_diagram_tick(screen_position);
+                        return;
+                    }
+                    let position = self.viewport.cursor().tile();
+                    if let CursorState::Paste { preview } = self.cursor_manager.current_state() {
+                        if let Err(err) = self.circuit.paste(preview.schematic(), position) {
+                            self.status = Some(format!("Cannot paste here: {:#}", err));
+                        }
+                        return;
+                    }
+                    if self.modifiers.shift() {
+                        self.cursor_manager.start_select(&self.viewport);
+                        return;
+                    }
+                    if self.cursor_manager.selection_contains(position) {
+                        self.cursor_manager
+                            .start_move(&self.viewport, &self.circuit);
+                        return;
+                    }
+                    self.cursor_manager.set_selection(None);
                     match self.cursor_manager.place_type() {
                         ComponentType::Pin => {
                             self.cursor_manager.start_place_wire(&self.viewport);
@@ -239,6 +399,24 @@ impl State {
                             }
                             self.cursor_manager.end();
                         }
+                        CursorState::Select { .. } => {
+                            self.cursor_manager.end();
+                        }
+                        &CursorState::Move { start_position, .. } => {
+                            let offset = self.viewport.cursor().tile() - start_position;
+                            if let Some((min, max)) = self.cursor_manager.selection() {
+                                match self.circuit.move_region(min, max, offset) {
+                                    Ok(()) => {
+                                        self.cursor_manager
+                                            .set_selection(Some((min + offset, max + offset)));
+                                    }
+                                    Err(err) => {
+                                        self.status = Some(format!("Cannot move here: {:#}", err));
+                                    }
+                                }
+                            }
+                            self.cursor_manager.end();
+                        }
                         _ => {}
                     }
                 }
@@ -254,16 +432,28 @@ impl State {
                 _ => {}
             },
             WindowEvent::MouseWheel { delta, .. } => match &self.cursor_manager.current_state() {
-                CursorState::Normal => {
+                CursorState::Normal | CursorState::Paste { .. } => {
                     let delta = match delta {
                         MouseScrollDelta::LineDelta(_x, y) => y,
                         MouseScrollDelta::PixelDelta(position) => position.y as f32 / 16.0,
                     };
+                    if self.modifiers.ctrl() {
+                        let offset = delta.round() as i64;
+                        if offset != 0 {
+                            self.step_history(offset);
+                        }
+                        return;
+                    }
                     let camera = self.viewport.camera_mut();
                     camera.set_zoom(camera.zoom * camera.zoom_step.powf(delta));
                 }
                 _ => {}
             },
+            WindowEvent::ReceivedCharacter(c) => {
+                if let Some(editor) = &mut self.label_editor {
+                    editor.type_char(c);
+                }
+            }
             WindowEvent::KeyboardInput { input, .. } => {
                 if let Some(keycode) = input.virtual_keycode {
                     let pressed = match input.state {
@@ -271,7 +461,69 @@ impl State {
                         ElementState::Released => false,
                     };
 
+                    // Releases still go through, so that keys held before typing aren't stuck.
+                    if pressed && self.label_editor.is_some() {
+                        self.edit_label_key(keycode);
+                        return;
+                    }
+
                     match keycode {
+                        VirtualKeyCode::S if pressed && self.modifiers.ctrl() => {
+                            self.save();
+                        }
+                        VirtualKeyCode::O if pressed && self.modifiers.ctrl() => {
+                            self.load();
+                        }
+                        VirtualKeyCode::N if pressed && self.modifiers.ctrl() => {
+                            if self.modifiers.shift() {
+                                self.export_cluster_graph();
+                            } else {
+                                self.export_netlist();
+                            }
+                        }
+                        VirtualKeyCode::I if pressed && self.modifiers.ctrl() => {
+                            self.import_netlist();
+                        }
+                        VirtualKeyCode::Z if pressed && self.modifiers.ctrl() => {
+                            if self.modifiers.shift() {
+                                self.circuit.redo();
+                            } else {
+                                self.circuit.undo();
+                            }
+                        }
+                        VirtualKeyCode::Y if pressed && self.modifiers.ctrl() => {
+                            self.circuit.redo();
+                        }
+                        VirtualKeyCode::C if pressed && self.modifiers.ctrl() => {
+                            if let Some((min, max)) = self.cursor_manager.selection() {
+                                self.clipboard = Some(self.circuit.copy_region(min, max));
+                            }
+                        }
+                        VirtualKeyCode::X if pressed && self.modifiers.ctrl() => {
+                            if let Some((min, max)) = self.cursor_manager.selection() {
+                                self.clipboard = Some(self.circuit.copy_region(min, max));
+                                self.circuit.delete_region(min, max);
+                                self.cursor_manager.set_selection(None);
+                            }
+                        }
+                        VirtualKeyCode::V if pressed && self.modifiers.ctrl() => {
+                            if let Some(clipboard) = &self.clipboard {
+                                self.cursor_manager
+                                    .start_paste(clipboard.clone(), &self.circuit);
+                            }
+                        }
+                        VirtualKeyCode::Delete if pressed => {
+                            if let Some((min, max)) = self.cursor_manager.selection() {
+                                self.circuit.delete_region(min, max);
+                                self.cursor_manager.set_selection(None);
+                            }
+                        }
+                        VirtualKeyCode::Escape if pressed => {
+                            if let CursorState::Paste { .. } = self.cursor_manager.current_state() {
+                                self.cursor_manager.end();
+                            }
+                            self.cursor_manager.set_selection(None);
+                        }
                         VirtualKeyCode::Up | VirtualKeyCode::W => {
                             self.viewport.camera_mut().pan_up = pressed;
                         }
@@ -291,32 +543,152 @@ impl State {
                             self.viewport.camera_mut().zoom_out = pressed;
                         }
                         VirtualKeyCode::Key1 if pressed => {
-                            self.cursor_manager.set_place_type(ComponentType::Pin);
+                            self.cursor_manager
+                                .set_place_type(ComponentType::Pin, &self.circuit);
                         }
                         VirtualKeyCode::Key2 if pressed => {
-                            self.cursor_manager.set_place_type(ComponentType::Flip);
+                            self.cursor_manager
+                                .set_place_type(ComponentType::Flip, &self.circuit);
                         }
                         VirtualKeyCode::Key3 if pressed => {
-                            self.cursor_manager.set_place_type(ComponentType::Flop);
+                            self.cursor_manager
+                                .set_place_type(ComponentType::Flop, &self.circuit);
                         }
                         VirtualKeyCode::Key4 if pressed => {
-                            self.cursor_manager.set_place_type(ComponentType::Switch);
+                            self.cursor_manager
+                                .set_place_type(ComponentType::Switch, &self.circuit);
                         }
                         VirtualKeyCode::Key5 if pressed => {
-                            self.cursor_manager.set_place_type(ComponentType::Lamp);
+                            self.cursor_manager
+                                .set_place_type(ComponentType::Lamp, &self.circuit);
+                        }
+                        VirtualKeyCode::Key6 if pressed => {
+                            self.next_chip();
+                        }
+                        VirtualKeyCode::G if pressed && self.modifiers.ctrl() => {
+                            self.define_chip();
+                        }
+                        VirtualKeyCode::Return if pressed => {
+                            if self.modifiers.shift() {
+                                self.leave_chip();
+                            } else {
+                                self.enter_chip();
+                            }
+                        }
+                        // With Ctrl, so that the key doesn't also type an "l" into the label.
+                        VirtualKeyCode::L if pressed && self.modifiers.ctrl() => {
+                            self.start_label(self.modifiers.shift());
                         }
                         VirtualKeyCode::E if pressed => {
                             //TODO more intuitive controls?
                             self.circuit.interact(self.viewport.cursor().tile())
                         }
+                        VirtualKeyCode::R if pressed && self.modifiers.ctrl() => {
+                            if self.modifiers.shift() {
+                                self.transform(Transform::Rotate(Relative::Left));
+                            } else {
+                                self.transform(Transform::Rotate(Relative::Right));
+                            }
+                        }
+                        VirtualKeyCode::T if pressed && self.modifiers.ctrl() => {
+                            if self.modifiers.shift() {
+                                self.export_truth_table();
+                            } else {
+                                self.transform(Transform::Rotate(Relative::Opposite));
+                            }
+                        }
+                        VirtualKeyCode::M if pressed && self.modifiers.ctrl() => {
+                            if self.modifiers.shift() {
+                                self.transform(Transform::MirrorVertical);
+                            } else {
+                                self.transform(Transform::MirrorHorizontal);
+                            }
+                        }
                         VirtualKeyCode::R if pressed => {
                             self.cursor_manager.set_place_orientation(
                                 self.cursor_manager.place_orientation().right(),
                             );
                         }
+                        VirtualKeyCode::Space if pressed => {
+                            self.tick_scheduler.toggle_pause();
+                        }
+                        VirtualKeyCode::Period if pressed && self.modifiers.ctrl() => {
+                            self.tick_scheduler.set_mode(TickMode::Paused);
+                            self.resume_from_breakpoint();
+                            self.settling = Some(self.worker.settle(SETTLE_BUDGET));
+                            self.status = Some("Running until settled...".to_string());
+                        }
+                        VirtualKeyCode::Period if pressed => {
+                            let rewound = self.worker.snapshot().viewed_tick().is_some();
+                            if rewound {
+                                self.step_history(self.history_step());
+                            } else {
+                                self.resume_from_breakpoint();
+                                self.tick_scheduler.step();
+                            }
+                        }
+                        VirtualKeyCode::Comma if pressed => {
+                            self.step_history(-self.history_step());
+                        }
+                        VirtualKeyCode::End if pressed => {
+                            self.worker.seek(u64::MAX);
+                        }
+                        VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract if pressed => {
+                            self.tick_scheduler
+                                .set_tick_rate(self.tick_scheduler.tick_rate() / 2.0);
+                        }
+                        VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd if pressed => {
+                            self.tick_scheduler
+                                .set_tick_rate(self.tick_scheduler.tick_rate() * 2.0);
+                        }
+                        VirtualKeyCode::B if pressed => {
+                            let position = self.viewport.cursor().tile();
+                            if self.modifiers.ctrl() && self.modifiers.shift() {
+                                self.breakpoints.clear();
+                            } else if self.modifiers.ctrl() {
+                                self.breakpoints.join(position);
+                            } else if self.modifiers.shift() {
+                                self.breakpoints.toggle_combine();
+                            } else {
+                                self.breakpoints.cycle(position);
+                            }
+                        }
+                        VirtualKeyCode::H if pressed && self.modifiers.ctrl() => {
+                            self.circuit.release_all();
+                        }
+                        VirtualKeyCode::H if pressed => {
+                            let position = self.viewport.cursor().tile();
+                            let next = match self.circuit.forced(position) {
+                                None => Some(true),
+                                Some(true) => Some(false),
+                                Some(false) => None,
+                            };
+                            self.circuit.force(position, next);
+                        }
+                        VirtualKeyCode::P if pressed => {
+                            if self.modifiers.ctrl() && self.modifiers.shift() {
+                                self.probes.clear();
+                            } else if self.modifiers.ctrl() {
+                                self.export_trace();
+                            } else if self.modifiers.shift() {
+                                self.probes.toggle_shown(self.viewport.cursor().tile());
+                            } else {
+                                self.probes.toggle(self.viewport.cursor().tile());
+                            }
+                        }
+                        VirtualKeyCode::U if pressed => {
+                            self.tick_scheduler.toggle_unlimited();
+                        }
                         VirtualKeyCode::F1 if pressed => {
                             self.draw_help = !self.draw_help;
                         }
+                        VirtualKeyCode::F2 if pressed => {
+                            if self.modifiers.shift() {
+                                self.timing_diagram.toggle_dock();
+                            } else {
+                                self.timing_diagram.toggle_visible();
+                            }
+                        }
                         _ => {}
                     }
                 }
@@ -325,11 +697,417 @@ impl State {
         }
     }
 
+    fn save(&mut self) {
+        if !self.chip_stack.is_empty() {
+            self.status = Some("Finish editing the chip before saving".to_string());
+            return;
+        }
+        let result = File::create(&self.file_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(self.circuit.save(BufWriter::new(file))?));
+        self.status = Some(match result {
+            Ok(()) => format!("Saved {}", self.file_path.display()),
+            Err(err) => format!("Failed to save {}: {:#}", self.file_path.display(), err),
+        });
+    }
+
+    /// Rewinds to the tick under `screen_position` in the timing diagram, if it is still in the
+    /// history.
+    fn go_to_diagram_tick(&mut self, screen_position: Vec2) {
+        let tick = match self.timing_diagram.tick_at(screen_position) {
+            Some(x) => x,
+            None => return,
+        };
+        let oldest_tick = self.worker.snapshot().oldest_tick();
+        if tick >= oldest_tick {
+            self.tick_scheduler.set_mode(TickMode::Paused);
+            self.worker.seek(tick);
+            self.status = Some(format!("Went to tick {}", tick));
+        } else {
+            self.status = Some(format!("Tick {} is no longer in the history", tick));
+        }
+    }
+
+    /// Writes what the probes recorded next to the circuit file.
+    fn export_trace(&mut self) {
+        let path = self.file_path.with_extension("vcd");
+        let trace = self.worker.fetch_trace();
+        let result = File::create(&path)
+            .and_then(|file| self.probes.write_vcd(BufWriter::new(file), &trace));
+        self.status = Some(match result {
+            Ok(()) => format!("Exported probes to {}", path.display()),
+            Err(err) => format!("Failed to export {}: {:#}", path.display(), err),
+        });
+    }
+
+    /// Starts typing into the label on the tile under the cursor, or with `free`, the free label
+    /// nearest to the cursor (or a new one at the cursor).
+    fn start_label(&mut self, free: bool) {
+        let cursor = self.viewport.cursor();
+        let anchor = if free {
+            let point = self
+                .circuit
+                .free_label_near(cursor.world_position, 0.5)
+                .unwrap_or(cursor.world_position);
+            Anchor::Free(point)
+        } else {
+            Anchor::Tile(cursor.tile())
+        };
+        self.label_editor = Some(LabelEditor::new(&self.circuit, anchor));
+        self.status = Some("Type the label, then press Enter".to_string());
+    }
+
+    /// Handles a key press while typing into a label.
+    fn edit_label_key(&mut self, keycode: VirtualKeyCode) {
+        match keycode {
+            VirtualKeyCode::Back => {
+                if let Some(editor) = &mut self.label_editor {
+                    editor.backspace();
+                }
+            }
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                if let Some(editor) = self.label_editor.take() {
+                    editor.finish(&mut self.circuit);
+                    self.status = None;
+                }
+            }
+            VirtualKeyCode::Escape => {
+                self.label_editor = None;
+                self.status = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes the circuit as Verilog and BLIF next to the circuit file, with a module named after
+    /// the file.
+    fn export_netlist(&mut self) {
+        let netlist = Netlist::new(&self.circuit);
+        let module = self
+            .file_path
+            .file_stem()
+            .map_or("circuit".into(), |stem| stem.to_string_lossy());
+        let verilog = self.file_path.with_extension("v");
+        let blif = self.file_path.with_extension("blif");
+        let result = File::create(&verilog)
+            .and_then(|file| netlist.write_verilog(BufWriter::new(file), &module))
+            .and_then(|()| File::create(&blif))
+            .and_then(|file| netlist.write_blif(BufWriter::new(file), &module));
+        self.status = Some(match result {
+            Ok(()) => format!(
+                "Exported netlists to {} and {}",
+                verilog.display(),
+                blif.display()
+            ),
+            Err(err) => format!("Failed to export netlists: {:#}", err),
+        });
+    }
+
+    /// Writes the graph of clusters as DOT next to the circuit file, with the power states that
+    /// are shown.
+    fn export_cluster_graph(&mut self) {
+        let graph = ClusterGraph::new(&self.circuit);
+        let name = self
+            .file_path
+            .file_stem()
+            .map_or("circuit".into(), |stem| stem.to_string_lossy());
+        let path = self.file_path.with_extension("dot");
+        // A copy, so that the worker doesn't wait on the file.
+        let snapshot = self.worker.snapshot().clone();
+        let result = File::create(&path)
+            .and_then(|file| graph.write_dot(BufWriter::new(file), &name, Some(&snapshot)));
+        self.status = Some(match result {
+            Ok(()) => format!("Exported the cluster graph to {}", path.display()),
+            Err(err) => format!("Failed to export {}: {:#}", path.display(), err),
+        });
+    }
+
+    /// Writes the truth table of the switches and lamps in the selection (or all of them) as CSV
+    /// and Markdown next to the circuit file. Every row runs the simulation, so the table is made
+    /// on a thread of its own, from a copy of the circuit.
+    fn export_truth_table(&mut self) {
+        if self.tabulating.is_some() {
+            self.status = Some("Still making the last truth table".into());
+            return;
+        }
+        let mut ports = self.circuit.ports();
+        if self.cursor_manager.selection().is_some() {
+            let selected = |port: &Port| self.cursor_manager.selection_contains(port.position);
+            ports.inputs.retain(selected);
+            ports.outputs.retain(selected);
+        }
+        let circuit = match self.circuit.duplicate() {
+            Ok(circuit) => circuit,
+            Err(err) => {
+                self.status = Some(format!("Failed to make a truth table: {:#}", err));
+                return;
+            }
+        };
+        self.status = Some(format!(
+            "Making a truth table of {} inputs and {} outputs...",
+            ports.inputs.len(),
+            ports.outputs.len()
+        ));
+        let file_path = self.file_path.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The window may have closed in the meantime.
+            sender
+                .send(write_truth_table(&circuit, &ports, &file_path))
+                .ok();
+        });
+        self.tabulating = Some(receiver);
+    }
+
+    /// Reads a Yosys JSON or BLIF netlist next to the circuit file, and starts pasting the
+    /// circuit built from it.
+    fn import_netlist(&mut self) {
+        let path = ["json", "blif"]
+            .iter()
+            .map(|extension| self.file_path.with_extension(extension))
+            .find(|path| path.exists());
+        let path = match path {
+            Some(path) => path,
+            None => {
+                self.status = Some(format!(
+                    "No netlist to import; expected {} or {}",
+                    self.file_path.with_extension("json").display(),
+                    self.file_path.with_extension("blif").display()
+                ));
+                return;
+            }
+        };
+        let result = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| {
+                let format = import::Format::from_path(&path).expect("known extension");
+                import::import(&text, format)
+            });
+        self.status = Some(match result {
+            Ok(imported) => {
+                self.cursor_manager
+                    .start_paste(imported.schematic, &self.circuit);
+                format!(
+                    "Imported {}: {}",
+                    path.display(),
+                    imported.timing.describe()
+                )
+            }
+            Err(err) => format!("Failed to import {}: {:#}", path.display(), err),
+        });
+    }
+
+    /// Rotates or mirrors the selection if there is one, or else the component under the cursor.
+    fn transform(&mut self, transform: Transform) {
+        let result = match self.cursor_manager.selection() {
+            Some((min, max)) => self
+                .circuit
+                .transform_region(min, max, transform)
+                .map(|selection| self.cursor_manager.set_selection(Some(selection))),
+            None => self
+                .circuit
+                .transform_component(self.viewport.cursor().tile(), transform),
+        };
+        if let Err(err) = result {
+            self.status = Some(format!("Cannot transform: {:#}", err));
+        }
+    }
+
+    /// Selects the next chip to place, after the current one.
+    fn next_chip(&mut self) {
+        let current = self.cursor_manager.place_type().chip_id();
+        let chips = self.circuit.chips();
+        let next = match current {
+            Some(current) => chips
+                .range(current + 1..)
+                .next()
+                .or_else(|| chips.iter().next()),
+            None => chips.iter().next(),
+        };
+        match next {
+            Some((&id, definition)) => {
+                self.status = Some(format!("Placing {}", definition.name()));
+                self.cursor_manager
+                    .set_place_type(ComponentType::Chip(id), &self.circuit);
+            }
+            None => {
+                self.status = Some("No chips defined; select an area and press Ctrl+G".to_string());
+            }
+        }
+    }
+
+    fn define_chip(&mut self) {
+        let (min, max) = match self.cursor_manager.selection() {
+            Some(x) => x,
+            None => return,
+        };
+        match self.circuit.define_chip(min, max) {
+            Ok(id) => {
+                let name = self.circuit.chips()[&id].name();
+                self.status = Some(format!("Defined {}", name));
+                self.cursor_manager
+                    .set_place_type(ComponentType::Chip(id), &self.circuit);
+            }
+            Err(err) => {
+                self.status = Some(format!("Cannot make a chip: {:#}", err));
+            }
+        }
+    }
+
+    /// How many ticks a single key press moves through history.
+    fn history_step(&self) -> i64 {
+        if self.modifiers.shift() {
+            100
+        } else {
+            1
+        }
+    }
+
+    /// Moves through history by `offset` ticks, pausing the simulation so that the viewed tick
+    /// stays put until it is resumed.
+    fn step_history(&mut self, offset: i64) {
+        self.tick_scheduler.set_mode(TickMode::Paused);
+        self.worker.seek_by(offset);
+    }
+
+    /// Lets the simulation carry on after a breakpoint fired.
+    fn resume_from_breakpoint(&mut self) {
+        if self.breakpoint_hit.is_some() {
+            self.worker.clear_breakpoint_hit();
+            self.cursor_manager.set_highlight(None);
+        }
+    }
+
+    /// Starts editing the chip under the cursor, in place of the current circuit.
+    fn enter_chip(&mut self) {
+        let id = match self.circuit.component_at(self.viewport.cursor().tile()) {
+            Some(ComponentType::Chip(id)) => id,
+            _ => return,
+        };
+        match self.circuit.edit_chip(id) {
+            Ok(inner) => {
+                // Keep the power states of the outer circuit for when the chip is left.
+                self.worker.sync(&mut self.circuit);
+                self.circuit.restore_simulation(self.worker.fetch());
+                let outer = std::mem::replace(&mut self.circuit, inner);
+                self.chip_stack.push((outer, id));
+                self.breakpoints.clear();
+                self.probes.clear();
+                self.label_editor = None;
+                self.cursor_manager.set_selection(None);
+                self.status = None;
+            }
+            Err(err) => {
+                self.status = Some(format!("Cannot edit chip: {:#}", err));
+            }
+        }
+    }
+
+    /// Finishes editing the current chip, updating every instance of it in the outer circuit.
+    fn leave_chip(&mut self) {
+        let (mut outer, id) = match self.chip_stack.pop() {
+            Some(x) => x,
+            None => return,
+        };
+        match outer.finish_chip_edit(id, &self.circuit) {
+            Ok(_) => {
+                self.circuit = outer;
+                self.breakpoints.clear();
+                self.probes.clear();
+                self.label_editor = None;
+                self.cursor_manager.set_selection(None);
+                self.cursor_manager
+                    .set_place_type(ComponentType::Pin, &self.circuit);
+                self.status = None;
+            }
+            Err(err) => {
+                self.chip_stack.push((outer, id));
+                self.status = Some(format!("Cannot finish editing chip: {:#}", err));
+            }
+        }
+    }
+
+    fn load(&mut self) {
+        let result = File::open(&self.file_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Circuit::load(BufReader::new(file)));
+        self.status = Some(match result {
+            Ok(circuit) => {
+                self.circuit = circuit;
+                self.chip_stack.clear();
+                self.breakpoints.clear();
+                self.probes.clear();
+                self.label_editor = None;
+                format!("Opened {}", self.file_path.display())
+            }
+            Err(err) => format!("Failed to open {}: {:#}", self.file_path.display(), err),
+        });
+    }
+
     fn update(&mut self) {
         let now = Instant::now();
         let dt = now - self.last_update;
         self.last_update = now;
 
+        // Edits go to the worker before the ticks that follow them.
+        self.worker.sync(&mut self.circuit);
+        if let Some(breakpoints) = self.breakpoints.resolve(&self.circuit) {
+            self.worker.set_breakpoints(breakpoints);
+        }
+        if let Some(probes) = self.probes.resolve(&self.circuit) {
+            self.worker.set_probes(probes);
+        }
+
+        let hit = self.worker.snapshot().breakpoint_hit();
+        if hit != self.breakpoint_hit {
+            if let Some(hit) = hit {
+                self.tick_scheduler.set_mode(TickMode::Paused);
+                self.cursor_manager
+                    .set_highlight(self.breakpoints.position(hit));
+                self.status = Some(format!(
+                    "Breakpoint {} hit after tick {}",
+                    hit.breakpoint + 1,
+                    hit.tick
+                ));
+            }
+            self.breakpoint_hit = hit;
+        }
+        if self.tick_scheduler.mode() != TickMode::Paused {
+            self.resume_from_breakpoint();
+        }
+
+        self.worker
+            .set_unlimited(self.tick_scheduler.mode() == TickMode::Unlimited);
+        self.worker.tick(self.tick_scheduler.run(dt));
+
+        if let Some(settling) = &self.settling {
+            if let Ok((ticks, settled)) = settling.try_recv() {
+                self.settling = None;
+                self.status = Some(if settled {
+                    format!("Settled after {} ticks", ticks)
+                } else {
+                    format!("Did not settle within {} ticks", ticks)
+                });
+            }
+        }
+        if let Some(tabulating) = &self.tabulating {
+            let status = match tabulating.try_recv() {
+                Ok(status) => Some(status),
+                Err(TryRecvError::Empty) => None,
+                // The thread panicked.
+                Err(TryRecvError::Disconnected) => Some("Failed to make a truth table".into()),
+            };
+            if status.is_some() {
+                self.tabulating = None;
+                self.status = status;
+            }
+        }
+
+        let tick_count = self.worker.snapshot().tick_count();
+        self.tick_counter
+            .add(tick_count.saturating_sub(self.last_tick_count) as usize);
+        self.last_tick_count = tick_count;
+
         self.cursor_manager
             .update(&mut self.viewport, &self.circuit);
         self.viewport.update(dt);
@@ -358,7 +1136,10 @@ impl State {
         let mut encoder = self.gfx.device.create_command_encoder(&Default::default());
 
         {
-            self.circuit.draw(
+            self.worker.sync(&mut self.circuit);
+            self.circuit_renderer
+                .update(&mut self.circuit, &self.worker.snapshot());
+            self.circuit_renderer.draw(
                 &self.viewport,
                 &mut encoder,
                 &frame_view,
@@ -370,9 +1151,20 @@ impl State {
                 &frame_view,
                 &self.depth_texture_view,
             );
+            let viewed_tick = self.worker.snapshot().viewed_tick();
+            self.timing_diagram
+                .update(&self.worker, &self.probes, viewed_tick);
+            self.timing_diagram
+                .draw(&mut encoder, &frame_view, &self.depth_texture_view);
         }
 
         let size = self.gfx.window.inner_size();
+        labels::queue_labels(
+            &mut self.glyph_brush,
+            &self.circuit,
+            &self.viewport,
+            self.label_editor.as_ref(),
+        );
         self.glyph_brush.queue(Section {
             screen_position: (0.0, 0.0),
             bounds: (size.width as f32 / 2.0, size.height as f32),
@@ -381,6 +1173,7 @@ impl State {
                 .with_scale(18.0)],
             ..Default::default()
         });
+        self.timing_diagram.queue_text(&mut self.glyph_brush);
         if self.draw_help {
             self.glyph_brush.queue(Section {
                 screen_position: (size.width as f32 / 2.0, 0.0),
@@ -413,18 +1206,102 @@ impl State {
 
     fn debug_text(&self) -> String {
         let fps = self.frame_counter.rate();
+        let tps = self.tick_counter.rate();
+        let tick_mode = match self.tick_scheduler.mode() {
+            TickMode::Running => format!("target {:.0}", self.tick_scheduler.tick_rate()),
+            TickMode::Paused => "paused".to_string(),
+            TickMode::Unlimited => "unlimited".to_string(),
+        };
         let cursor_pos = <(f32, f32)>::from(self.viewport.cursor().screen_position);
         let world_pos = <(f32, f32)>::from(self.viewport.cursor().world_position);
         let cursor_tile = <(i32, i32)>::from(self.viewport.cursor().tile());
         let tile_debug_info = self.circuit.tile_debug_info(self.viewport.cursor().tile());
 
+        let status = self.status.as_deref().unwrap_or_default();
+        let tick = {
+            let snapshot = self.worker.snapshot();
+            match snapshot.viewed_tick() {
+                Some(viewed) => format!(
+                    "{} of {} (rewound, history from {})",
+                    viewed,
+                    snapshot.tick_count(),
+                    snapshot.oldest_tick(),
+                ),
+                None => snapshot.tick_count().to_string(),
+            }
+        };
+        let activity = {
+            let snapshot = self.worker.snapshot();
+            match snapshot.activity() {
+                Activity::Unsettled => "unsettled".to_string(),
+                Activity::Stable { since } => format!("stable since tick {}", since),
+                Activity::Oscillating { period, since } => {
+                    let clusters = snapshot.oscillating_clusters();
+                    const SHOWN: usize = 8;
+                    let mut list = clusters
+                        .iter()
+                        .take(SHOWN)
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    if clusters.len() > SHOWN {
+                        list.push_str(", ...");
+                    }
+                    format!(
+                        "oscillating with period {} since tick {}\n    {} clusters: {}",
+                        period,
+                        since,
+                        clusters.len(),
+                        list,
+                    )
+                }
+            }
+        };
+        let editing = match self.chip_stack.last() {
+            Some((outer, id)) => format!("Editing {}\n", outer.chips()[id].name()),
+            None => String::new(),
+        };
+
+        let breakpoints = self.breakpoints.describe(&self.circuit);
+        let probes = self.probes.describe(&self.circuit, &self.worker.snapshot());
+        let forced: String = self
+            .circuit
+            .forced_tiles()
+            .into_iter()
+            .map(|(cluster, value, position)| {
+                format!(
+                    "Forced {}: {:?} (cluster {})\n",
+                    if value { "high" } else { "low" },
+                    <(i32, i32)>::from(position),
+                    cluster,
+                )
+            })
+            .collect();
+
         format!(
             "FPS: {:.0}\n\
+            TPS: {:.0} ({})\n\
+            Tick: {}\n\
+            Activity: {}\n\
             Cursor: {:.0?}\n\
             World: {:.2?}\n\
             Tile: {:?}\n\
-            {}",
-            fps, cursor_pos, world_pos, cursor_tile, tile_debug_info,
+            {}\n\
+            {}{}{}{}{}",
+            fps,
+            tps,
+            tick_mode,
+            tick,
+            activity,
+            cursor_pos,
+            world_pos,
+            cursor_tile,
+            tile_debug_info,
+            editing,
+            breakpoints,
+            probes,
+            forced,
+            status,
         )
     }
 
@@ -435,9 +1312,44 @@ impl State {
     }
 }
 
-fn main() -> anyhow::Result<()> {
+/// Makes the truth table of `ports` and writes it next to `file_path` in every format. Returns
+/// the status to show.
+fn write_truth_table(circuit: &Circuit, ports: &Ports, file_path: &Path) -> String {
+    let table =
+        match TruthTable::generate(circuit, &ports.inputs, &ports.outputs, TRUTH_TABLE_BUDGET) {
+            Ok(table) => table,
+            Err(err) => return format!("Failed to make a truth table: {:#}", err),
+        };
+    let mut paths = Vec::new();
+    for format in truth_table::Format::ALL {
+        let path = file_path.with_extension(format.extension());
+        let result = File::create(&path).and_then(|file| table.write(BufWriter::new(file), format));
+        if let Err(err) = result {
+            return format!("Failed to export {}: {:#}", path.display(), err);
+        }
+        paths.push(path.display().to_string());
+    }
+    let mut status = format!(
+        "Exported a truth table of {} inputs and {} outputs to {}",
+        ports.inputs.len(),
+        ports.outputs.len(),
+        paths.join(" and ")
+    );
+    let unsettled = table.unsettled();
+    if unsettled > 0 {
+        status += &format!("; {} rows never settled", unsettled);
+    }
+    status
+}
+
+fn main() -> anyhow::Result<ExitCode> {
     env_logger::init();
 
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    if cli::is_command(&args) {
+        return cli::run(&args);
+    }
+
     // The window decorations provided by winit when using wayland do not match the native system
     // theme, so fallback to X11 via XWayland if possible.
     // std::env::set_var("WINIT_UNIX_BACKEND", "x11");
@@ -447,7 +1359,13 @@ fn main() -> anyhow::Result<()> {
         .with_title("FlipFlop")
         .build(&event_loop)?;
 
-    let mut state = block_on(State::new(window))?;
+    let file_path = args
+        .into_iter()
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_FILE_PATH));
+
+    let mut state = block_on(State::new(window, file_path))?;
 
     event_loop.run(move |event, _, control_flow| {
         match event {
@@ -0,0 +1,391 @@
+//! Exporting a circuit as a netlist for other EDA tools, as structural Verilog or BLIF.
+//!
+//! The netlist follows the simulation tick for tick. Every cluster is a net, driven by the OR of
+//! the registers that lead into it. A flip is a registered inverter of the net at its input and
+//! a flop is a plain register of it, all clocked by a single `tick` input, one rising edge per
+//! tick. Every register starts out at 0, like the clusters of a new simulation.
+//!
+//! Switches become inputs and lamps become outputs, named after their labels (see
+//! [`Circuit::ports`]). A switch is a flop that is powered on the tick after it is switched, so
+//! each input goes through a register of its own before it reaches the flop. Names are changed
+//! where needed to make them valid, unique identifiers.
+//!
+//! Forced clusters are exported with the value that their drivers give them, since forcing is
+//! only an aid for debugging.
+
+use crate::circuit::Circuit;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::{self, Write};
+
+// The input that clocks every register.
+const CLOCK: &str = "tick";
+// The Verilog modules that registers are instances of.
+const FLIP_MODULE: &str = "flipflop_flip";
+const FLOP_MODULE: &str = "flipflop_flop";
+
+// Words that can't be used as names, on top of the ones used by the netlist itself.
+const RESERVED: &[&str] = &[
+    "always",
+    "assign",
+    "begin",
+    "buf",
+    "end",
+    "endmodule",
+    "initial",
+    "input",
+    "module",
+    "not",
+    "or",
+    "output",
+    "reg",
+    "wire",
+];
+
+pub struct Netlist {
+    // Port names with their clusters: the one that a switch powers, or the one of a lamp.
+    inputs: Vec<(String, u32)>,
+    outputs: Vec<(String, u32)>,
+    registers: Vec<Register>,
+    // Every cluster that is used, in order.
+    nets: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Source {
+    Net(u32),
+    Input(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Register {
+    output: u32,
+    source: Source,
+    inverted: bool,
+}
+
+impl Netlist {
+    /// Reads the netlist out of the simulation of `circuit`.
+    pub fn new(circuit: &Circuit) -> Self {
+        let mut used = HashSet::new();
+        let mut name = |name: &str| unique_identifier(name, &mut used);
+        let ports = circuit.ports();
+        let inputs: Vec<(String, u32)> = ports
+            .inputs
+            .iter()
+            .filter_map(|port| {
+                let cluster = circuit.switch_input_cluster(port.position)?;
+                Some((name(&port.name), cluster))
+            })
+            .collect();
+        let outputs: Vec<(String, u32)> = ports
+            .outputs
+            .iter()
+            .filter_map(|port| Some((name(&port.name), circuit.tile_cluster(port.position)?)))
+            .collect();
+
+        let simulation = circuit.simulation();
+        let flips = simulation.flips().map(|(input, output)| Register {
+            output,
+            source: Source::Net(input),
+            inverted: true,
+        });
+        let flops = simulation.flops().map(|(input, output)| Register {
+            output,
+            source: Source::Net(input),
+            inverted: false,
+        });
+        let switches = inputs
+            .iter()
+            .enumerate()
+            .map(|(index, &(_, output))| Register {
+                output,
+                source: Source::Input(index),
+                inverted: false,
+            });
+        let mut registers: Vec<Register> = flips.chain(flops).chain(switches).collect();
+        registers.sort();
+
+        let mut nets = BTreeSet::new();
+        for register in &registers {
+            nets.insert(register.output);
+            if let Source::Net(input) = register.source {
+                nets.insert(input);
+            }
+        }
+        nets.extend(outputs.iter().map(|&(_, cluster)| cluster));
+
+        Self {
+            inputs,
+            outputs,
+            registers,
+            nets: nets.into_iter().collect(),
+        }
+    }
+
+    /// Writes the netlist as a Verilog module named `module`, along with the modules of the
+    /// two kinds of registers, and flushes `writer`.
+    pub fn write_verilog<W: Write>(&self, mut writer: W, module: &str) -> io::Result<()> {
+        let module = module_name(module);
+        writeln!(
+            writer,
+            "// Exported from FlipFlop {}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(writer)?;
+        for (name, operator) in [(FLIP_MODULE, "~"), (FLOP_MODULE, "")] {
+            writeln!(writer, "module {}(input clk, input d, output reg q);", name)?;
+            writeln!(writer, "  initial q = 1'b0;")?;
+            writeln!(writer, "  always @(posedge clk) q <= {}d;", operator)?;
+            writeln!(writer, "endmodule")?;
+            writeln!(writer)?;
+        }
+
+        let ports: Vec<&str> = std::iter::once(CLOCK)
+            .chain(self.inputs.iter().map(|(name, _)| name.as_str()))
+            .chain(self.outputs.iter().map(|(name, _)| name.as_str()))
+            .collect();
+        writeln!(writer, "module {}({});", module, ports.join(", "))?;
+        writeln!(writer, "  input {};", CLOCK)?;
+        for (name, _) in &self.inputs {
+            writeln!(writer, "  input {};", name)?;
+        }
+        for (name, _) in &self.outputs {
+            writeln!(writer, "  output {};", name)?;
+        }
+        for &net in &self.nets {
+            writeln!(writer, "  wire {};", net_name(net))?;
+        }
+        for index in 0..self.registers.len() {
+            writeln!(writer, "  wire {};", register_name(index))?;
+        }
+
+        for (index, register) in self.registers.iter().enumerate() {
+            writeln!(
+                writer,
+                "  {} r{} (.clk({}), .d({}), .q({}));",
+                if register.inverted {
+                    FLIP_MODULE
+                } else {
+                    FLOP_MODULE
+                },
+                index,
+                CLOCK,
+                self.source_name(register.source),
+                register_name(index),
+            )?;
+        }
+        for (net, drivers) in self.drivers() {
+            match drivers.len() {
+                0 => writeln!(writer, "  assign {} = 1'b0;", net_name(net))?,
+                1 => writeln!(writer, "  assign {} = {};", net_name(net), drivers[0])?,
+                _ => writeln!(writer, "  or ({}, {});", net_name(net), drivers.join(", "))?,
+            }
+        }
+        for (name, net) in &self.outputs {
+            writeln!(writer, "  assign {} = {};", name, net_name(*net))?;
+        }
+        writeln!(writer, "endmodule")?;
+        writer.flush()
+    }
+
+    /// Writes the netlist as a BLIF model named `model`, with every register as a latch on the
+    /// rising edge of the clock, and flushes `writer`.
+    pub fn write_blif<W: Write>(&self, mut writer: W, model: &str) -> io::Result<()> {
+        writeln!(
+            writer,
+            "# Exported from FlipFlop {}.",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(writer, ".model {}", module_name(model))?;
+        write!(writer, ".inputs {}", CLOCK)?;
+        for (name, _) in &self.inputs {
+            write!(writer, " {}", name)?;
+        }
+        writeln!(writer)?;
+        write!(writer, ".outputs")?;
+        for (name, _) in &self.outputs {
+            write!(writer, " {}", name)?;
+        }
+        writeln!(writer)?;
+
+        for (index, register) in self.registers.iter().enumerate() {
+            let source = self.source_name(register.source);
+            let input = if register.inverted {
+                let inverted = format!("d{}", index);
+                writeln!(writer, ".names {} {}", source, inverted)?;
+                writeln!(writer, "0 1")?;
+                inverted
+            } else {
+                source
+            };
+            writeln!(
+                writer,
+                ".latch {} {} re {} 0",
+                input,
+                register_name(index),
+                CLOCK
+            )?;
+        }
+        for (net, drivers) in self.drivers() {
+            // One row per driver, since any one of them powers the net. A net without any
+            // rows is always 0.
+            writeln!(writer, ".names {} {}", drivers.join(" "), net_name(net))?;
+            for row in 0..drivers.len() {
+                let inputs: String = (0..drivers.len())
+                    .map(|column| if column == row { '1' } else { '-' })
+                    .collect();
+                writeln!(writer, "{} 1", inputs)?;
+            }
+        }
+        for (name, net) in &self.outputs {
+            writeln!(writer, ".names {} {}", net_name(*net), name)?;
+            writeln!(writer, "1 1")?;
+        }
+        writeln!(writer, ".end")?;
+        writer.flush()
+    }
+
+    /// The outputs of the registers that drive each net.
+    fn drivers(&self) -> BTreeMap<u32, Vec<String>> {
+        let mut drivers: BTreeMap<u32, Vec<String>> =
+            self.nets.iter().map(|&net| (net, Vec::new())).collect();
+        for (index, register) in self.registers.iter().enumerate() {
+            drivers
+                .entry(register.output)
+                .or_default()
+                .push(register_name(index));
+        }
+        drivers
+    }
+
+    fn source_name(&self, source: Source) -> String {
+        match source {
+            Source::Net(net) => net_name(net),
+            Source::Input(index) => self.inputs[index].0.clone(),
+        }
+    }
+}
+
+fn net_name(cluster: u32) -> String {
+    format!("n{}", cluster)
+}
+
+fn register_name(index: usize) -> String {
+    format!("q{}", index)
+}
+
+/// `name` as a valid identifier that isn't in `used`, which it is then added to.
+fn unique_identifier(name: &str, used: &mut HashSet<String>) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        identifier.insert(0, '_');
+    }
+    // Keeps clear of the names of nets, registers and inverters.
+    let mut chars = identifier.chars();
+    if matches!(chars.next(), Some('n' | 'q' | 'd' | 'r'))
+        && chars.as_str().chars().all(|c| c.is_ascii_digit())
+    {
+        identifier.push('_');
+    }
+    let reserved =
+        |name: &str| RESERVED.contains(&name) || [CLOCK, FLIP_MODULE, FLOP_MODULE].contains(&name);
+    let mut unique = identifier.clone();
+    let mut suffix = 1;
+    while reserved(&unique) || used.contains(&unique) {
+        suffix += 1;
+        unique = format!("{}_{}", identifier, suffix);
+    }
+    used.insert(unique.clone());
+    unique
+}
+
+fn module_name(name: &str) -> String {
+    unique_identifier(name, &mut HashSet::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Netlist, Source};
+    use crate::circuit::tests::place_chain;
+    use crate::circuit::{Circuit, ComponentType};
+    use crate::direction::Direction;
+    use crate::schematic::Anchor;
+    use glam::IVec2;
+
+    /// Switch, flip, flop and lamp in a row, with a second lamp on the output of the flip. The
+    /// switch is a flop of its own.
+    fn chain() -> Circuit {
+        let mut circuit = Circuit::new();
+        place_chain(&mut circuit, ComponentType::Switch, 0);
+        circuit.place_component(ComponentType::Lamp, IVec2::new(3, 2), Direction::East);
+        assert!(circuit.place_wire(IVec2::new(3, 0), IVec2::new(3, 2)));
+        circuit.set_label(Anchor::Tile(IVec2::new(0, 0)), "enable");
+        circuit.set_label(Anchor::Tile(IVec2::new(6, 0)), "out put");
+        circuit
+    }
+
+    #[test]
+    fn writes_verilog_and_blif() {
+        let netlist = Netlist::new(&chain());
+        let mut verilog = Vec::new();
+        netlist.write_verilog(&mut verilog, "my chain").unwrap();
+        let verilog = String::from_utf8(verilog).unwrap();
+        assert!(verilog.contains("module my_chain(tick, enable, out0, out_put);\n"));
+        assert!(verilog.contains("  input enable;\n"));
+        assert!(verilog.contains("  output out_put;\n"));
+        assert_eq!(verilog.matches("  flipflop_flip r").count(), 1);
+        assert_eq!(verilog.matches("  flipflop_flop r").count(), 3);
+
+        let mut blif = Vec::new();
+        netlist.write_blif(&mut blif, "chain").unwrap();
+        let blif = String::from_utf8(blif).unwrap();
+        assert!(blif.contains(".inputs tick enable\n.outputs out0 out_put\n"));
+        assert!(blif.contains(".latch enable q"));
+        assert_eq!(blif.matches(".latch ").count(), 4);
+        assert!(blif.ends_with(".end\n"));
+    }
+
+    #[test]
+    fn matches_simulation() {
+        let mut circuit = chain();
+        let netlist = Netlist::new(&circuit);
+        let mut registers = vec![false; netlist.registers.len()];
+        let mut switched = false;
+        for tick in 0..20 {
+            if tick % 7 == 3 {
+                circuit.interact(IVec2::new(0, 0));
+                switched = !switched;
+            }
+            circuit.tick();
+
+            // One rising edge of the clock: every register takes in its source.
+            let nets = |registers: &[bool], net: u32| {
+                netlist
+                    .registers
+                    .iter()
+                    .zip(registers)
+                    .any(|(register, &value)| register.output == net && value)
+            };
+            registers = netlist
+                .registers
+                .iter()
+                .map(|register| match register.source {
+                    Source::Net(net) => nets(&registers, net) != register.inverted,
+                    Source::Input(..) => switched,
+                })
+                .collect();
+            for &net in &netlist.nets {
+                assert_eq!(
+                    nets(&registers, net),
+                    circuit.simulation().is_powered(net),
+                    "net {} after tick {}",
+                    net,
+                    tick
+                );
+            }
+        }
+    }
+}
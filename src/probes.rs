@@ -0,0 +1,155 @@
+//! Named probes on tiles, whose values the simulation records on every tick.
+//!
+//! Like breakpoints, probes are kept on tiles and looked up again whenever the circuit changes,
+//! so that they stay on the same wire when its cluster is renumbered by an edit.
+
+use crate::circuit::Circuit;
+use crate::cluster_cache::ClusterCache;
+use crate::simulation::{Snapshot, Trace};
+use crate::vcd;
+use glam::IVec2;
+use std::io::{self, Write};
+
+// The ID of every probe, with the cluster that it is on.
+type Resolved = Vec<(u32, Option<u32>)>;
+
+pub struct Probe {
+    /// Stays the same for as long as the probe exists, to find its signal in a [`Trace`].
+    pub id: u32,
+    pub name: String,
+    pub position: IVec2,
+    /// Whether it is shown in the timing diagram.
+    pub shown: bool,
+}
+
+#[derive(Default)]
+pub struct Probes {
+    list: Vec<Probe>,
+    next_id: u32,
+    sent: ClusterCache<Resolved>,
+}
+
+impl Probes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn list(&self) -> &[Probe] {
+        &self.list
+    }
+
+    /// Adds a probe on the tile at `position`, or removes the one that is there. New probes
+    /// are named after the order they were added in.
+    pub fn toggle(&mut self, position: IVec2) {
+        match self
+            .list
+            .iter()
+            .position(|probe| probe.position == position)
+        {
+            Some(index) => {
+                self.list.remove(index);
+            }
+            None => {
+                self.add(format!("probe{}", self.next_id), position);
+            }
+        }
+    }
+
+    pub fn add(&mut self, name: String, position: IVec2) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.list.push(Probe {
+            id,
+            name,
+            position,
+            shown: true,
+        });
+        id
+    }
+
+    /// Shows or hides the probe on the tile at `position` in the timing diagram.
+    pub fn toggle_shown(&mut self, position: IVec2) {
+        if let Some(probe) = self
+            .list
+            .iter_mut()
+            .find(|probe| probe.position == position)
+        {
+            probe.shown = !probe.shown;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    /// Looks up the cluster of every probe. Returns the probes for the simulation of `circuit`
+    /// if they differ from the ones that were returned last time.
+    pub fn resolve(&mut self, circuit: &Circuit) -> Option<Resolved> {
+        let list = &self.list;
+        self.sent.resolve(circuit, |cluster| {
+            list.iter()
+                .map(|probe| (probe.id, cluster(probe.position)))
+                .collect()
+        })
+    }
+
+    /// Writes the recorded values of every probe as a Value Change Dump.
+    pub fn write_vcd<W: Write>(&self, writer: W, trace: &Trace) -> io::Result<()> {
+        let names: Vec<(u32, &str)> = self
+            .list
+            .iter()
+            .map(|probe| (probe.id, probe.name.as_str()))
+            .collect();
+        vcd::write_vcd(writer, trace, &names)
+    }
+
+    /// One line per probe with its current value, for the debug overlay.
+    pub fn describe(&self, circuit: &Circuit, snapshot: &Snapshot) -> String {
+        let mut text = String::new();
+        for probe in &self.list {
+            let value = match circuit.tile_cluster(probe.position) {
+                Some(cluster) if cluster < snapshot.num_clusters() => {
+                    if snapshot.is_powered(cluster) {
+                        "1"
+                    } else {
+                        "0"
+                    }
+                }
+                _ => "x",
+            };
+            text.push_str(&format!(
+                "Probe {} {:?}: {}{}\n",
+                probe.name,
+                <(i32, i32)>::from(probe.position),
+                value,
+                if probe.shown { "" } else { " (hidden)" },
+            ));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Probes;
+    use crate::circuit::{Circuit, ComponentType};
+    use crate::direction::Direction;
+    use glam::IVec2;
+
+    #[test]
+    fn follows_tile() {
+        let mut circuit = Circuit::new();
+        circuit.place_wire(IVec2::new(0, 0), IVec2::new(4, 0));
+        let mut probes = Probes::new();
+        probes.toggle(IVec2::new(4, 0));
+        let before = probes.resolve(&circuit).unwrap();
+        assert_eq!(probes.resolve(&circuit), None);
+
+        // Cutting the wire leaves the probe on the far half, whatever its cluster is now.
+        circuit.place_component(ComponentType::Pin, IVec2::new(2, 0), Direction::East);
+        circuit.delete_all_at(IVec2::new(2, 0));
+        let after = probes.resolve(&circuit).unwrap_or(before);
+        assert_eq!(after[0].1, circuit.tile_cluster(IVec2::new(4, 0)));
+        assert_ne!(after[0].1, circuit.tile_cluster(IVec2::new(0, 0)));
+    }
+}
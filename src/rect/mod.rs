@@ -1,6 +1,6 @@
 use crate::direction::Direction;
 use crate::instance::InstanceManager;
-use crate::simulation::Simulation;
+use crate::simulation::Snapshot;
 use crate::viewport::Viewport;
 use crate::GraphicsContext;
 use bitvec::prelude::{BitVec, Lsb0};
@@ -113,6 +113,7 @@ pub struct RectRenderer {
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     cluster_state_buffer: wgpu::Buffer,
+    forced_cluster_buffer: wgpu::Buffer,
     bind_group: wgpu::BindGroup,
     instances: InstanceManager<Instance>,
 }
@@ -144,6 +145,16 @@ impl RectRenderer {
                             },
                             count: None,
                         },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::VERTEX,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
                     ],
                 });
 
@@ -214,20 +225,18 @@ impl RectRenderer {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
+        // One bit per cluster, for the same number of clusters as `cluster_state_buffer`.
+        let forced_cluster_buffer = gfx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("RectRenderer.forced_cluster_buffer"),
+            size: 512 * 4 * 4,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
         let wire_palette_buffer =
             gfx.device
                 .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some("RectRenderer.wire_palette_buffer"),
-                    contents: bytemuck::bytes_of(&WirePalette {
-                        buffer: [
-                            // Normal wire
-                            [0.0, 0.0, 0.0, 1.0],
-                            [1.0, 0.0, 0.0, 1.0],
-                            // Lamp
-                            [0.03, 0.03, 0.03, 1.0],
-                            [1.0, 1.0, 0.0, 1.0],
-                        ],
-                    }),
+                    contents: bytemuck::bytes_of(&WirePalette { buffer: PALETTE }),
                     usage: wgpu::BufferUsages::UNIFORM,
                 });
 
@@ -243,6 +252,10 @@ impl RectRenderer {
                     binding: 1,
                     resource: wire_palette_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: forced_cluster_buffer.as_entire_binding(),
+                },
             ],
         });
 
@@ -254,6 +267,7 @@ impl RectRenderer {
             vertex_buffer,
             index_buffer,
             cluster_state_buffer,
+            forced_cluster_buffer,
             bind_group,
             instances,
         }
@@ -264,12 +278,17 @@ impl RectRenderer {
         Handle { inner }
     }
 
-    pub fn update_cluster_states(&mut self, simulation: &Simulation) {
+    /// Uploads the power states of every cluster, and which ones are forced, which are marked
+    /// with stripes.
+    pub fn update_cluster_states<I>(&mut self, snapshot: &Snapshot, forced: I)
+    where
+        I: IntoIterator<Item = u32>,
+    {
         let mut state_buffer: BitVec<u32, Lsb0> =
-            BitVec::with_capacity(simulation.num_clusters() as usize * 2);
-        for index in 0..simulation.num_clusters() {
-            state_buffer.push(simulation.is_powered(index));
-            state_buffer.push(simulation.was_powered(index));
+            BitVec::with_capacity(snapshot.num_clusters() as usize * 2);
+        for index in 0..snapshot.num_clusters() {
+            state_buffer.push(snapshot.is_powered(index));
+            state_buffer.push(snapshot.was_powered(index));
         }
 
         self.gfx.queue.write_buffer(
@@ -277,6 +296,20 @@ impl RectRenderer {
             0,
             bytemuck::cast_slice(state_buffer.as_raw_slice()),
         );
+
+        let mut forced_buffer: BitVec<u32, Lsb0> =
+            BitVec::repeat(false, snapshot.num_clusters() as usize);
+        for id in forced {
+            // The snapshot can be older than the clusters that were just forced.
+            if let Some(mut bit) = forced_buffer.get_mut(id as usize) {
+                *bit = true;
+            }
+        }
+        self.gfx.queue.write_buffer(
+            &self.forced_cluster_buffer,
+            0,
+            bytemuck::cast_slice(forced_buffer.as_raw_slice()),
+        );
     }
 
     pub fn draw(
@@ -352,6 +385,28 @@ impl Default for Color {
 }
 
 impl Color {
+    /// The color that the rect is drawn in while the clusters are as in `snapshot`, like the
+    /// shader picks it, but without marking forced clusters.
+    pub fn resolve(&self, snapshot: &Snapshot) -> Vec4 {
+        match *self {
+            Self::Fixed(color) => color,
+            Self::Wire {
+                cluster_index,
+                palette_index,
+                delayed,
+                inverted,
+            } => {
+                let is_on = cluster_index < snapshot.num_clusters()
+                    && match delayed {
+                        true => snapshot.was_powered(cluster_index),
+                        false => snapshot.is_powered(cluster_index),
+                    };
+                let index = (palette_index << 1) as usize | (is_on ^ inverted) as usize;
+                PALETTE[index].into()
+            }
+        }
+    }
+
     fn color(&self) -> Vec4 {
         match self {
             &Self::Fixed(color) => color,
@@ -401,6 +456,16 @@ const LAMP_Z_INDEX: u8 = 6;
 pub const WIRE_PALETTE: u32 = 0;
 pub const LAMP_PALETTE: u32 = 1;
 
+// The off and on colors of each palette.
+const PALETTE: [[f32; 4]; 4] = [
+    // Normal wire
+    [0.0, 0.0, 0.0, 1.0],
+    [1.0, 0.0, 0.0, 1.0],
+    // Lamp
+    [0.03, 0.03, 0.03, 1.0],
+    [1.0, 1.0, 0.0, 1.0],
+];
+
 #[derive(Clone, Copy)]
 pub enum WireConnection {
     Pin,
@@ -482,6 +547,27 @@ impl From<Body> for Rect {
     }
 }
 
+/// The body of a chip, covering `length` tiles to the right of `orientation`.
+pub struct ChipBody {
+    pub position: IVec2,
+    pub orientation: Direction,
+    pub length: i32,
+}
+
+impl From<ChipBody> for Rect {
+    fn from(body: ChipBody) -> Self {
+        let end = body.position + body.orientation.right().offset() * (body.length - 1);
+        let min = body.position.min(end);
+        let max = body.position.max(end);
+        Self {
+            position: min.as_vec2() + Vec2::splat(0.5 - BODY_RADIUS),
+            z_index: BODY_Z_INDEX,
+            size: (max - min).as_vec2() + Vec2::splat(2.0 * BODY_RADIUS),
+            color: Color::Fixed(Vec4::new(0.8, 0.8, 0.8, 1.0)),
+        }
+    }
+}
+
 pub struct Pin {
     pub position: IVec2,
     pub color: Color,
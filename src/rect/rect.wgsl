@@ -11,6 +11,8 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) color: vec4<f32>,
+    @location(1) world_position: vec2<f32>,
+    @location(2) @interpolate(flat) forced: u32,
 };
 
 struct Viewport {
@@ -29,6 +31,13 @@ struct WirePalette {
     buffer: array<vec4<f32>, 2>,
 };
 @group(1) @binding(1) var<uniform> wire_palette: WirePalette;
+struct ForcedClusters {
+    buffer: array<vec4<u32>, 512>,
+};
+@group(1) @binding(2) var<uniform> forced_clusters: ForcedClusters;
+
+// Forced clusters are striped with this color.
+let FORCED_COLOR: vec4<f32> = vec4<f32>(0.0, 0.6, 1.0, 1.0);
 
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
@@ -36,6 +45,8 @@ fn vs_main(in: VertexInput) -> VertexOutput {
 
     let rect_coordinate: vec2<f32> = in.rect_position + in.size * in.position;
     out.position = viewport.view_proj * vec4<f32>(rect_coordinate, in.z_index, 1.0);
+    out.world_position = rect_coordinate;
+    out.forced = 0u;
 
     if (in.cluster_index == 0xffffffffu) {
         out.color = in.color;
@@ -47,6 +58,12 @@ fn vs_main(in: VertexInput) -> VertexOutput {
         let invert: u32 = in.cluster_index & 1u;
 
         out.color = wire_palette.buffer[(in.palette_index << 1u) | (is_on ^ invert)];
+
+        // Delayed rects show the cluster that drives another one, so they aren't marked.
+        let cluster: u32 = in.cluster_index >> 2u;
+        let delayed: u32 = (in.cluster_index >> 1u) & 1u;
+        let is_forced: u32 = (forced_clusters.buffer[cluster >> 7u][(cluster >> 5u) & 3u] >> (cluster & 31u)) & 1u;
+        out.forced = is_forced & (delayed ^ 1u);
     }
     
     return out;
@@ -54,5 +71,8 @@ fn vs_main(in: VertexInput) -> VertexOutput {
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (in.forced != 0u && fract((in.world_position.x + in.world_position.y) * 4.0) < 0.5) {
+        return mix(in.color, FORCED_COLOR, 0.6);
+    }
     return in.color;
 }
@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+pub const DEFAULT_TICK_RATE: f32 = 100.0;
+pub const MIN_TICK_RATE: f32 = 1.0;
+pub const MAX_TICK_RATE: f32 = 100_000.0;
+
+// Upper bound on how far the scheduler tries to catch up after a slow frame, so a single
+// long stall doesn't turn into a burst of thousands of ticks.
+const MAX_BACKLOG: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickMode {
+    /// Tick at the configured tick rate.
+    Running,
+    /// Don't tick, except for requested single steps.
+    Paused,
+    /// Tick as fast as the simulation worker can, which it does on its own.
+    Unlimited,
+}
+
+/// Fixed-timestep scheduler that decides how many simulation ticks to run per frame,
+/// independently of the frame rate.
+pub struct TickScheduler {
+    mode: TickMode,
+    tick_rate: f32,
+    backlog: Duration,
+    pending_steps: usize,
+}
+
+impl TickScheduler {
+    pub fn new() -> Self {
+        Self {
+            mode: TickMode::Running,
+            tick_rate: DEFAULT_TICK_RATE,
+            backlog: Duration::ZERO,
+            pending_steps: 0,
+        }
+    }
+
+    pub fn mode(&self) -> TickMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: TickMode) {
+        self.mode = mode;
+        self.backlog = Duration::ZERO;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        match self.mode {
+            TickMode::Paused => self.set_mode(TickMode::Running),
+            _ => self.set_mode(TickMode::Paused),
+        }
+    }
+
+    pub fn toggle_unlimited(&mut self) {
+        match self.mode {
+            TickMode::Unlimited => self.set_mode(TickMode::Running),
+            _ => self.set_mode(TickMode::Unlimited),
+        }
+    }
+
+    /// Pauses the scheduler (if it isn't already) and queues up a single tick.
+    pub fn step(&mut self) {
+        self.set_mode(TickMode::Paused);
+        self.pending_steps += 1;
+    }
+
+    pub fn tick_rate(&self) -> f32 {
+        self.tick_rate
+    }
+
+    /// Sets the tick rate, clamped to the supported range. Rates that aren't finite are ignored.
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        if !tick_rate.is_finite() {
+            return;
+        }
+        self.tick_rate = tick_rate.clamp(MIN_TICK_RATE, MAX_TICK_RATE);
+    }
+
+    /// Returns how many ticks are due after `dt` of elapsed time. None are in unlimited mode,
+    /// where the worker doesn't wait to be told.
+    pub fn run(&mut self, dt: Duration) -> usize {
+        match self.mode {
+            TickMode::Running => {
+                let interval = Duration::from_secs_f32(1.0 / self.tick_rate);
+                self.backlog = (self.backlog + dt).min(MAX_BACKLOG.max(interval));
+                let mut count = 0;
+                while self.backlog >= interval {
+                    self.backlog -= interval;
+                    count += 1;
+                }
+                count
+            }
+            TickMode::Paused => std::mem::take(&mut self.pending_steps),
+            TickMode::Unlimited => 0,
+        }
+    }
+}
+
+impl Default for TickScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TickMode, TickScheduler, MAX_TICK_RATE, MIN_TICK_RATE};
+    use std::time::Duration;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn caps_backlog() {
+        // A tick every 15.625ms, which is exact in binary.
+        let mut scheduler = TickScheduler::new();
+        scheduler.set_tick_rate(64.0);
+        // A long stall only catches up on the last 250ms.
+        assert_eq!(scheduler.run(Duration::from_secs(10)), 16);
+        assert_eq!(scheduler.run(ms(0)), 0);
+        assert_eq!(scheduler.run(ms(16)), 1);
+    }
+
+    #[test]
+    fn accumulates_fractions() {
+        let mut scheduler = TickScheduler::new();
+        scheduler.set_tick_rate(64.0);
+        let ticks: Vec<usize> = (0..8).map(|_| scheduler.run(ms(10))).collect();
+        assert_eq!(ticks, [0, 1, 0, 1, 1, 0, 1, 1]);
+        // Switching modes drops what was left over.
+        scheduler.set_mode(TickMode::Running);
+        assert_eq!(scheduler.run(ms(15)), 0);
+    }
+
+    #[test]
+    fn clamps_rate() {
+        let mut scheduler = TickScheduler::new();
+        scheduler.set_tick_rate(0.0);
+        assert_eq!(scheduler.tick_rate(), MIN_TICK_RATE);
+        // Ticks slower than the backlog cap still happen.
+        assert_eq!(scheduler.run(ms(999)), 0);
+        assert_eq!(scheduler.run(ms(1)), 1);
+        scheduler.set_tick_rate(1e9);
+        assert_eq!(scheduler.tick_rate(), MAX_TICK_RATE);
+        assert_eq!(scheduler.run(ms(1)), 100);
+        for rate in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            scheduler.set_tick_rate(rate);
+            assert_eq!(scheduler.tick_rate(), MAX_TICK_RATE);
+        }
+        assert_eq!(scheduler.run(ms(1)), 100);
+    }
+
+    #[test]
+    fn steps_while_paused() {
+        let mut scheduler = TickScheduler::new();
+        scheduler.toggle_pause();
+        assert_eq!(scheduler.run(Duration::from_secs(1)), 0);
+        scheduler.step();
+        scheduler.step();
+        assert_eq!(scheduler.run(ms(0)), 2);
+        assert_eq!(scheduler.run(ms(0)), 0);
+        // The worker ticks on its own in unlimited mode.
+        scheduler.toggle_unlimited();
+        assert_eq!(scheduler.run(Duration::from_secs(1)), 0);
+    }
+}
@@ -0,0 +1,600 @@
+//! Plain-data description of a circuit layout, and the FlipFlop file format.
+//!
+//! A [`Schematic`] lists every component, wire segment and label of a circuit without any of the
+//! cluster or rendering state, so it can be written to disk and later rebuilt through the
+//! normal placement paths of [`Circuit`](crate::circuit::Circuit).
+//!
+//! # File format
+//!
+//! Circuit files are UTF-8 text, one record per line. Blank lines and lines starting with `#`
+//! are ignored. The first record is the header, naming the format version:
+//!
+//! ```text
+//! flipflop 1
+//! ```
+//!
+//! It is followed by any number of component and wire records, in any order:
+//!
+//! ```text
+//! component <type> <x> <y> <orientation> [on]
+//! wire <x1> <y1> <x2> <y2>
+//! ```
+//!
+//! Since version 2, chip definitions may come first. Each one is a block of component and wire
+//! records, which may only use chips defined before it:
+//!
+//! ```text
+//! chip <id> <name>
+//! component ...
+//! wire ...
+//! end
+//! ```
+//!
+//! Since version 3, labels may be placed among the component and wire records, either on a
+//! tile or at any point of the board:
+//!
+//! ```text
+//! label <x> <y> <text>
+//! note <x> <y> <text>
+//! ```
+//!
+//! - `<type>` is one of `pin`, `flip`, `flop`, `switch`, `lamp` or `chip:<id>`.
+//! - `<orientation>` is one of `east`, `north`, `west` or `south`.
+//! - The trailing `on` flag is only valid for switches, and marks them as switched on.
+//! - A `label` is on the tile at the integer `<x> <y>`, and there is at most one per tile. A
+//!   `note` is at the point `<x> <y>`, which may have a fractional part. The `<text>` is the
+//!   rest of the line; runs of whitespace in it are read back as a single space.
+//! - Wire endpoints must share either their X or Y coordinate. Each wire record is one segment
+//!   between two components; longer wires are split wherever they connect to a component.
+//!
+//! Readers must reject files with a version newer than the one they support, and records that
+//! are newer than the version in the header.
+
+use crate::circuit::ComponentType;
+use crate::direction::{Direction, Transform};
+use anyhow::{anyhow, bail, ensure, Context};
+use glam::{IVec2, Vec2};
+use std::io::{self, BufRead, Write};
+
+/// The newest file format version that can be read and written.
+pub const FORMAT_VERSION: u32 = 3;
+
+const MAGIC: &str = "flipflop";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schematic {
+    pub components: Vec<PlacedComponent>,
+    pub wires: Vec<PlacedWire>,
+    pub labels: Vec<PlacedLabel>,
+    /// Definitions of the chips that are used, including chips used inside of other chips,
+    /// ordered by ID.
+    pub chips: Vec<SchematicChip>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchematicChip {
+    pub id: u32,
+    pub name: String,
+    pub schematic: Schematic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedComponent {
+    pub ty: ComponentType,
+    pub position: IVec2,
+    pub orientation: Direction,
+    pub switched: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedWire {
+    pub start: IVec2,
+    pub end: IVec2,
+}
+
+/// Text on the board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedLabel {
+    pub anchor: Anchor,
+    pub text: String,
+}
+
+/// Where a label is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    /// On a tile, and on whatever component is placed there.
+    Tile(IVec2),
+    /// At a point of the board, in tiles, not attached to anything.
+    Free(Vec2),
+}
+
+impl Anchor {
+    /// Moves the anchor by a whole number of tiles.
+    pub fn offset(self, offset: IVec2) -> Self {
+        match self {
+            Self::Tile(position) => Self::Tile(position + offset),
+            Self::Free(point) => Self::Free(point + offset.as_vec2()),
+        }
+    }
+}
+
+/// Collapses runs of whitespace into single spaces and trims the ends, the way that the text
+/// of a label is read back from a file.
+pub fn normalize_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl Schematic {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty() && self.wires.is_empty() && self.labels.is_empty()
+    }
+
+    /// Applies `transform` to a schematic that fits in the rectangle from the origin to `size`.
+    ///
+    /// Returns the transformed schematic, moved back into a rectangle starting at the origin,
+    /// along with that rectangle's size.
+    pub fn transform(&self, transform: Transform, size: IVec2) -> (Self, IVec2) {
+        let corner = transform.vector(size);
+        let offset = -corner.min(IVec2::ZERO);
+        let components = self
+            .components
+            .iter()
+            .map(|component| PlacedComponent {
+                position: transform.vector(component.position) + offset,
+                orientation: transform.direction(component.orientation),
+                ..*component
+            })
+            .collect();
+        let wires = self
+            .wires
+            .iter()
+            .map(|wire| PlacedWire {
+                start: transform.vector(wire.start) + offset,
+                end: transform.vector(wire.end) + offset,
+            })
+            .collect();
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| PlacedLabel {
+                anchor: match label.anchor {
+                    Anchor::Tile(position) => Anchor::Tile(transform.vector(position) + offset),
+                    Anchor::Free(point) => Anchor::Free(
+                        transform.point(point - Vec2::splat(0.5))
+                            + offset.as_vec2()
+                            + Vec2::splat(0.5),
+                    ),
+                },
+                text: label.text.clone(),
+            })
+            .collect();
+        let chips = self.chips.clone();
+        (
+            Self {
+                components,
+                wires,
+                labels,
+                chips,
+            },
+            corner.abs(),
+        )
+    }
+
+    /// Writes the schematic in the file format, and flushes `writer`, so that a buffered
+    /// writer can't lose the end of the file without an error.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{} {}", MAGIC, FORMAT_VERSION)?;
+        for chip in &self.chips {
+            writeln!(writer, "chip {} {}", chip.id, chip.name)?;
+            chip.schematic.write_records(&mut writer)?;
+            writeln!(writer, "end")?;
+        }
+        self.write_records(&mut writer)?;
+        writer.flush()
+    }
+
+    fn write_records<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for component in &self.components {
+            write!(
+                writer,
+                "component {} {} {} {}",
+                type_name(component.ty),
+                component.position.x,
+                component.position.y,
+                direction_name(component.orientation),
+            )?;
+            if component.switched {
+                write!(writer, " on")?;
+            }
+            writeln!(writer)?;
+        }
+        for wire in &self.wires {
+            writeln!(
+                writer,
+                "wire {} {} {} {}",
+                wire.start.x, wire.start.y, wire.end.x, wire.end.y,
+            )?;
+        }
+        for label in &self.labels {
+            match label.anchor {
+                Anchor::Tile(position) => {
+                    writeln!(writer, "label {} {} {}", position.x, position.y, label.text)?
+                }
+                Anchor::Free(point) => {
+                    writeln!(writer, "note {} {} {}", point.x, point.y, label.text)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> anyhow::Result<Self> {
+        let mut schematic = Self::new();
+        let mut version = None;
+        let mut chip: Option<SchematicChip> = None;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line_number = index + 1;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() || fields[0].starts_with('#') {
+                continue;
+            }
+
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    version = Some(
+                        parse_header(&fields).with_context(|| format!("line {}", line_number))?,
+                    );
+                    continue;
+                }
+            };
+            let result = match (fields[0], &mut chip) {
+                ("chip", None) => require_version(version, 2, "chip definitions")
+                    .and_then(|()| parse_chip(&fields, &schematic.chips))
+                    .map(|new| {
+                        chip = Some(new);
+                    }),
+                ("chip", Some(..)) => Err(anyhow!("chips cannot be defined inside of a chip")),
+                ("end", Some(..)) => {
+                    let chip = chip.take().unwrap();
+                    // Chips can only be made of chips that were defined before them, so they
+                    // can't contain themselves.
+                    chip.schematic
+                        .check_chips_defined(&schematic.chips)
+                        .with_context(|| format!("in chip {}", chip.id))
+                        .map(|()| schematic.chips.push(chip))
+                }
+                (_, Some(chip)) => chip.schematic.parse_record(&fields, version),
+                (_, None) => schematic.parse_record(&fields, version),
+            };
+            result.with_context(|| format!("line {}", line_number))?;
+        }
+
+        ensure!(version.is_some(), "missing file header");
+        ensure!(chip.is_none(), "missing `end` of chip definition");
+        schematic.check_chips_defined(&schematic.chips)?;
+        Ok(schematic)
+    }
+
+    fn check_chips_defined(&self, defined: &[SchematicChip]) -> anyhow::Result<()> {
+        for component in &self.components {
+            if let ComponentType::Chip(id) = component.ty {
+                ensure!(
+                    defined.iter().any(|chip| chip.id == id),
+                    "chip {} is used but not defined",
+                    id
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a component, wire or label record of a file of the format `version`.
+    fn parse_record(&mut self, fields: &[&str], version: u32) -> anyhow::Result<()> {
+        match fields[0] {
+            "component" => {
+                ensure!(
+                    fields.len() == 5 || fields.len() == 6,
+                    "expected `component <type> <x> <y> <orientation> [on]`"
+                );
+                let ty = parse_type(fields[1])?;
+                if let ComponentType::Chip(..) = ty {
+                    require_version(version, 2, "chips")?;
+                }
+                let position = parse_position(&fields[2..4])?;
+                let orientation = parse_direction(fields[4])?;
+                let switched = match fields.get(5) {
+                    None => false,
+                    Some(&"on") if ty == ComponentType::Switch => true,
+                    Some(other) => bail!("unexpected component flag `{}`", other),
+                };
+                self.components.push(PlacedComponent {
+                    ty,
+                    position,
+                    orientation,
+                    switched,
+                });
+            }
+            "wire" => {
+                ensure!(fields.len() == 5, "expected `wire <x1> <y1> <x2> <y2>`");
+                let start = parse_position(&fields[1..3])?;
+                let end = parse_position(&fields[3..5])?;
+                ensure!(
+                    (start.x == end.x) ^ (start.y == end.y),
+                    "wire must be horizontal or vertical and have a nonzero length"
+                );
+                self.wires.push(PlacedWire { start, end });
+            }
+            "label" => {
+                require_version(version, 3, "labels")?;
+                ensure!(fields.len() >= 4, "expected `label <x> <y> <text>`");
+                let position = parse_position(&fields[1..3])?;
+                ensure!(
+                    !self
+                        .labels
+                        .iter()
+                        .any(|label| label.anchor == Anchor::Tile(position)),
+                    "tile {} already has a label",
+                    position
+                );
+                self.labels.push(PlacedLabel {
+                    anchor: Anchor::Tile(position),
+                    text: fields[3..].join(" "),
+                });
+            }
+            "note" => {
+                require_version(version, 3, "notes")?;
+                ensure!(fields.len() >= 4, "expected `note <x> <y> <text>`");
+                let x: f32 = fields[1].parse().context("invalid x coordinate")?;
+                let y: f32 = fields[2].parse().context("invalid y coordinate")?;
+                ensure!(x.is_finite() && y.is_finite(), "coordinates must be finite");
+                self.labels.push(PlacedLabel {
+                    anchor: Anchor::Free(Vec2::new(x, y)),
+                    text: fields[3..].join(" "),
+                });
+            }
+            other => bail!("unknown record `{}`", other),
+        }
+        Ok(())
+    }
+}
+
+fn parse_chip(fields: &[&str], defined: &[SchematicChip]) -> anyhow::Result<SchematicChip> {
+    ensure!(fields.len() >= 3, "expected `chip <id> <name>`");
+    let id: u32 = fields[1].parse().context("invalid chip id")?;
+    ensure!(
+        defined.last().is_none_or(|last| last.id < id),
+        "chips must be defined in order of their IDs"
+    );
+    Ok(SchematicChip {
+        id,
+        name: fields[2..].join(" "),
+        schematic: Schematic::new(),
+    })
+}
+
+fn parse_header(fields: &[&str]) -> anyhow::Result<u32> {
+    ensure!(
+        fields.len() == 2 && fields[0] == MAGIC,
+        "not a FlipFlop circuit file"
+    );
+    let version: u32 = fields[1].parse().context("invalid format version")?;
+    ensure!(
+        (1..=FORMAT_VERSION).contains(&version),
+        "unsupported format version {} (newest supported is {})",
+        version,
+        FORMAT_VERSION
+    );
+    Ok(version)
+}
+
+/// Fails if a file of the format `version` can't have `what`, which came with `since`.
+fn require_version(version: u32, since: u32, what: &str) -> anyhow::Result<()> {
+    ensure!(
+        version >= since,
+        "{} need format version {}, but the file is version {}",
+        what,
+        since,
+        version
+    );
+    Ok(())
+}
+
+fn parse_position(fields: &[&str]) -> anyhow::Result<IVec2> {
+    let x = fields[0].parse().context("invalid x coordinate")?;
+    let y = fields[1].parse().context("invalid y coordinate")?;
+    Ok(IVec2::new(x, y))
+}
+
+fn type_name(ty: ComponentType) -> String {
+    match ty {
+        ComponentType::Pin => "pin".into(),
+        ComponentType::Flip => "flip".into(),
+        ComponentType::Flop => "flop".into(),
+        ComponentType::Switch => "switch".into(),
+        ComponentType::Lamp => "lamp".into(),
+        ComponentType::Chip(id) => format!("chip:{}", id),
+    }
+}
+
+fn parse_type(name: &str) -> anyhow::Result<ComponentType> {
+    Ok(match name {
+        "pin" => ComponentType::Pin,
+        "flip" => ComponentType::Flip,
+        "flop" => ComponentType::Flop,
+        "switch" => ComponentType::Switch,
+        "lamp" => ComponentType::Lamp,
+        other => match other.strip_prefix("chip:") {
+            Some(id) => ComponentType::Chip(id.parse().context("invalid chip id")?),
+            None => bail!("unknown component type `{}`", other),
+        },
+    })
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::East => "east",
+        Direction::North => "north",
+        Direction::West => "west",
+        Direction::South => "south",
+    }
+}
+
+fn parse_direction(name: &str) -> anyhow::Result<Direction> {
+    Ok(match name {
+        "east" => Direction::East,
+        "north" => Direction::North,
+        "west" => Direction::West,
+        "south" => Direction::South,
+        other => bail!("unknown orientation `{}`", other),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Anchor, PlacedComponent, PlacedLabel, PlacedWire, Schematic, SchematicChip, FORMAT_VERSION,
+    };
+    use crate::circuit::ComponentType;
+    use crate::direction::{Direction, Relative, Transform};
+    use glam::{IVec2, Vec2};
+
+    #[test]
+    fn round_trip() {
+        let schematic = Schematic {
+            components: vec![
+                PlacedComponent {
+                    ty: ComponentType::Switch,
+                    position: IVec2::new(0, 0),
+                    orientation: Direction::East,
+                    switched: true,
+                },
+                PlacedComponent {
+                    ty: ComponentType::Lamp,
+                    position: IVec2::new(-3, 0),
+                    orientation: Direction::North,
+                    switched: false,
+                },
+                PlacedComponent {
+                    ty: ComponentType::Chip(4),
+                    position: IVec2::new(2, 2),
+                    orientation: Direction::West,
+                    switched: false,
+                },
+            ],
+            wires: vec![PlacedWire {
+                start: IVec2::new(0, 0),
+                end: IVec2::new(-3, 0),
+            }],
+            labels: vec![
+                PlacedLabel {
+                    anchor: Anchor::Tile(IVec2::new(0, 0)),
+                    text: "Carry in".into(),
+                },
+                PlacedLabel {
+                    anchor: Anchor::Free(Vec2::new(-2.75, 1.5)),
+                    text: "adds two bits".into(),
+                },
+            ],
+            chips: vec![SchematicChip {
+                id: 4,
+                name: "half adder".into(),
+                schematic: Schematic {
+                    components: vec![PlacedComponent {
+                        ty: ComponentType::Lamp,
+                        position: IVec2::new(1, 0),
+                        orientation: Direction::North,
+                        switched: false,
+                    }],
+                    ..Schematic::new()
+                },
+            }],
+        };
+        let mut buffer = Vec::new();
+        schematic.write(&mut buffer).unwrap();
+        assert_eq!(Schematic::read(&buffer[..]).unwrap(), schematic);
+    }
+
+    #[test]
+    fn transform() {
+        let schematic = Schematic {
+            components: vec![PlacedComponent {
+                ty: ComponentType::Flip,
+                position: IVec2::new(0, 0),
+                orientation: Direction::East,
+                switched: false,
+            }],
+            wires: vec![PlacedWire {
+                start: IVec2::new(0, 0),
+                end: IVec2::new(3, 0),
+            }],
+            labels: vec![PlacedLabel {
+                anchor: Anchor::Free(Vec2::new(3.5, 0.5)),
+                text: "end".into(),
+            }],
+            ..Schematic::new()
+        };
+        let (rotated, size) =
+            schematic.transform(Transform::Rotate(Relative::Right), IVec2::new(3, 1));
+        assert_eq!(size, IVec2::new(1, 3));
+        assert_eq!(rotated.components[0].position, IVec2::new(0, 3));
+        assert_eq!(rotated.components[0].orientation, Direction::South);
+        assert_eq!(rotated.wires[0].end, IVec2::new(0, 0));
+        // Still on the middle of the same tile as the end of the wire.
+        assert_eq!(rotated.labels[0].anchor, Anchor::Free(Vec2::new(0.5, 0.5)));
+    }
+
+    #[test]
+    fn reject_newer_version() {
+        assert!(Schematic::read(&b"flipflop 999\n"[..]).is_err());
+        assert!(Schematic::read(format!("flipflop {}\n", FORMAT_VERSION + 1).as_bytes()).is_err());
+        assert!(Schematic::read(&b"flipflop 0\n"[..]).is_err());
+        assert!(Schematic::read(&b"# no header\n"[..]).is_err());
+        assert!(Schematic::read(&b"flipflop 2\ncomponent chip:1 0 0 east\n"[..]).is_err());
+        assert!(Schematic::read(&b"flipflop 3\nlabel 0 0 a\nlabel 0 0 b\n"[..]).is_err());
+    }
+
+    #[test]
+    fn reject_records_newer_than_header() {
+        for (newer, older) in [
+            ("chip 1 a\nend\n", 1),
+            ("chip 1 a\nend\ncomponent chip:1 0 0 east\n", 1),
+            ("label 0 0 a\n", 2),
+            ("note 0.5 0.5 a\n", 2),
+        ] {
+            let text = |version: u32| format!("flipflop {}\n{}", version, newer);
+            assert!(
+                Schematic::read(text(older).as_bytes()).is_err(),
+                "{}",
+                newer
+            );
+            assert!(
+                Schematic::read(text(older + 1).as_bytes()).is_ok(),
+                "{}",
+                newer
+            );
+        }
+    }
+
+    #[test]
+    fn reject_undefined_chips_in_chips() {
+        let defined = b"flipflop 2\nchip 1 a\nend\nchip 2 b\ncomponent chip:1 0 0 east\nend\n";
+        assert!(Schematic::read(&defined[..]).is_ok());
+        for text in [
+            // Itself.
+            &b"flipflop 2\nchip 1 a\ncomponent chip:1 0 0 east\nend\n"[..],
+            // One defined after it.
+            b"flipflop 2\nchip 1 a\ncomponent chip:2 0 0 east\nend\nchip 2 b\nend\n",
+            // One that isn't defined at all.
+            b"flipflop 2\nchip 1 a\ncomponent chip:7 0 0 east\nend\n",
+        ] {
+            assert!(Schematic::read(text).is_err());
+        }
+    }
+}
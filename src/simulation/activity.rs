@@ -0,0 +1,96 @@
+//! Tells whether a [`Simulation`](super::Simulation) has settled or is stuck in a loop.
+//!
+//! Each tick only depends on the power states of the tick before it, so once the same states
+//! come up twice without an edit in between, everything from then on repeats. States are
+//! compared by a hash that is updated from the clusters that changed.
+
+use std::collections::{HashMap, VecDeque};
+
+// How many ticks back a repeated state is looked for, which is also the longest period that
+// can be detected.
+const WINDOW: usize = 4096;
+
+/// What the simulation has been doing since it was last edited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Activity {
+    /// No state has repeated yet.
+    #[default]
+    Unsettled,
+    /// Nothing has changed since `since`.
+    Stable { since: u64 },
+    /// The states have been repeating every `period` ticks since `since`.
+    Oscillating { period: u64, since: u64 },
+}
+
+/// Remembers the hashes of the most recent ticks.
+#[derive(Default, Clone)]
+pub(super) struct Detector {
+    hashes: VecDeque<(u64, u64)>,
+    // The latest tick that each hash in `hashes` was seen on.
+    latest: HashMap<u64, u64>,
+    activity: Activity,
+}
+
+impl Detector {
+    pub(super) fn activity(&self) -> Activity {
+        self.activity
+    }
+
+    /// Forgets every tick seen so far, because the simulation was edited.
+    pub(super) fn reset(&mut self) {
+        self.hashes.clear();
+        self.latest.clear();
+        self.activity = Activity::Unsettled;
+    }
+
+    /// Notes the hash of the power states after `tick`, returning whether a new loop was found.
+    pub(super) fn observe(&mut self, tick: u64, hash: u64) -> bool {
+        let found = match self.latest.insert(hash, tick) {
+            Some(previous) => {
+                let period = tick - previous;
+                match self.activity {
+                    Activity::Stable { .. } if period == 1 => false,
+                    Activity::Oscillating {
+                        period: current, ..
+                    } if current == period => false,
+                    _ => {
+                        self.activity = if period == 1 {
+                            Activity::Stable { since: previous }
+                        } else {
+                            Activity::Oscillating {
+                                period,
+                                since: previous,
+                            }
+                        };
+                        true
+                    }
+                }
+            }
+            None => {
+                self.activity = Activity::Unsettled;
+                false
+            }
+        };
+
+        self.hashes.push_back((tick, hash));
+        if self.hashes.len() > WINDOW {
+            let (old_tick, old_hash) = self.hashes.pop_front().unwrap();
+            if self.latest.get(&old_hash) == Some(&old_tick) {
+                self.latest.remove(&old_hash);
+            }
+        }
+        found
+    }
+}
+
+/// A random-looking key for each cluster; the hash of a state is the XOR of the keys of every
+/// powered cluster (Zobrist hashing).
+pub(super) fn cluster_key(id: u32) -> u64 {
+    // splitmix64
+    let mut z = (id as u64)
+        .wrapping_add(1)
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
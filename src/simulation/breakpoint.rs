@@ -0,0 +1,110 @@
+//! Conditions on the power states of clusters that pause the simulation.
+
+/// What a single cluster has to do for a trigger to be true on a tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// It was off before the tick, and is on after it.
+    Rises,
+    /// It was on before the tick, and is off after it.
+    Falls,
+    /// It rises or falls.
+    Changes,
+    /// It is on after the tick.
+    High,
+    /// It is off after the tick.
+    Low,
+}
+
+impl Condition {
+    /// Whether the condition holds for a cluster that went from `was` to `is`.
+    pub fn holds(self, was: bool, is: bool) -> bool {
+        match self {
+            Self::Rises => !was && is,
+            Self::Falls => was && !is,
+            Self::Changes => was != is,
+            Self::High => is,
+            Self::Low => !is,
+        }
+    }
+
+    /// Whether the condition is about a change, rather than a level.
+    pub fn is_edge(self) -> bool {
+        matches!(self, Self::Rises | Self::Falls | Self::Changes)
+    }
+}
+
+/// How the triggers of a breakpoint are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combine {
+    /// Every trigger has to be true on the same tick.
+    All,
+    /// Any trigger being true is enough.
+    Any,
+}
+
+/// A set of triggers, each one a condition on a target. The simulation itself works with
+/// breakpoints on cluster IDs; the editor keeps them on tiles, and turns them into clusters
+/// whenever the circuit changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint<T = u32> {
+    pub triggers: Vec<(T, Condition)>,
+    pub combine: Combine,
+}
+
+impl<T> Breakpoint<T> {
+    /// Replaces every target through `f`, or returns `None` if any of them has no replacement.
+    pub fn resolve<U, F>(&self, mut f: F) -> Option<Breakpoint<U>>
+    where
+        F: FnMut(&T) -> Option<U>,
+    {
+        let triggers = self
+            .triggers
+            .iter()
+            .map(|(target, condition)| Some((f(target)?, *condition)))
+            .collect::<Option<_>>()?;
+        Some(Breakpoint {
+            triggers,
+            combine: self.combine,
+        })
+    }
+}
+
+impl Breakpoint {
+    /// If the breakpoint fires on a tick, returns the index of the trigger that caused it.
+    ///
+    /// `state` gives the power state of a cluster before and after the tick.
+    pub(super) fn check<F>(&self, mut state: F) -> Option<usize>
+    where
+        F: FnMut(u32) -> (bool, bool),
+    {
+        let mut holds = self.triggers.iter().map(|&(cluster, condition)| {
+            let (was, is) = state(cluster);
+            condition.holds(was, is)
+        });
+        match self.combine {
+            Combine::Any => holds.position(|holds| holds),
+            Combine::All => {
+                if !holds.all(|holds| holds) || self.triggers.is_empty() {
+                    return None;
+                }
+                // A change is what made it fire, unless there are only levels.
+                let cause = self
+                    .triggers
+                    .iter()
+                    .position(|&(_, condition)| condition.is_edge());
+                Some(cause.unwrap_or(0))
+            }
+        }
+    }
+}
+
+/// A breakpoint that fired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hit {
+    /// The tick that it fired after.
+    pub tick: u64,
+    /// The index of the breakpoint.
+    pub breakpoint: usize,
+    /// The index of the trigger that caused it, within the breakpoint.
+    pub trigger: usize,
+}
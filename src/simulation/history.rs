@@ -0,0 +1,188 @@
+//! Past power states of a [`Simulation`](super::Simulation), for rewinding it.
+//!
+//! Every tick is stored as the list of clusters whose power state differs from the tick before.
+//! Applying a tick's list toggles those clusters, which moves a state one tick forward or one
+//! tick back, so any recorded tick can be reached from the newest state.
+
+use super::activity::cluster_key;
+use bitvec::prelude::{BitVec, Lsb0};
+use std::collections::VecDeque;
+
+// The oldest ticks are dropped once either of these is exceeded.
+const MAX_TICKS: usize = 100_000;
+const MAX_CHANGES: usize = 1 << 22;
+
+#[derive(Default, Clone)]
+pub(super) struct History {
+    // The changes of every recorded tick, oldest first, back to back.
+    changes: VecDeque<u32>,
+    // Where the changes of each tick start, counting every change that was ever recorded.
+    starts: VecDeque<u64>,
+    // The number of changes that were dropped from the front of `changes`.
+    dropped: u64,
+
+    // The power states after the newest recorded tick.
+    newest: BitVec<usize, Lsb0>,
+    newest_tick: u64,
+    // The hash of `newest`.
+    hash: u64,
+
+    view: Option<View>,
+}
+
+/// A past tick that is being looked at.
+#[derive(Clone)]
+pub(super) struct View {
+    pub(super) tick: u64,
+    pub(super) is_powered: BitVec<usize, Lsb0>,
+    pub(super) was_powered: BitVec<usize, Lsb0>,
+}
+
+impl History {
+    /// The oldest tick that can be viewed.
+    pub(super) fn oldest_tick(&self) -> u64 {
+        self.newest_tick - self.starts.len() as u64
+    }
+
+    /// The hash of the power states after the newest tick.
+    pub(super) fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub(super) fn view(&self) -> Option<&View> {
+        self.view.as_ref()
+    }
+
+    /// Stops viewing the past, returning the tick that was being viewed.
+    pub(super) fn take_view(&mut self) -> Option<View> {
+        self.view.take()
+    }
+
+    /// Records the power states after `tick`.
+    pub(super) fn record(&mut self, tick: u64, is_powered: &BitVec<usize, Lsb0>) {
+        if tick != self.newest_tick + 1 {
+            // The tick count was changed some other way, so the past doesn't lead up to this.
+            self.clear();
+            self.set_newest(is_powered);
+            self.newest_tick = tick;
+            return;
+        }
+        let len = is_powered.len();
+        self.newest.resize(len, false);
+        self.starts
+            .push_back(self.dropped + self.changes.len() as u64);
+        let words = self
+            .newest
+            .as_raw_mut_slice()
+            .iter_mut()
+            .zip(is_powered.as_raw_slice());
+        for (word_index, (old, &new)) in words.enumerate() {
+            let mut diff = *old ^ new;
+            *old = new;
+            while diff != 0 {
+                let i = word_index * usize::BITS as usize + diff.trailing_zeros() as usize;
+                diff &= diff - 1;
+                // The last word can have bits past the end.
+                if i < len {
+                    self.changes.push_back(i as u32);
+                    self.hash ^= cluster_key(i as u32);
+                }
+            }
+        }
+        self.newest_tick = tick;
+
+        while self.starts.len() > MAX_TICKS || self.changes.len() > MAX_CHANGES {
+            self.starts.pop_front();
+            let end = self
+                .starts
+                .front()
+                .copied()
+                .unwrap_or(self.dropped + self.changes.len() as u64);
+            let count = end - self.dropped;
+            self.changes.drain(..count as usize);
+            self.dropped = end;
+        }
+    }
+
+    /// Looks at the power states after `tick`, clamped to the recorded range. Returns to the
+    /// present if that is the newest tick.
+    pub(super) fn seek(&mut self, tick: u64) {
+        let tick = tick.clamp(self.oldest_tick(), self.newest_tick);
+        if tick == self.newest_tick {
+            self.view = None;
+            return;
+        }
+        let mut view = self.view.take().unwrap_or_else(|| View {
+            tick: self.newest_tick,
+            is_powered: self.newest.clone(),
+            was_powered: BitVec::new(),
+        });
+        while view.tick > tick {
+            self.toggle(&mut view.is_powered, view.tick);
+            view.tick -= 1;
+        }
+        while view.tick < tick {
+            view.tick += 1;
+            self.toggle(&mut view.is_powered, view.tick);
+        }
+        view.was_powered.clone_from(&view.is_powered);
+        if view.tick > self.oldest_tick() {
+            self.toggle(&mut view.was_powered, view.tick);
+        }
+        self.view = Some(view);
+    }
+
+    /// Forgets every tick after `tick`, which becomes the newest one, with the given power
+    /// states.
+    pub(super) fn truncate(&mut self, tick: u64, is_powered: &BitVec<usize, Lsb0>) {
+        while self.newest_tick > tick && !self.starts.is_empty() {
+            let start = self.starts.pop_back().unwrap();
+            self.changes.truncate((start - self.dropped) as usize);
+            self.newest_tick -= 1;
+        }
+        self.set_newest(is_powered);
+    }
+
+    /// Every cluster that changed after `tick`, in order of their IDs.
+    pub(super) fn changed_since(&self, tick: u64) -> Vec<u32> {
+        let tick = tick.max(self.oldest_tick());
+        let index = (tick - self.oldest_tick()) as usize;
+        let start = match self.starts.get(index) {
+            Some(&start) => (start - self.dropped) as usize,
+            None => return Vec::new(),
+        };
+        let mut clusters: Vec<u32> = self.changes.range(start..).copied().collect();
+        clusters.sort_unstable();
+        clusters.dedup();
+        clusters
+    }
+
+    fn set_newest(&mut self, is_powered: &BitVec<usize, Lsb0>) {
+        self.newest.clone_from(is_powered);
+        self.hash = is_powered
+            .iter_ones()
+            .fold(0, |hash, i| hash ^ cluster_key(i as u32));
+    }
+
+    fn clear(&mut self) {
+        self.dropped += self.changes.len() as u64;
+        self.changes.clear();
+        self.starts.clear();
+        self.view = None;
+    }
+
+    /// Applies the changes of `tick`, which moves a state between `tick - 1` and `tick`.
+    fn toggle(&self, state: &mut BitVec<usize, Lsb0>, tick: u64) {
+        let index = (tick - self.oldest_tick() - 1) as usize;
+        let start = (self.starts[index] - self.dropped) as usize;
+        let end = match self.starts.get(index + 1) {
+            Some(&end) => (end - self.dropped) as usize,
+            None => self.changes.len(),
+        };
+        for &id in self.changes.range(start..end) {
+            let i = id as usize;
+            let value = !state[i];
+            state.set(i, value);
+        }
+    }
+}
@@ -0,0 +1,1509 @@
+mod activity;
+mod breakpoint;
+mod history;
+mod trace;
+
+pub use self::activity::Activity;
+pub use self::breakpoint::{Breakpoint, Combine, Condition, Hit};
+pub use self::trace::{Signal, Trace};
+
+use self::activity::Detector;
+use self::history::History;
+use bitvec::prelude::{BitVec, Lsb0};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::thread;
+
+// A tick evaluates every cluster once more than this fraction of them needs evaluating.
+const FULL_TICK_DIVISOR: usize = 8;
+
+// Full ticks are split across threads from this many clusters on. Below it, starting the
+// threads costs more than they save.
+const PARALLEL_THRESHOLD: usize = 1 << 18;
+
+static PARALLELISM: Lazy<usize> =
+    Lazy::new(|| thread::available_parallelism().map_or(1, |n| n.get()));
+
+#[derive(Clone)]
+pub struct Simulation {
+    // Tracks unused cluster indexes so they can be re-used.
+    num_clusters: u32,
+    free_clusters: Vec<u32>,
+
+    is_powered: BitVec<usize, Lsb0>,
+    was_powered: BitVec<usize, Lsb0>,
+
+    // The inputs of the flips and flops driving each cluster, and the clusters driven by each
+    // cluster's flips and flops. These are multi-sets, because there's nothing stopping the
+    // player from adding multiple flips/flops and so we need to keep track of how many there
+    // are.
+    flip_inputs: Adjacency,
+    flop_inputs: Adjacency,
+    dependents: Adjacency,
+    // The number of flips and flops driving each cluster.
+    fan_in: Vec<u32>,
+
+    manual_power: Vec<u32>,
+    // Values that clusters are held at regardless of their flips, flops and manual power.
+    forced: Vec<Option<bool>>,
+
+    // Clusters that have to be re-evaluated on the next tick, because their inputs, gates or
+    // manual power changed.
+    dirty: Vec<u32>,
+    is_dirty: Vec<bool>,
+
+    // Clusters where `is_powered` may differ from `was_powered`. Every other cluster has the
+    // same value in both.
+    changed: Vec<u32>,
+    is_changed: Vec<bool>,
+
+    // Every change made since the journal was started, if it was.
+    journal: Option<Vec<Command>>,
+
+    tick_count: u64,
+    history: History,
+
+    detector: Detector,
+    // The clusters that changed during the last period of the oscillation that was detected.
+    oscillating: Vec<u32>,
+
+    breakpoints: Vec<Breakpoint>,
+    // The first breakpoint that fired since this was last cleared.
+    hit: Option<Hit>,
+
+    trace: Trace,
+}
+
+/// A change to a [`Simulation`], recorded so that it can be replayed on a copy of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    AllocCluster(u32),
+    FreeCluster(u32),
+    AddFlip(u32, u32),
+    AddFlop(u32, u32),
+    RemoveFlip(u32, u32),
+    RemoveFlop(u32, u32),
+    Power(u32),
+    Unpower(u32),
+    Force(u32, Option<bool>),
+    SetPowered(u32, bool),
+    MergePower { into: u32, from: u32 },
+    CopyPower { from: u32, to: u32 },
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self {
+            num_clusters: 0,
+            free_clusters: Vec::new(),
+            is_powered: BitVec::new(),
+            was_powered: BitVec::new(),
+            flip_inputs: Adjacency::default(),
+            flop_inputs: Adjacency::default(),
+            dependents: Adjacency::default(),
+            fan_in: Vec::new(),
+            manual_power: Vec::new(),
+            forced: Vec::new(),
+            dirty: Vec::new(),
+            is_dirty: Vec::new(),
+            changed: Vec::new(),
+            is_changed: Vec::new(),
+            journal: None,
+            tick_count: 0,
+            history: History::default(),
+            detector: Detector::default(),
+            oscillating: Vec::new(),
+            breakpoints: Vec::new(),
+            hit: None,
+            trace: Trace::default(),
+        }
+    }
+
+    pub fn num_clusters(&self) -> u32 {
+        self.num_clusters
+    }
+
+    /// The number of ticks that have been run.
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// Allocates a new cluster ID that is not currently being used.
+    pub fn alloc_cluster(&mut self) -> u32 {
+        // The fork has to happen before the new cluster is added to the power states.
+        self.fork();
+        let id = self.next_cluster();
+        self.record(Command::AllocCluster(id));
+        id
+    }
+
+    fn next_cluster(&mut self) -> u32 {
+        if let Some(id) = self.free_clusters.pop() {
+            id
+        } else {
+            let id = self.num_clusters;
+            self.num_clusters += 1;
+
+            self.is_powered.push(false);
+            self.was_powered.push(false);
+            self.fan_in.push(0);
+            self.manual_power.push(0);
+            self.forced.push(None);
+            self.is_dirty.push(false);
+            self.is_changed.push(false);
+
+            id
+        }
+    }
+
+    /// Frees the given cluster, allowing the ID to be re-used. If it was forced, it is
+    /// released.
+    pub fn free_cluster(&mut self, id: u32) {
+        let index = cluster_array_index(id);
+        assert!(self.fan_in[index] == 0);
+        assert!(self.manual_power[index] == 0);
+        self.forced[index] = None;
+        self.free_clusters.push(id);
+        self.record(Command::FreeCluster(id));
+    }
+
+    pub fn add_flip(&mut self, inp: u32, out: u32) {
+        self.record(Command::AddFlip(inp, out));
+        self.flip_inputs.add(out, inp);
+        self.dependents.add(inp, out);
+        self.fan_in[cluster_array_index(out)] += 1;
+        self.mark_dirty(out);
+    }
+
+    pub fn add_flop(&mut self, inp: u32, out: u32) {
+        self.record(Command::AddFlop(inp, out));
+        self.flop_inputs.add(out, inp);
+        self.dependents.add(inp, out);
+        self.fan_in[cluster_array_index(out)] += 1;
+        self.mark_dirty(out);
+    }
+
+    pub fn remove_flip(&mut self, inp: u32, out: u32) {
+        self.record(Command::RemoveFlip(inp, out));
+        self.flip_inputs.remove(out, inp);
+        self.dependents.remove(inp, out);
+        self.fan_in[cluster_array_index(out)] -= 1;
+        self.mark_dirty(out);
+    }
+
+    pub fn remove_flop(&mut self, inp: u32, out: u32) {
+        self.record(Command::RemoveFlop(inp, out));
+        self.flop_inputs.remove(out, inp);
+        self.dependents.remove(inp, out);
+        self.fan_in[cluster_array_index(out)] -= 1;
+        self.mark_dirty(out);
+    }
+
+    /// Every flip by its (input, output) clusters, in no particular order. Flips between the
+    /// same two clusters are listed once.
+    pub fn flips(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.flip_inputs.iter().map(|(out, inp)| (inp, out))
+    }
+
+    /// Every flop by its (input, output) clusters, like [`Simulation::flips`].
+    pub fn flops(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.flop_inputs.iter().map(|(out, inp)| (inp, out))
+    }
+
+    pub fn power(&mut self, id: u32) {
+        self.record(Command::Power(id));
+        self.manual_power[cluster_array_index(id)] += 1;
+        self.mark_dirty(id);
+    }
+
+    pub fn unpower(&mut self, id: u32) {
+        self.record(Command::Unpower(id));
+        self.manual_power[cluster_array_index(id)] -= 1;
+        self.mark_dirty(id);
+    }
+
+    /// Holds a cluster on or off from the next tick on, whatever drives it, or releases it with
+    /// `None`.
+    pub fn force(&mut self, id: u32, value: Option<bool>) {
+        self.record(Command::Force(id, value));
+        self.forced[cluster_array_index(id)] = value;
+        self.mark_dirty(id);
+    }
+
+    /// The value that a cluster is held at, if it is forced.
+    pub fn forced(&self, id: u32) -> Option<bool> {
+        self.forced[cluster_array_index(id)]
+    }
+
+    /// Every forced cluster and its value, in order of their IDs.
+    pub fn forced_clusters(&self) -> impl Iterator<Item = (u32, bool)> + '_ {
+        self.forced
+            .iter()
+            .enumerate()
+            .filter_map(|(i, value)| Some((i as u32, (*value)?)))
+    }
+
+    pub fn is_powered(&self, id: u32) -> bool {
+        self.is_powered[cluster_array_index(id)]
+    }
+
+    pub fn was_powered(&self, id: u32) -> bool {
+        self.was_powered[cluster_array_index(id)]
+    }
+
+    pub fn set_powered(&mut self, id: u32, powered: bool) {
+        self.record(Command::SetPowered(id, powered));
+        self.write_powered(id, powered);
+    }
+
+    /// Powers `into` if either it or `from` is powered, for when two clusters are merged.
+    pub fn merge_power(&mut self, into: u32, from: u32) {
+        self.record(Command::MergePower { into, from });
+        self.write_powered(into, self.is_powered(into) || self.is_powered(from));
+    }
+
+    /// Gives `to` the same power state as `from`, for when a cluster is split.
+    pub fn copy_power(&mut self, from: u32, to: u32) {
+        self.record(Command::CopyPower { from, to });
+        self.write_powered(to, self.is_powered(from));
+    }
+
+    /// Starts recording every change made to the simulation, to be replayed on a copy of it
+    /// with [`Simulation::apply`]. Anything recorded before is discarded.
+    pub fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    pub fn stop_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// Takes the changes recorded since the last call.
+    pub fn take_journal(&mut self) -> Vec<Command> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Replays a change recorded on another copy of this simulation.
+    pub fn apply(&mut self, command: Command) {
+        match command {
+            Command::AllocCluster(id) => {
+                let allocated = self.alloc_cluster();
+                assert_eq!(allocated, id, "simulation copies are out of sync");
+            }
+            Command::FreeCluster(id) => self.free_cluster(id),
+            Command::AddFlip(inp, out) => self.add_flip(inp, out),
+            Command::AddFlop(inp, out) => self.add_flop(inp, out),
+            Command::RemoveFlip(inp, out) => self.remove_flip(inp, out),
+            Command::RemoveFlop(inp, out) => self.remove_flop(inp, out),
+            Command::Power(id) => self.power(id),
+            Command::Unpower(id) => self.unpower(id),
+            Command::Force(id, value) => self.force(id, value),
+            Command::SetPowered(id, powered) => self.set_powered(id, powered),
+            Command::MergePower { into, from } => self.merge_power(into, from),
+            Command::CopyPower { from, to } => self.copy_power(from, to),
+        }
+    }
+
+    /// The oldest tick that [`Simulation::seek`] can go back to.
+    pub fn oldest_tick(&self) -> u64 {
+        self.history.oldest_tick()
+    }
+
+    /// The past tick that is being viewed, if the simulation was rewound.
+    pub fn viewed_tick(&self) -> Option<u64> {
+        self.history.view().map(|view| view.tick)
+    }
+
+    /// Rewinds to the power states right after `tick`, as far as the history goes back.
+    /// Seeking to the current tick count returns to the present.
+    ///
+    /// While rewound, the present is kept as it was. The next tick or edit forks history
+    /// instead: the simulation carries on from the viewed tick, and everything after it is
+    /// forgotten.
+    pub fn seek(&mut self, tick: u64) {
+        self.history.seek(tick);
+    }
+
+    /// Copies the power states into `snapshot`, reusing its storage. While rewound, these are
+    /// the ones of the viewed tick.
+    pub fn write_snapshot(&self, snapshot: &mut Snapshot) {
+        match self.history.view() {
+            Some(view) => {
+                snapshot.is_powered.clone_from(&view.is_powered);
+                snapshot.was_powered.clone_from(&view.was_powered);
+            }
+            None => {
+                snapshot.is_powered.clone_from(&self.is_powered);
+                snapshot.was_powered.clone_from(&self.was_powered);
+            }
+        }
+        snapshot.tick_count = self.tick_count;
+        snapshot.viewed_tick = self.viewed_tick();
+        snapshot.oldest_tick = self.oldest_tick();
+        snapshot.activity = self.activity();
+        snapshot.oscillating.clone_from(&self.oscillating);
+        snapshot.breakpoint_hit = self.hit;
+    }
+
+    /// Whether the simulation has settled or is oscillating, judging by the ticks since the
+    /// last edit.
+    pub fn activity(&self) -> Activity {
+        self.detector.activity()
+    }
+
+    /// The clusters that take part in the current oscillation, in order of their IDs. Empty
+    /// unless the simulation is oscillating.
+    pub fn oscillating_clusters(&self) -> &[u32] {
+        &self.oscillating
+    }
+
+    /// Replaces the breakpoints that are checked after every tick.
+    pub fn set_breakpoints(&mut self, breakpoints: Vec<Breakpoint>) {
+        self.breakpoints = breakpoints;
+    }
+
+    /// The first breakpoint that fired since [`Simulation::clear_breakpoint_hit`] was called.
+    /// The simulation doesn't stop by itself; it is up to whoever runs it to stop ticking.
+    pub fn breakpoint_hit(&self) -> Option<Hit> {
+        self.hit
+    }
+
+    pub fn clear_breakpoint_hit(&mut self) {
+        self.hit = None;
+    }
+
+    /// Replaces the probes that are recorded on every tick. Each one is an ID, which stays the
+    /// same while the probe moves between clusters, and the cluster that it is on now.
+    pub fn set_probes(&mut self, probes: Vec<(u32, Option<u32>)>) {
+        let is_powered = &self.is_powered;
+        self.trace.set_probes(probes, self.tick_count, |id| {
+            is_powered[cluster_array_index(id)]
+        });
+    }
+
+    /// The values of the probes, from when each one was added.
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// If the simulation was rewound, makes the viewed tick the present.
+    fn fork(&mut self) {
+        let view = match self.history.take_view() {
+            Some(view) => view,
+            None => return,
+        };
+        self.is_powered = view.is_powered;
+        self.was_powered = view.was_powered;
+        self.tick_count = view.tick;
+        self.history.truncate(view.tick, &self.is_powered);
+        self.trace.truncate(view.tick);
+        self.forget_activity();
+
+        // Clusters allocated since the viewed tick weren't powered back then.
+        let num_clusters = cluster_array_index(self.num_clusters);
+        self.is_powered.resize(num_clusters, false);
+        self.was_powered.resize(num_clusters, false);
+        // Manual power may have changed since, and any cluster may now differ from the tick
+        // before it.
+        for id in 0..self.num_clusters {
+            self.mark_dirty(id);
+            self.mark_changed(id);
+        }
+    }
+
+    fn record(&mut self, command: Command) {
+        self.fork();
+        // Whatever repeated before doesn't have to repeat after an edit.
+        self.forget_activity();
+        if let Some(journal) = &mut self.journal {
+            journal.push(command);
+        }
+    }
+
+    fn forget_activity(&mut self) {
+        self.detector.reset();
+        self.oscillating.clear();
+    }
+
+    fn write_powered(&mut self, id: u32, powered: bool) {
+        self.is_powered.set(cluster_array_index(id), powered);
+        // The value is overwritten on the next tick, and its dependents have to see the change.
+        self.mark_dirty(id);
+        self.mark_changed(id);
+    }
+
+    /// Advances the simulation by one tick.
+    ///
+    /// Only clusters whose inputs changed on the last tick (or that were edited since) are
+    /// evaluated; every other cluster would come out the same anyway. Large circuits that have
+    /// to be evaluated in full are evaluated on every core.
+    pub fn tick(&mut self) {
+        let threads = if cluster_array_index(self.num_clusters) >= PARALLEL_THRESHOLD {
+            *PARALLELISM
+        } else {
+            1
+        };
+        self.tick_with_threads(threads);
+    }
+
+    /// Ticks until nothing changes anymore, but at most `max_ticks` times. Returns the number of
+    /// ticks that were run, and whether the simulation settled.
+    pub fn run_until_stable(&mut self, max_ticks: u64) -> (u64, bool) {
+        self.fork();
+        let mut ticks = 0;
+        while !self.is_settled() {
+            if ticks == max_ticks {
+                return (ticks, false);
+            }
+            self.tick();
+            ticks += 1;
+        }
+        (ticks, true)
+    }
+
+    /// Whether the next tick would leave every cluster as it is.
+    pub fn is_settled(&self) -> bool {
+        self.dirty.is_empty()
+            && self
+                .changed
+                .iter()
+                .all(|&id| self.dependents.get(cluster_array_index(id)).is_empty())
+    }
+
+    fn tick_with_threads(&mut self, threads: usize) {
+        self.fork();
+        std::mem::swap(&mut self.is_powered, &mut self.was_powered);
+        self.tick_count += 1;
+
+        // Past a point, tracking events costs more than evaluating everything.
+        let busy = self.changed.len() + self.dirty.len();
+        if busy > cluster_array_index(self.num_clusters) / FULL_TICK_DIVISOR {
+            self.tick_all(threads);
+        } else {
+            self.tick_events();
+        }
+        self.history.record(self.tick_count, &self.is_powered);
+        let is_powered = &self.is_powered;
+        self.trace
+            .record(self.tick_count, |id| is_powered[cluster_array_index(id)]);
+
+        if self.detector.observe(self.tick_count, self.history.hash()) {
+            self.oscillating = match self.detector.activity() {
+                Activity::Oscillating { period, .. } => {
+                    self.history.changed_since(self.tick_count - period)
+                }
+                _ => Vec::new(),
+            };
+        }
+
+        if self.hit.is_none() {
+            self.check_breakpoints();
+        }
+    }
+
+    fn check_breakpoints(&mut self) {
+        let state = |id: u32| {
+            let i = cluster_array_index(id);
+            match (self.was_powered.get(i), self.is_powered.get(i)) {
+                (Some(was), Some(is)) => (*was, *is),
+                _ => (false, false),
+            }
+        };
+        self.hit = self
+            .breakpoints
+            .iter()
+            .enumerate()
+            .find_map(|(index, breakpoint)| {
+                breakpoint.check(state).map(|trigger| Hit {
+                    tick: self.tick_count,
+                    breakpoint: index,
+                    trigger,
+                })
+            });
+    }
+
+    /// Evaluates the clusters whose inputs changed on the last tick, or that were edited since.
+    fn tick_events(&mut self) {
+        // Clusters that didn't change already hold their current value in both buffers; the
+        // ones that did are brought up to date, and their dependents are evaluated below.
+        for id in std::mem::take(&mut self.changed) {
+            let i = cluster_array_index(id);
+            self.is_changed[i] = false;
+            let value = self.was_powered[i];
+            self.is_powered.set(i, value);
+            for &dependent in self.dependents.get(i) {
+                let j = cluster_array_index(dependent);
+                if !self.is_dirty[j] {
+                    self.is_dirty[j] = true;
+                    self.dirty.push(dependent);
+                }
+            }
+        }
+
+        for id in std::mem::take(&mut self.dirty) {
+            let i = cluster_array_index(id);
+            self.is_dirty[i] = false;
+            let value = self.evaluate(i);
+            self.is_powered.set(i, value);
+            if value != self.was_powered[i] {
+                self.mark_changed(id);
+            }
+        }
+    }
+
+    /// Evaluates every cluster, then finds the ones that changed a word at a time.
+    ///
+    /// With more than one thread, each thread evaluates its own range of words of `is_powered`.
+    /// Every cluster only depends on `was_powered`, so the result is the same either way.
+    fn tick_all(&mut self, threads: usize) {
+        for id in std::mem::take(&mut self.changed) {
+            self.is_changed[cluster_array_index(id)] = false;
+        }
+        for id in std::mem::take(&mut self.dirty) {
+            self.is_dirty[cluster_array_index(id)] = false;
+        }
+
+        let num_clusters = cluster_array_index(self.num_clusters);
+        let mut is_powered = std::mem::take(&mut self.is_powered);
+        let words = is_powered.as_raw_mut_slice();
+        let chunk_len = words.len().div_ceil(threads).max(1);
+        if threads > 1 {
+            let this = &*self;
+            thread::scope(|scope| {
+                for (chunk_index, chunk) in words.chunks_mut(chunk_len).enumerate() {
+                    scope.spawn(move || {
+                        this.evaluate_words(chunk_index * chunk_len, chunk, num_clusters);
+                    });
+                }
+            });
+        } else {
+            self.evaluate_words(0, words, num_clusters);
+        }
+        self.is_powered = is_powered;
+
+        let words = self
+            .is_powered
+            .as_raw_slice()
+            .iter()
+            .zip(self.was_powered.as_raw_slice());
+        for (word_index, (is, was)) in words.enumerate() {
+            let mut diff = is ^ was;
+            while diff != 0 {
+                let i = word_index * usize::BITS as usize + diff.trailing_zeros() as usize;
+                diff &= diff - 1;
+                // The last word can have bits past the end.
+                if i < num_clusters {
+                    self.is_changed[i] = true;
+                    self.changed.push(i as u32);
+                }
+            }
+        }
+    }
+
+    /// Evaluates the clusters in a range of words of `is_powered`, starting at word `first`.
+    fn evaluate_words(&self, first: usize, words: &mut [usize], num_clusters: usize) {
+        const BITS: usize = usize::BITS as usize;
+        for (offset, word) in words.iter_mut().enumerate() {
+            let start = (first + offset) * BITS;
+            let end = (start + BITS).min(num_clusters);
+            let mut value = 0;
+            for i in start..end {
+                if self.evaluate(i) {
+                    value |= 1 << (i - start);
+                }
+            }
+            *word = value;
+        }
+    }
+
+    fn evaluate(&self, i: usize) -> bool {
+        if let Some(value) = self.forced[i] {
+            return value;
+        }
+        self.manual_power[i] > 0
+            || self
+                .flip_inputs
+                .get(i)
+                .iter()
+                .any(|&id| !self.was_powered[cluster_array_index(id)])
+            || self
+                .flop_inputs
+                .get(i)
+                .iter()
+                .any(|&id| self.was_powered[cluster_array_index(id)])
+    }
+
+    fn mark_dirty(&mut self, id: u32) {
+        let i = cluster_array_index(id);
+        if !self.is_dirty[i] {
+            self.is_dirty[i] = true;
+            self.dirty.push(id);
+        }
+    }
+
+    fn mark_changed(&mut self, id: u32) {
+        let i = cluster_array_index(id);
+        if !self.is_changed[i] {
+            self.is_changed[i] = true;
+            self.changed.push(id);
+        }
+    }
+
+    /// The original tick, which evaluates every cluster. Kept as a reference for the
+    /// event-driven one.
+    #[cfg(test)]
+    fn tick_exhaustive(&mut self) {
+        std::mem::swap(&mut self.is_powered, &mut self.was_powered);
+        self.tick_count += 1;
+
+        for i in 0..cluster_array_index(self.num_clusters) {
+            let value = self.evaluate(i);
+            self.is_powered.set(i, value);
+        }
+    }
+}
+
+/// The power state of every cluster at one point in time, copied out of a [`Simulation`] for
+/// drawing.
+#[derive(Default, Clone)]
+pub struct Snapshot {
+    is_powered: BitVec<usize, Lsb0>,
+    was_powered: BitVec<usize, Lsb0>,
+    tick_count: u64,
+    viewed_tick: Option<u64>,
+    oldest_tick: u64,
+    activity: Activity,
+    oscillating: Vec<u32>,
+    breakpoint_hit: Option<Hit>,
+}
+
+impl Snapshot {
+    /// The number of ticks the simulation had run when the snapshot was taken.
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// The past tick that the power states are from, if the simulation was rewound.
+    pub fn viewed_tick(&self) -> Option<u64> {
+        self.viewed_tick
+    }
+
+    /// The oldest tick that the simulation can be rewound to.
+    pub fn oldest_tick(&self) -> u64 {
+        self.oldest_tick
+    }
+
+    /// See [`Simulation::activity`].
+    pub fn activity(&self) -> Activity {
+        self.activity
+    }
+
+    /// See [`Simulation::oscillating_clusters`].
+    pub fn oscillating_clusters(&self) -> &[u32] {
+        &self.oscillating
+    }
+
+    /// See [`Simulation::breakpoint_hit`].
+    pub fn breakpoint_hit(&self) -> Option<Hit> {
+        self.breakpoint_hit
+    }
+
+    pub fn num_clusters(&self) -> u32 {
+        self.is_powered.len() as u32
+    }
+
+    pub fn is_powered(&self, id: u32) -> bool {
+        self.is_powered[cluster_array_index(id)]
+    }
+
+    pub fn was_powered(&self, id: u32) -> bool {
+        self.was_powered[cluster_array_index(id)]
+    }
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Multi-sets of clusters, one per cluster, stored back to back in one array.
+///
+/// Each list has room to grow in place. One that runs out of room is moved to the end of the
+/// array with twice as much, and once more than half of the array is left behind like that, the
+/// lists are packed together again. Long lists, like those of clocks, also keep where each of
+/// their clusters is, so that an edit costs about the same however many there are.
+#[derive(Default, Clone)]
+struct Adjacency {
+    rows: Vec<Row>,
+    // The list of cluster `i` is `targets[rows[i].start..][..rows[i].len]`, and `counts` holds
+    // how many times each of them is in it.
+    targets: Vec<u32>,
+    counts: Vec<u32>,
+    // Slots that no list uses anymore.
+    unused: usize,
+    // The offset of each cluster in the lists that grew too long to search.
+    positions: HashMap<u32, HashMap<u32, u32>>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Row {
+    start: u32,
+    len: u32,
+    capacity: u32,
+}
+
+impl Adjacency {
+    // The room that a list gets when it is first added to.
+    const MIN_CAPACITY: u32 = 2;
+    // Lists longer than this are looked up in `positions` instead of being searched.
+    const MAX_SEARCHED: u32 = 32;
+
+    fn get(&self, i: usize) -> &[u32] {
+        match self.rows.get(i) {
+            Some(row) => &self.targets[row.start as usize..][..row.len as usize],
+            None => &[],
+        }
+    }
+
+    /// Every (cluster, target) pair, once each.
+    fn iter(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (0..self.rows.len()).flat_map(move |i| self.get(i).iter().map(move |&to| (i as u32, to)))
+    }
+
+    /// Where `to` is in the list of cluster `i`, if it is.
+    fn find(&self, i: usize, to: u32) -> Option<usize> {
+        match self.positions.get(&(i as u32)) {
+            Some(positions) => positions.get(&to).map(|&offset| offset as usize),
+            None => self.get(i).iter().position(|&target| target == to),
+        }
+    }
+
+    /// Adds one occurrence of `to` to the list of `from`.
+    fn add(&mut self, from: u32, to: u32) {
+        let i = cluster_array_index(from);
+        if i >= self.rows.len() {
+            self.rows.resize(i + 1, Row::default());
+        }
+        if let Some(offset) = self.find(i, to) {
+            self.counts[self.rows[i].start as usize + offset] += 1;
+            return;
+        }
+        if self.rows[i].len == self.rows[i].capacity {
+            self.grow(i);
+        }
+        let row = &mut self.rows[i];
+        let offset = row.len;
+        let slot = (row.start + offset) as usize;
+        row.len += 1;
+        self.targets[slot] = to;
+        self.counts[slot] = 1;
+        if let Some(positions) = self.positions.get_mut(&from) {
+            positions.insert(to, offset);
+        } else if offset + 1 > Self::MAX_SEARCHED {
+            let positions = self
+                .get(i)
+                .iter()
+                .zip(0..)
+                .map(|(&to, offset)| (to, offset));
+            self.positions.insert(from, positions.collect());
+        }
+    }
+
+    /// Removes one occurrence of `to` from the list of `from`, which has to have it.
+    fn remove(&mut self, from: u32, to: u32) {
+        let i = cluster_array_index(from);
+        let offset = self.find(i, to).expect("connection is not in the list");
+        let row = &mut self.rows[i];
+        let start = row.start as usize;
+        self.counts[start + offset] -= 1;
+        if self.counts[start + offset] == 0 {
+            row.len -= 1;
+            let last = start + row.len as usize;
+            self.targets.swap(start + offset, last);
+            self.counts.swap(start + offset, last);
+            if let Some(positions) = self.positions.get_mut(&from) {
+                positions.remove(&to);
+                if start + offset != last {
+                    positions.insert(self.targets[start + offset], offset as u32);
+                }
+            }
+        }
+    }
+
+    /// Moves the list of cluster `i` to the end, with twice the room.
+    fn grow(&mut self, i: usize) {
+        let row = self.rows[i];
+        let (start, len) = (row.start as usize, row.len as usize);
+        let capacity = (row.capacity * 2).max(Self::MIN_CAPACITY);
+        let new_start = self.targets.len();
+        self.targets.extend_from_within(start..start + len);
+        self.counts.extend_from_within(start..start + len);
+        self.targets.resize(new_start + capacity as usize, 0);
+        self.counts.resize(new_start + capacity as usize, 0);
+        self.rows[i] = Row {
+            start: new_start.try_into().unwrap(),
+            len: row.len,
+            capacity,
+        };
+        self.unused += row.capacity as usize;
+        if self.unused > self.targets.len() / 2 {
+            self.pack();
+        }
+    }
+
+    /// Moves the lists back together, keeping their room.
+    fn pack(&mut self) {
+        let mut targets = Vec::with_capacity(self.targets.len() - self.unused);
+        let mut counts = Vec::with_capacity(targets.capacity());
+        for row in &mut self.rows {
+            let start = row.start as usize;
+            let end = start + row.capacity as usize;
+            row.start = targets.len().try_into().unwrap();
+            targets.extend_from_slice(&self.targets[start..end]);
+            counts.extend_from_slice(&self.counts[start..end]);
+        }
+        self.targets = targets;
+        self.counts = counts;
+        self.unused = 0;
+    }
+}
+
+fn cluster_array_index(idx: u32) -> usize {
+    idx.try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Activity, Adjacency, Breakpoint, Combine, Condition, Simulation, Snapshot};
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn feedback_flip() {
+        let mut sim = Simulation::new();
+
+        let cluster = sim.alloc_cluster();
+        sim.add_flip(cluster, cluster);
+        assert!(!sim.is_powered(cluster));
+        for _ in 0..10 {
+            sim.tick();
+            assert!(sim.is_powered(cluster));
+            sim.tick();
+            assert!(!sim.is_powered(cluster));
+        }
+        sim.remove_flip(cluster, cluster);
+        sim.tick();
+        assert!(!sim.is_powered(cluster));
+        sim.free_cluster(cluster);
+    }
+
+    #[test]
+    fn sr_latch_astable() {
+        let mut sim = Simulation::new();
+
+        let a = sim.alloc_cluster();
+        let b = sim.alloc_cluster();
+        sim.add_flip(a, b);
+        sim.add_flip(b, a);
+
+        for _ in 0..10 {
+            sim.tick();
+            assert!(sim.is_powered(a));
+            assert!(sim.is_powered(b));
+            sim.tick();
+            assert!(!sim.is_powered(a));
+            assert!(!sim.is_powered(a));
+        }
+        sim.remove_flip(a, b);
+        sim.remove_flip(b, a);
+        sim.tick();
+        assert!(!sim.is_powered(a));
+        assert!(!sim.is_powered(a));
+        sim.free_cluster(a);
+        sim.free_cluster(b);
+    }
+
+    #[test]
+    fn sr_latch_stable() {
+        let mut sim = Simulation::new();
+
+        let a = sim.alloc_cluster();
+        let b = sim.alloc_cluster();
+        sim.add_flip(a, b);
+        sim.tick();
+        sim.add_flip(b, a);
+
+        for _ in 0..10 {
+            sim.tick();
+            assert!(!sim.is_powered(a));
+            assert!(sim.is_powered(b));
+        }
+
+        sim.power(a);
+        sim.tick();
+        sim.tick();
+        sim.unpower(a);
+
+        for _ in 0..10 {
+            sim.tick();
+            assert!(sim.is_powered(a));
+            assert!(!sim.is_powered(b));
+        }
+
+        sim.remove_flip(a, b);
+        sim.remove_flip(b, a);
+        sim.free_cluster(a);
+        sim.free_cluster(b);
+    }
+
+    #[test]
+    fn sr_latch_enter_astable() {
+        let mut sim = Simulation::new();
+
+        let a = sim.alloc_cluster();
+        let b = sim.alloc_cluster();
+        sim.add_flip(a, b);
+        sim.tick();
+        sim.add_flip(b, a);
+
+        for _ in 0..10 {
+            sim.tick();
+            assert!(!sim.is_powered(a));
+            assert!(sim.is_powered(b));
+        }
+
+        sim.power(a);
+        sim.tick();
+        sim.unpower(a);
+
+        for _ in 0..10 {
+            sim.tick();
+            assert!(!sim.is_powered(a));
+            assert!(!sim.is_powered(b));
+            sim.tick();
+            assert!(sim.is_powered(a));
+            assert!(sim.is_powered(b));
+        }
+
+        sim.remove_flip(a, b);
+        sim.remove_flip(b, a);
+        sim.free_cluster(a);
+        sim.free_cluster(b);
+    }
+
+    /// xorshift64, so the random graphs are the same on every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn matches_exhaustive_tick() {
+        for seed in 1..=20u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let mut fast = Simulation::new();
+            let mut slow = Simulation::new();
+            let mut live: Vec<u32> = Vec::new();
+            // (is_flop, input, output)
+            let mut gates: Vec<(bool, u32, u32)> = Vec::new();
+            let mut powered: Vec<u32> = Vec::new();
+
+            for _ in 0..2000 {
+                match rng.below(20) {
+                    0..=1 if live.len() < 40 => {
+                        let id = fast.alloc_cluster();
+                        assert_eq!(slow.alloc_cluster(), id);
+                        live.push(id);
+                    }
+                    2..=4 if !live.is_empty() => {
+                        let gate = (
+                            rng.below(2) == 0,
+                            live[rng.below(live.len())],
+                            live[rng.below(live.len())],
+                        );
+                        for sim in [&mut fast, &mut slow] {
+                            if gate.0 {
+                                sim.add_flop(gate.1, gate.2);
+                            } else {
+                                sim.add_flip(gate.1, gate.2);
+                            }
+                        }
+                        gates.push(gate);
+                    }
+                    5 if !gates.is_empty() => {
+                        let (is_flop, inp, out) = gates.swap_remove(rng.below(gates.len()));
+                        for sim in [&mut fast, &mut slow] {
+                            if is_flop {
+                                sim.remove_flop(inp, out);
+                            } else {
+                                sim.remove_flip(inp, out);
+                            }
+                        }
+                    }
+                    6 if !live.is_empty() => {
+                        let id = live[rng.below(live.len())];
+                        fast.power(id);
+                        slow.power(id);
+                        powered.push(id);
+                    }
+                    7 if !powered.is_empty() => {
+                        let id = powered.swap_remove(rng.below(powered.len()));
+                        fast.unpower(id);
+                        slow.unpower(id);
+                    }
+                    8 if !live.is_empty() => {
+                        let id = live[rng.below(live.len())];
+                        let value = rng.below(2) == 0;
+                        fast.set_powered(id, value);
+                        slow.set_powered(id, value);
+                    }
+                    9 if !live.is_empty() => {
+                        let index = rng.below(live.len());
+                        let id = live[index];
+                        let unused = !powered.contains(&id)
+                            && gates.iter().all(|&(_, inp, out)| inp != id && out != id);
+                        if unused {
+                            fast.free_cluster(id);
+                            slow.free_cluster(id);
+                            live.swap_remove(index);
+                        }
+                    }
+                    _ => {
+                        fast.tick();
+                        slow.tick_exhaustive();
+                        assert_eq!(fast.is_powered, slow.is_powered);
+                        assert_eq!(fast.was_powered, slow.was_powered);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_tick_matches_serial() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut serial = Simulation::new();
+        // 1000 clusters don't divide evenly into words, and words don't divide evenly into
+        // threads.
+        let clusters: Vec<u32> = (0..1000).map(|_| serial.alloc_cluster()).collect();
+        for _ in 0..2000 {
+            let inp = clusters[rng.below(clusters.len())];
+            let out = clusters[rng.below(clusters.len())];
+            if rng.below(4) == 0 {
+                serial.add_flop(inp, out);
+            } else {
+                serial.add_flip(inp, out);
+            }
+        }
+        let mut parallel = [serial.clone(), serial.clone()];
+
+        for tick in 0..200 {
+            if tick % 10 == 0 {
+                let id = clusters[rng.below(clusters.len())];
+                let value = rng.below(2) == 0;
+                serial.set_powered(id, value);
+                for sim in &mut parallel {
+                    sim.set_powered(id, value);
+                }
+            }
+            serial.tick_with_threads(1);
+            parallel[0].tick_with_threads(3);
+            parallel[1].tick_with_threads(7);
+            for sim in &parallel {
+                assert_eq!(sim.is_powered, serial.is_powered);
+                assert_eq!(sim.was_powered, serial.was_powered);
+                assert_eq!(sim.changed, serial.changed);
+            }
+        }
+    }
+
+    #[test]
+    fn rewind_and_fork() {
+        let mut rng = Rng(0x853c_49e6_748f_ea9b);
+        let mut sim = Simulation::new();
+        let clusters: Vec<u32> = (0..300).map(|_| sim.alloc_cluster()).collect();
+        for _ in 0..600 {
+            let inp = clusters[rng.below(clusters.len())];
+            let out = clusters[rng.below(clusters.len())];
+            if rng.below(4) == 0 {
+                sim.add_flop(inp, out);
+            } else {
+                sim.add_flip(inp, out);
+            }
+        }
+
+        let mut states = vec![(sim.is_powered.clone(), sim.was_powered.clone())];
+        for tick in 1..=60 {
+            if tick == 30 {
+                // Changes made between ticks are part of the next tick.
+                sim.set_powered(clusters[0], !sim.is_powered(clusters[0]));
+            }
+            sim.tick();
+            states.push((sim.is_powered.clone(), sim.was_powered.clone()));
+        }
+
+        let mut snapshot = Snapshot::default();
+        for tick in [20, 45, 29, 30, 31, 1] {
+            sim.seek(tick);
+            assert_eq!(sim.viewed_tick(), Some(tick));
+            sim.write_snapshot(&mut snapshot);
+            assert_eq!(snapshot.is_powered, states[tick as usize].0);
+            // History only keeps the state after each tick, so the previous state of the tick
+            // right after an edit doesn't include the edit.
+            if tick != 30 {
+                assert_eq!(snapshot.was_powered, states[tick as usize].1);
+            }
+        }
+        sim.seek(60);
+        assert_eq!(sim.viewed_tick(), None);
+        sim.write_snapshot(&mut snapshot);
+        assert_eq!(snapshot.is_powered, states[60].0);
+
+        // Ticking while rewound carries on from the viewed tick.
+        sim.seek(25);
+        sim.tick();
+        assert_eq!(sim.viewed_tick(), None);
+        assert_eq!(sim.tick_count(), 26);
+        assert_eq!(sim.is_powered, states[26].0);
+        assert_eq!(sim.was_powered, states[26].1);
+        for (is_powered, _) in &states[27..=29] {
+            sim.tick();
+            assert_eq!(&sim.is_powered, is_powered);
+        }
+
+        // The past is kept, and the forgotten future can't be reached anymore.
+        assert_eq!(sim.oldest_tick(), 0);
+        sim.seek(10);
+        sim.write_snapshot(&mut snapshot);
+        assert_eq!(snapshot.is_powered, states[10].0);
+        sim.seek(50);
+        assert_eq!(sim.viewed_tick(), None);
+    }
+
+    #[test]
+    fn detects_oscillation() {
+        let mut sim = Simulation::new();
+        // A ring of three flips, and a flip driven by a cluster that never changes.
+        let ring: Vec<u32> = (0..3).map(|_| sim.alloc_cluster()).collect();
+        for i in 0..3 {
+            sim.add_flip(ring[i], ring[(i + 1) % 3]);
+        }
+        let quiet = sim.alloc_cluster();
+        let inverted = sim.alloc_cluster();
+        sim.add_flip(quiet, inverted);
+        assert_eq!(sim.activity(), Activity::Unsettled);
+
+        for _ in 0..20 {
+            sim.tick();
+        }
+        match sim.activity() {
+            // Every flip of the ring starts out the same, so they all toggle together.
+            Activity::Oscillating { period, .. } => assert_eq!(period, 2),
+            other => panic!("expected an oscillation, got {:?}", other),
+        }
+        assert_eq!(sim.oscillating_clusters(), &ring[..]);
+
+        // Breaking the ring lets it settle.
+        sim.remove_flip(ring[2], ring[0]);
+        assert_eq!(sim.activity(), Activity::Unsettled);
+        assert!(sim.oscillating_clusters().is_empty());
+        for _ in 0..20 {
+            sim.tick();
+        }
+        match sim.activity() {
+            Activity::Stable { since } => assert!(since < 30),
+            other => panic!("expected a stable state, got {:?}", other),
+        }
+        assert!(sim.oscillating_clusters().is_empty());
+    }
+
+    #[test]
+    fn run_until_stable() {
+        let mut sim = Simulation::new();
+        let a = sim.alloc_cluster();
+        let b = sim.alloc_cluster();
+        let c = sim.alloc_cluster();
+        sim.add_flip(a, b);
+        sim.add_flop(b, c);
+        assert_eq!(sim.run_until_stable(100), (2, true));
+        assert!(sim.is_powered(b));
+        assert!(sim.is_powered(c));
+        assert_eq!(sim.run_until_stable(100), (0, true));
+
+        sim.power(a);
+        assert_eq!(sim.run_until_stable(1), (1, false));
+        assert_eq!(sim.run_until_stable(100), (2, true));
+        assert!(!sim.is_powered(c));
+
+        // A flip feeding itself never settles.
+        sim.add_flip(c, c);
+        assert_eq!(sim.run_until_stable(50), (50, false));
+    }
+
+    #[test]
+    fn force() {
+        let mut sim = Simulation::new();
+        let a = sim.alloc_cluster();
+        let b = sim.alloc_cluster();
+        sim.add_flip(a, b);
+
+        // Forcing overrides the flip both ways, until it is released.
+        sim.force(b, Some(false));
+        sim.tick();
+        assert!(!sim.is_powered(b));
+        sim.power(a);
+        sim.force(b, Some(true));
+        sim.tick();
+        assert!(sim.is_powered(b));
+        assert!(sim.is_powered(a));
+        assert_eq!(sim.forced_clusters().collect::<Vec<_>>(), [(b, true)]);
+        sim.force(b, None);
+        sim.tick();
+        assert!(!sim.is_powered(b));
+        assert_eq!(sim.forced_clusters().count(), 0);
+
+        // A freed cluster isn't forced when it is handed out again.
+        let c = sim.alloc_cluster();
+        sim.force(c, Some(true));
+        sim.free_cluster(c);
+        assert_eq!(sim.alloc_cluster(), c);
+        assert_eq!(sim.forced(c), None);
+    }
+
+    #[test]
+    fn probes_follow_clusters() {
+        let mut sim = Simulation::new();
+        let low = sim.alloc_cluster();
+        let high = sim.alloc_cluster();
+        sim.power(high);
+        sim.set_probes(vec![(1, Some(low))]);
+        for _ in 0..5 {
+            sim.tick();
+        }
+
+        // Moving the probe to another cluster carries on with the same signal.
+        sim.set_probes(vec![(1, Some(high)), (2, None)]);
+        sim.tick();
+        let signal = sim.trace().signal(1).unwrap();
+        assert_eq!(
+            signal.changes().collect::<Vec<_>>(),
+            [(0, Some(false)), (6, Some(true))]
+        );
+        assert_eq!(signal.value_at(5), Some(false));
+        assert_eq!(signal.value_at(6), Some(true));
+        let recent = sim.trace().since(6);
+        assert_eq!(
+            recent.signal(1).unwrap().changes().collect::<Vec<_>>(),
+            [(6, Some(true))]
+        );
+        assert_eq!(recent.signal(1).unwrap().value_at(6), Some(true));
+        assert_eq!(
+            sim.trace().signal(2).unwrap().changes().collect::<Vec<_>>(),
+            [(5, None)]
+        );
+
+        // Going back in time and ticking from there forgets what came after.
+        sim.seek(3);
+        sim.tick();
+        assert_eq!(sim.trace().end(), 4);
+        assert_eq!(
+            sim.trace().signal(2).unwrap().changes().collect::<Vec<_>>(),
+            [(4, None)]
+        );
+        assert_eq!(
+            sim.trace().signal(1).unwrap().changes().collect::<Vec<_>>(),
+            [(0, Some(false)), (4, Some(true))]
+        );
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut sim = Simulation::new();
+        // `a` toggles every tick, and `b` follows it one tick later.
+        let a = sim.alloc_cluster();
+        let b = sim.alloc_cluster();
+        sim.add_flip(a, a);
+        sim.add_flop(a, b);
+        sim.set_breakpoints(vec![
+            Breakpoint {
+                triggers: vec![(a, Condition::High), (b, Condition::Falls)],
+                combine: Combine::All,
+            },
+            Breakpoint {
+                triggers: vec![(b, Condition::Low), (b, Condition::Rises)],
+                combine: Combine::Any,
+            },
+        ]);
+
+        // `b` is low from the start.
+        sim.tick();
+        let hit = sim.breakpoint_hit().unwrap();
+        assert_eq!((hit.tick, hit.breakpoint, hit.trigger), (1, 1, 0));
+
+        // The hit stays until it is cleared, even though later ticks don't fire.
+        sim.set_breakpoints(vec![Breakpoint {
+            triggers: vec![(a, Condition::High), (b, Condition::Falls)],
+            combine: Combine::All,
+        }]);
+        sim.tick();
+        assert_eq!(sim.breakpoint_hit(), Some(hit));
+        sim.clear_breakpoint_hit();
+
+        // `a` is high after odd ticks, and `b` falls after odd ticks from the third one on.
+        while sim.breakpoint_hit().is_none() {
+            sim.tick();
+        }
+        let hit = sim.breakpoint_hit().unwrap();
+        assert_eq!((hit.tick, hit.breakpoint, hit.trigger), (3, 0, 1));
+        assert!(sim.is_powered(a));
+        assert!(!sim.is_powered(b));
+    }
+
+    #[test]
+    fn adjacency_matches_multiset() {
+        let mut rng = Rng(0x1405_7b7e_f767_814f);
+        let mut adjacency = Adjacency::default();
+        let mut expected: HashMap<(u32, u32), u32> = HashMap::new();
+        // Few enough lists that they outgrow their room and get moved many times, and long
+        // enough that they are indexed.
+        for _ in 0..20_000 {
+            let key = (rng.below(30) as u32, rng.below(100) as u32);
+            if rng.below(3) == 0 {
+                if let Some(count) = expected.get_mut(&key) {
+                    adjacency.remove(key.0, key.1);
+                    *count -= 1;
+                    if *count == 0 {
+                        expected.remove(&key);
+                    }
+                }
+            } else {
+                adjacency.add(key.0, key.1);
+                *expected.entry(key).or_insert(0) += 1;
+            }
+        }
+        let mut pairs: Vec<(u32, u32)> = adjacency.iter().collect();
+        pairs.sort_unstable();
+        let mut keys: Vec<(u32, u32)> = expected.keys().copied().collect();
+        keys.sort_unstable();
+        assert_eq!(pairs, keys);
+        assert!(adjacency.unused <= adjacency.targets.len() / 2);
+        for (&from, positions) in &adjacency.positions {
+            for (&to, &offset) in positions {
+                assert_eq!(adjacency.get(from as usize)[offset as usize], to);
+            }
+            assert_eq!(positions.len(), adjacency.get(from as usize).len());
+        }
+    }
+
+    /// The simulation as it was before connections were kept in flat arrays, for comparing
+    /// speed against: a multi-set of inputs per cluster, and every cluster evaluated on every
+    /// tick.
+    struct Baseline {
+        is_powered: Vec<bool>,
+        was_powered: Vec<bool>,
+        flips: Vec<HashMap<u32, u32>>,
+        flops: Vec<HashMap<u32, u32>>,
+    }
+
+    impl Baseline {
+        fn tick(&mut self) {
+            std::mem::swap(&mut self.is_powered, &mut self.was_powered);
+            for i in 0..self.is_powered.len() {
+                self.is_powered[i] = self.flips[i]
+                    .keys()
+                    .any(|&id| !self.was_powered[id as usize])
+                    || self.flops[i]
+                        .keys()
+                        .any(|&id| self.was_powered[id as usize]);
+            }
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored tick_speed --nocapture`.
+    #[test]
+    #[ignore = "timing; only meaningful in release builds"]
+    fn tick_speed() {
+        const CLUSTERS: usize = 1 << 18;
+        const TICKS: u32 = 200;
+        // A large circuit that mostly sits still, like a design waiting on a slow clock: a ring
+        // of flips that keeps running, driving long chains of flops that pass its pulses along,
+        // and many gates that are wired up but settle after the first few ticks.
+        let mut rng = Rng(0xda94_2042_e4dd_58b5);
+        let mut sim = Simulation::new();
+        let mut baseline = Baseline {
+            is_powered: vec![false; CLUSTERS],
+            was_powered: vec![false; CLUSTERS],
+            flips: vec![HashMap::new(); CLUSTERS],
+            flops: vec![HashMap::new(); CLUSTERS],
+        };
+        let clusters: Vec<u32> = (0..CLUSTERS).map(|_| sim.alloc_cluster()).collect();
+        let mut connect = |is_flop: bool, inp: u32, out: u32| {
+            let list = match is_flop {
+                true => &mut baseline.flops,
+                false => &mut baseline.flips,
+            };
+            *list[out as usize].entry(inp).or_insert(0) += 1;
+            match is_flop {
+                true => sim.add_flop(inp, out),
+                false => sim.add_flip(inp, out),
+            }
+        };
+        for i in 0..3 {
+            connect(false, clusters[i], clusters[(i + 1) % 3]);
+        }
+        for chain in 0..16 {
+            let mut previous = clusters[0];
+            for i in 0..1000 {
+                let next = clusters[3 + chain * 1000 + i];
+                connect(true, previous, next);
+                previous = next;
+            }
+        }
+        // The rest are lit by a flip from a cluster that nothing drives, and some also by a
+        // flop from another one of them, which is lit already.
+        let ground = clusters[3 + 16 * 1000];
+        for i in 4 + 16 * 1000..CLUSTERS {
+            connect(false, ground, clusters[i]);
+            if rng.below(8) == 0 {
+                let other = 4 + 16 * 1000 + rng.below(i - 4 - 16 * 1000);
+                connect(true, clusters[other], clusters[i]);
+            }
+        }
+
+        let time = |tick: &mut dyn FnMut()| {
+            let start = Instant::now();
+            for _ in 0..TICKS {
+                tick();
+            }
+            start.elapsed()
+        };
+        let flat = time(&mut || sim.tick());
+        let before = time(&mut || baseline.tick());
+        for (id, &powered) in baseline.is_powered.iter().enumerate() {
+            assert_eq!(sim.is_powered(id as u32), powered);
+        }
+        let tps = |elapsed: Duration| TICKS as f64 / elapsed.as_secs_f64();
+        println!(
+            "{} clusters: {:.0} ticks/s, baseline {:.0} ticks/s ({:.1}x)",
+            CLUSTERS,
+            tps(flat),
+            tps(before),
+            tps(flat) / tps(before)
+        );
+        assert!(tps(flat) >= 10.0 * tps(before));
+    }
+}
@@ -0,0 +1,153 @@
+//! Recordings of the values of probed clusters, tick by tick.
+
+use std::collections::VecDeque;
+
+// The oldest changes of a signal are dropped past this many.
+const MAX_CHANGES: usize = 1 << 20;
+
+/// The recorded values of every probe, as [`Signal`]s.
+#[derive(Debug, Default, Clone)]
+pub struct Trace {
+    signals: Vec<Signal>,
+    // The newest tick that was recorded.
+    end: u64,
+}
+
+/// The values of one probe, stored as the ticks on which they changed.
+#[derive(Debug, Clone)]
+pub struct Signal {
+    id: u32,
+    cluster: Option<u32>,
+    // The value after each tick that it changed on, oldest first. `None` is unknown, for when
+    // the probe isn't on a cluster.
+    changes: VecDeque<(u64, Option<bool>)>,
+}
+
+impl Trace {
+    pub fn signals(&self) -> &[Signal] {
+        &self.signals
+    }
+
+    pub fn signal(&self, id: u32) -> Option<&Signal> {
+        self.signals.iter().find(|signal| signal.id == id)
+    }
+
+    /// The newest tick that was recorded.
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// The oldest tick that any signal has a value for.
+    pub fn start(&self) -> Option<u64> {
+        self.signals
+            .iter()
+            .filter_map(|signal| signal.changes.front())
+            .map(|&(tick, _)| tick)
+            .min()
+    }
+
+    /// A copy with only the values from `tick` on, for when the ones before aren't needed.
+    pub fn since(&self, tick: u64) -> Trace {
+        Trace {
+            signals: self
+                .signals
+                .iter()
+                .map(|signal| signal.since(tick))
+                .collect(),
+            end: self.end,
+        }
+    }
+
+    /// Replaces the probes, each one an ID and the cluster that it is on now. Signals of IDs
+    /// that are still there carry on from where they were, and new ones start at `tick` with
+    /// the value given by `state`.
+    pub(super) fn set_probes<F>(&mut self, probes: Vec<(u32, Option<u32>)>, tick: u64, state: F)
+    where
+        F: Fn(u32) -> bool,
+    {
+        let mut old = std::mem::take(&mut self.signals);
+        for (id, cluster) in probes {
+            let signal = match old.iter().position(|signal| signal.id == id) {
+                Some(index) => {
+                    let mut signal = old.swap_remove(index);
+                    signal.cluster = cluster;
+                    signal
+                }
+                None => {
+                    let mut signal = Signal {
+                        id,
+                        cluster,
+                        changes: VecDeque::new(),
+                    };
+                    signal.push(tick, cluster.map(&state));
+                    signal
+                }
+            };
+            self.signals.push(signal);
+        }
+        self.end = self.end.max(tick);
+    }
+
+    /// Notes the values after `tick`.
+    pub(super) fn record<F>(&mut self, tick: u64, state: F)
+    where
+        F: Fn(u32) -> bool,
+    {
+        for signal in &mut self.signals {
+            signal.push(tick, signal.cluster.map(&state));
+        }
+        self.end = tick;
+    }
+
+    /// Forgets every value after `tick`, because the simulation went back to it.
+    pub(super) fn truncate(&mut self, tick: u64) {
+        for signal in &mut self.signals {
+            while signal.changes.back().is_some_and(|&(t, _)| t > tick) {
+                signal.changes.pop_back();
+            }
+        }
+        self.end = self.end.min(tick);
+    }
+}
+
+impl Signal {
+    /// The ID that the probe was given.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Every tick that the value changed on, with the value after it. The first one is where
+    /// the recording starts.
+    pub fn changes(&self) -> impl Iterator<Item = (u64, Option<bool>)> + '_ {
+        self.changes.iter().copied()
+    }
+
+    /// The value after `tick`, if it is known.
+    pub fn value_at(&self, tick: u64) -> Option<bool> {
+        let index = self.changes.partition_point(|&(t, _)| t <= tick);
+        index.checked_sub(1).and_then(|index| self.changes[index].1)
+    }
+
+    fn since(&self, tick: u64) -> Signal {
+        // The change before `tick` is kept, since it gives the value at `tick`.
+        let start = self
+            .changes
+            .partition_point(|&(t, _)| t <= tick)
+            .saturating_sub(1);
+        Signal {
+            id: self.id,
+            cluster: self.cluster,
+            changes: self.changes.range(start..).copied().collect(),
+        }
+    }
+
+    fn push(&mut self, tick: u64, value: Option<bool>) {
+        if self.changes.back().is_some_and(|&(_, last)| last == value) {
+            return;
+        }
+        self.changes.push_back((tick, value));
+        if self.changes.len() > MAX_CHANGES {
+            self.changes.pop_front();
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Counts of what a circuit is made of, and how long it takes to settle.
+
+use crate::circuit::{Circuit, ComponentType};
+use glam::IVec2;
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Statistics {
+    pub flips: usize,
+    pub flops: usize,
+    pub switches: usize,
+    pub lamps: usize,
+    pub pins: usize,
+    pub chips: usize,
+    pub wires: usize,
+    /// The length of all wires together, in tiles.
+    pub wire_length: u64,
+    pub labels: usize,
+    pub chip_definitions: usize,
+    pub clusters: usize,
+    /// The flips and flops that are simulated, including those inside chips.
+    pub simulated_flips: usize,
+    pub simulated_flops: usize,
+    /// The bottom left and top right tiles that anything is on.
+    pub bounds: Option<(IVec2, IVec2)>,
+    /// How many ticks it takes the circuit to settle after it is loaded, or `None` if it
+    /// doesn't within `settle_budget`.
+    pub settle_ticks: Option<u64>,
+    pub settle_budget: u64,
+}
+
+impl Statistics {
+    /// Counts everything in `circuit`, and runs a copy of it for at most `settle_budget` ticks.
+    pub fn new(circuit: &Circuit, settle_budget: u64) -> anyhow::Result<Self> {
+        // A fresh copy has no clusters left over from edits.
+        let mut circuit = circuit.duplicate()?;
+        let schematic = circuit.schematic();
+        let mut statistics = Self {
+            wires: schematic.wires.len(),
+            wire_length: schematic
+                .wires
+                .iter()
+                .map(|wire| (wire.end - wire.start).abs().max_element() as u64)
+                .sum(),
+            labels: schematic.labels.len(),
+            chip_definitions: schematic.chips.len(),
+            simulated_flips: circuit.simulation().flips().count(),
+            simulated_flops: circuit.simulation().flops().count(),
+            settle_budget,
+            ..Self::default()
+        };
+        for component in &schematic.components {
+            let count = match component.ty {
+                ComponentType::Flip => &mut statistics.flips,
+                ComponentType::Flop => &mut statistics.flops,
+                ComponentType::Switch => &mut statistics.switches,
+                ComponentType::Lamp => &mut statistics.lamps,
+                ComponentType::Pin => &mut statistics.pins,
+                ComponentType::Chip(..) => &mut statistics.chips,
+            };
+            *count += 1;
+        }
+        // Cluster IDs are not reused right away, so only those that are connected count.
+        let mut clusters = BTreeSet::new();
+        for (position, cluster) in circuit.cluster_tiles() {
+            clusters.insert(cluster);
+            let (min, max) = statistics.bounds.get_or_insert((position, position));
+            *min = min.min(position);
+            *max = max.max(position);
+        }
+        let simulation = circuit.simulation();
+        for (input, output) in simulation.flips().chain(simulation.flops()) {
+            clusters.extend([input, output]);
+        }
+        statistics.clusters = clusters.len();
+        let (ticks, settled) = circuit.run_until_stable(settle_budget);
+        statistics.settle_ticks = settled.then_some(ticks);
+        Ok(statistics)
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "components: {} flips, {} flops, {} switches, {} lamps, {} pins, {} chips",
+            self.flips, self.flops, self.switches, self.lamps, self.pins, self.chips
+        )?;
+        writeln!(f, "wires: {}, {} tiles long", self.wires, self.wire_length)?;
+        writeln!(f, "labels: {}", self.labels)?;
+        writeln!(f, "chip definitions: {}", self.chip_definitions)?;
+        writeln!(
+            f,
+            "simulation: {} clusters, {} flips, {} flops",
+            self.clusters, self.simulated_flips, self.simulated_flops
+        )?;
+        match self.bounds {
+            Some((min, max)) => {
+                let size = max - min + 1;
+                writeln!(
+                    f,
+                    "size: {} x {} tiles, from ({}, {}) to ({}, {})",
+                    size.x, size.y, min.x, min.y, max.x, max.y
+                )?;
+            }
+            None => writeln!(f, "size: empty")?,
+        }
+        match self.settle_ticks {
+            Some(ticks) => write!(f, "settles at tick {}", ticks),
+            None => write!(f, "does not settle by tick {}", self.settle_budget),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Statistics;
+    use crate::circuit::tests::{place_chip_instance, place_inverter};
+    use crate::circuit::Circuit;
+    use glam::IVec2;
+
+    #[test]
+    fn counts_circuit() {
+        let mut circuit = Circuit::new();
+        place_inverter(&mut circuit, 0);
+
+        let statistics = Statistics::new(&circuit, 100).unwrap();
+        assert_eq!(
+            statistics.to_string(),
+            "components: 1 flips, 0 flops, 1 switches, 1 lamps, 0 pins, 0 chips\n\
+             wires: 2, 4 tiles long\n\
+             labels: 0\n\
+             chip definitions: 0\n\
+             simulation: 3 clusters, 1 flips, 1 flops\n\
+             size: 5 x 1 tiles, from (0, 0) to (4, 0)\n\
+             settles at tick 1"
+        );
+    }
+
+    #[test]
+    fn counts_empty_circuit() {
+        let statistics = Statistics::new(&Circuit::new(), 100).unwrap();
+        assert_eq!(statistics.clusters, 0);
+        assert_eq!(statistics.bounds, None);
+        assert_eq!(statistics.settle_ticks, Some(0));
+        assert!(statistics.to_string().contains("\nsize: empty\n"));
+    }
+
+    #[test]
+    fn counts_chips() {
+        // Two inverters, one of which is made into a chip that is placed once more.
+        let mut circuit = Circuit::new();
+        place_inverter(&mut circuit, 0);
+        place_inverter(&mut circuit, 4);
+        let id = circuit
+            .define_chip(IVec2::new(0, 0), IVec2::new(4, 0))
+            .unwrap();
+        place_chip_instance(&mut circuit, id);
+
+        let statistics = Statistics::new(&circuit, 100).unwrap();
+        assert_eq!((statistics.flips, statistics.switches), (2, 3));
+        assert_eq!((statistics.chips, statistics.chip_definitions), (1, 1));
+        // The chip's flip is simulated, but not placed, and its switch is only a port.
+        assert_eq!(statistics.simulated_flips, 3);
+        assert_eq!(statistics.simulated_flops, 3);
+        assert_eq!(
+            statistics.bounds,
+            Some((IVec2::new(-2, 0), IVec2::new(4, 10)))
+        );
+        assert_eq!(statistics.settle_ticks, Some(1));
+    }
+}
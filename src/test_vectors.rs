@@ -0,0 +1,339 @@
+//! Test vectors: files that say which inputs to apply to a circuit and which outputs to expect.
+//!
+//! A file names the switches and lamps that it uses, then lists one vector per line:
+//!
+//! ```text
+//! # A half adder.
+//! inputs a b
+//! outputs sum carry
+//! 00 stable 00
+//! 10 stable 10
+//! 11 4      01
+//! ```
+//!
+//! Ports are named by the label on their tile (or the name that [`Circuit::ports`] gives them),
+//! or by their coordinates, like `3,-2`. Each vector has a digit for every input, or `-` to
+//! leave a switch as it is; then how long to wait, either a number of ticks or `stable` to run
+//! until the circuit settles; then a digit for every output, or `x` for one that may be either.
+//! Blank lines and everything after a `#` are ignored.
+//!
+//! Switches are toggled on a [`Harness`], which starts out with every wire unpowered, so the
+//! first vector usually waits until it is `stable`.
+
+use crate::circuit::{Circuit, ComponentType, Port, PortRef};
+use crate::harness::Harness;
+use anyhow::{bail, ensure, Context};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A vector that waits until the circuit is `stable` fails if it takes more ticks than this.
+pub const STABLE_BUDGET: u64 = 10_000;
+
+pub struct TestVectors {
+    inputs: Vec<PortRef>,
+    outputs: Vec<PortRef>,
+    vectors: Vec<Vector>,
+}
+
+struct Vector {
+    line: usize,
+    inputs: Vec<Option<bool>>,
+    wait: Wait,
+    outputs: Vec<Option<bool>>,
+}
+
+enum Wait {
+    Ticks(u64),
+    Stable,
+}
+
+/// What came of running test vectors.
+pub struct Report {
+    pub vectors: usize,
+    pub failures: Vec<Failure>,
+}
+
+pub struct Failure {
+    /// The line of the vector in the file.
+    pub line: usize,
+    /// The tick of the simulation when the outputs were checked.
+    pub tick: u64,
+    pub problem: Problem,
+}
+
+pub enum Problem {
+    /// An output was the opposite of `expected`.
+    Mismatch {
+        output: String,
+        expected: bool,
+    },
+    Unsettled,
+}
+
+impl TestVectors {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut inputs: Option<Vec<PortRef>> = None;
+        let mut outputs: Option<Vec<PortRef>> = None;
+        let mut vectors = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let words: Vec<&str> = line
+                .split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect();
+            let result = (|| {
+                match words[..] {
+                    [] => {}
+                    ["inputs", ref names @ ..] => {
+                        ensure!(inputs.is_none(), "inputs are already named");
+                        ensure!(vectors.is_empty(), "inputs must be named before any vectors");
+                        inputs = Some(names.iter().map(|name| PortRef::parse(name)).collect());
+                    }
+                    ["outputs", ref names @ ..] => {
+                        ensure!(outputs.is_none(), "outputs are already named");
+                        ensure!(vectors.is_empty(), "outputs must be named before any vectors");
+                        outputs = Some(names.iter().map(|name| PortRef::parse(name)).collect());
+                    }
+                    [applied, wait, expected] => {
+                        let (Some(inputs), Some(outputs)) = (&inputs, &outputs) else {
+                            bail!("inputs and outputs must be named before any vectors");
+                        };
+                        vectors.push(Vector {
+                            line: number,
+                            inputs: parse_bits(applied, '-', inputs, "inputs")?,
+                            wait: Wait::parse(wait)?,
+                            outputs: parse_bits(expected, 'x', outputs, "outputs")?,
+                        });
+                    }
+                    _ => bail!(
+                        "expected `inputs`, `outputs`, or inputs, a wait and outputs, but found `{}`",
+                        line.trim()
+                    ),
+                }
+                Ok(())
+            })();
+            result.with_context(|| format!("on line {}", number))?;
+        }
+        Ok(Self {
+            inputs: inputs.context("no inputs are named")?,
+            outputs: outputs.context("no outputs are named")?,
+            vectors,
+        })
+    }
+
+    /// Applies every vector in turn to a copy of `circuit`, and checks its outputs. Fails if a
+    /// port can't be found, but not if the circuit doesn't do what is expected of it.
+    pub fn run(&self, circuit: &Circuit) -> anyhow::Result<Report> {
+        let switches = self
+            .inputs
+            .iter()
+            .map(|port| circuit.find_port(port, ComponentType::Switch))
+            .collect::<anyhow::Result<Vec<Port>>>()?;
+        let lamps = self
+            .outputs
+            .iter()
+            .map(|port| circuit.find_port(port, ComponentType::Lamp))
+            .collect::<anyhow::Result<Vec<Port>>>()?;
+        let mut harness = Harness::new(circuit, &switches, &lamps)?;
+
+        let mut failures = Vec::new();
+        for vector in &self.vectors {
+            harness.set_inputs(vector.inputs.iter().copied());
+            let settled = match vector.wait {
+                Wait::Ticks(ticks) => {
+                    harness.tick(ticks);
+                    true
+                }
+                Wait::Stable => harness.run_until_stable(STABLE_BUDGET).1,
+            };
+            let tick = harness.tick_count();
+            if !settled {
+                failures.push(Failure {
+                    line: vector.line,
+                    tick,
+                    problem: Problem::Unsettled,
+                });
+            }
+            let outputs = harness.outputs();
+            let expected = lamps.iter().zip(outputs).zip(&vector.outputs);
+            for ((lamp, powered), &value) in expected {
+                if let Some(value) = value.filter(|&value| powered != value) {
+                    failures.push(Failure {
+                        line: vector.line,
+                        tick,
+                        problem: Problem::Mismatch {
+                            output: lamp.name.clone(),
+                            expected: value,
+                        },
+                    });
+                }
+            }
+        }
+
+        Ok(Report {
+            vectors: self.vectors.len(),
+            failures,
+        })
+    }
+}
+
+impl Wait {
+    fn parse(word: &str) -> anyhow::Result<Self> {
+        match word {
+            "stable" => Ok(Self::Stable),
+            _ => word
+                .parse()
+                .map(Self::Ticks)
+                .with_context(|| format!("expected a number of ticks or `stable`, not `{}`", word)),
+        }
+    }
+}
+
+/// One value for each of `ports`, with `any` for those that are left out.
+fn parse_bits(
+    word: &str,
+    any: char,
+    ports: &[PortRef],
+    kind: &str,
+) -> anyhow::Result<Vec<Option<bool>>> {
+    let bits = word
+        .chars()
+        .map(|c| match c {
+            '0' => Ok(Some(false)),
+            '1' => Ok(Some(true)),
+            c if c == any => Ok(None),
+            c => bail!("invalid {} `{}`; expected 0, 1 or {}", kind, c, any),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    ensure!(
+        bits.len() == ports.len(),
+        "`{}` has {} {}, but {} are named",
+        word,
+        bits.len(),
+        kind,
+        ports.len()
+    );
+    Ok(bits)
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for failure in &self.failures {
+            writeln!(f, "{}", failure)?;
+        }
+        let failed: BTreeSet<usize> = self.failures.iter().map(|failure| failure.line).collect();
+        write!(
+            f,
+            "{} of {} vectors passed",
+            self.vectors - failed.len(),
+            self.vectors
+        )
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, tick {}: ", self.line, self.tick)?;
+        match &self.problem {
+            Problem::Mismatch { output, expected } => write!(
+                f,
+                "expected `{}` to be {}, but it is {}",
+                output, *expected as u8, !*expected as u8
+            ),
+            Problem::Unsettled => write!(f, "did not settle within {} ticks", STABLE_BUDGET),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestVectors;
+    use crate::circuit::tests::{place_chip_instance, place_inverter};
+    use crate::circuit::Circuit;
+    use crate::schematic::Anchor;
+    use glam::IVec2;
+
+    #[test]
+    fn runs_vectors() {
+        let mut circuit = Circuit::new();
+        place_inverter(&mut circuit, 0);
+        circuit.set_label(Anchor::Tile(IVec2::new(0, 0)), "a");
+
+        let vectors = TestVectors::parse(
+            "# An inverter.\n\
+             inputs a\n\
+             outputs 4,0 # by position\n\
+             0 stable 1\n\
+             1 2 x\n\
+             - 1 0\n\
+             0 2 1 # too soon\n\
+             - stable 1\n",
+        )
+        .unwrap();
+        let report = vectors.run(&circuit).unwrap();
+        assert!(!report.passed());
+        assert_eq!(
+            report.to_string(),
+            "line 7, tick 6: expected `4,0` to be 1, but it is 0\n\
+             4 of 5 vectors passed"
+        );
+        let missing = TestVectors::parse("inputs b\noutputs 4,0\n0 1 x\n").unwrap();
+        assert!(missing.run(&circuit).is_err());
+        let not_a_lamp = TestVectors::parse("inputs a\noutputs 2,0\n0 1 x\n").unwrap();
+        assert!(not_a_lamp.run(&circuit).is_err());
+    }
+
+    #[test]
+    fn runs_vectors_on_chips() {
+        // An inverter next to a chip made from it, which switches just as fast.
+        let mut circuit = Circuit::new();
+        place_inverter(&mut circuit, 0);
+        let id = circuit
+            .define_chip(IVec2::new(0, 0), IVec2::new(4, 0))
+            .unwrap();
+        place_chip_instance(&mut circuit, id);
+        circuit.set_label(Anchor::Tile(IVec2::new(0, 0)), "a");
+        circuit.set_label(Anchor::Tile(IVec2::new(-2, 10)), "b");
+
+        let vectors = TestVectors::parse(
+            "inputs a b\n\
+             outputs 4,0 2,10\n\
+             00 stable 11\n\
+             11 stable 00\n\
+             01 stable 10\n\
+             10 3      01\n",
+        )
+        .unwrap();
+        let report = vectors.run(&circuit).unwrap();
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.to_string(), "4 of 4 vectors passed");
+    }
+
+    #[test]
+    fn needs_ports() {
+        let vectors = TestVectors::parse("inputs a\noutputs b\n0 stable 1\n").unwrap();
+        assert!(vectors.run(&Circuit::new()).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        for text in [
+            "inputs a\n0 1 1\n",
+            "inputs a\noutputs b\n01 1 1\n",
+            "inputs a\noutputs b\n0 soon 1\n",
+            "inputs a\noutputs b\n0 1 -\n",
+            "inputs a\noutputs b\n0 1 1\ninputs c\n",
+            "inputs a\noutputs b\n0 1\n",
+        ] {
+            assert!(TestVectors::parse(text).is_err(), "{:?}", text);
+        }
+    }
+}
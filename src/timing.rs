@@ -0,0 +1,338 @@
+//! A panel along an edge of the window that shows the most recent ticks of the probes as
+//! waveforms.
+//!
+//! The panel is drawn in pixels rather than tiles, with a [`Viewport`] of its own whose camera
+//! is fixed so that one unit is one pixel, with the origin in the bottom left corner.
+
+use crate::probes::Probes;
+use crate::rect::{self, Color, Rect, RectRenderer};
+use crate::simulation::Trace;
+use crate::viewport::Viewport;
+use crate::worker::SimulationWorker;
+use crate::GraphicsContext;
+use glam::{Vec2, Vec4};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
+use wgpu_glyph::{GlyphBrush, Section, Text};
+
+// How many ticks are shown at once.
+const TICKS: u64 = 100;
+// Sizes in pixels.
+const LABEL_WIDTH: f32 = 120.0;
+const RULER_HEIGHT: f32 = 22.0;
+const ROW_HEIGHT: f32 = 24.0;
+const PADDING: f32 = 6.0;
+const LINE_WIDTH: f32 = 2.0;
+const TEXT_SCALE: f32 = 16.0;
+// Ruler labels are this many ticks apart.
+const LABEL_INTERVAL: u64 = 10;
+
+const BACKGROUND_COLOR: Vec4 = Vec4::new(0.08, 0.08, 0.08, 1.0);
+const RULER_COLOR: Vec4 = Vec4::new(0.5, 0.5, 0.5, 1.0);
+const SIGNAL_COLOR: Vec4 = Vec4::new(0.2, 1.0, 0.2, 1.0);
+const UNKNOWN_COLOR: Vec4 = Vec4::new(0.35, 0.35, 0.35, 1.0);
+const VIEWED_COLOR: Vec4 = Vec4::new(1.0, 0.5, 0.0, 1.0);
+
+const BACKGROUND_Z_INDEX: u8 = 1;
+const SIGNAL_Z_INDEX: u8 = 2;
+const VIEWED_Z_INDEX: u8 = 3;
+
+/// The edge of the window that the panel is docked to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dock {
+    Bottom,
+    Top,
+}
+
+pub struct TimingDiagram {
+    gfx: GraphicsContext,
+    viewport: Viewport,
+    rect_renderer: RectRenderer,
+    rects: Vec<rect::Handle>,
+    labels: Vec<(String, Vec2)>,
+    visible: bool,
+    dock: Dock,
+    trace: Trace,
+    request: Option<Receiver<Trace>>,
+    // Where the panel was last laid out, for finding the tick under the mouse.
+    layout: Option<Layout>,
+}
+
+#[derive(Clone, Copy)]
+struct Layout {
+    // The bottom left corner and size of the panel.
+    origin: Vec2,
+    size: Vec2,
+    first_tick: u64,
+    tick_width: f32,
+}
+
+impl Layout {
+    fn contains(&self, position: Vec2) -> bool {
+        let offset = position - self.origin;
+        offset.cmpge(Vec2::ZERO).all() && offset.cmplt(self.size).all()
+    }
+
+    fn tick_x(&self, tick: u64) -> f32 {
+        self.origin.x + LABEL_WIDTH + (tick - self.first_tick) as f32 * self.tick_width
+    }
+}
+
+impl TimingDiagram {
+    pub fn new(gfx: &GraphicsContext) -> Self {
+        let mut viewport = Viewport::new(gfx);
+        let camera = viewport.camera_mut();
+        camera.min_zoom = 1.0;
+        camera.max_zoom = 1.0;
+        camera.zoom = 1.0;
+        let rect_renderer = RectRenderer::new(gfx, &viewport);
+        Self {
+            gfx: gfx.clone(),
+            viewport,
+            rect_renderer,
+            rects: Vec::new(),
+            labels: Vec::new(),
+            visible: false,
+            dock: Dock::Bottom,
+            trace: Trace::default(),
+            request: None,
+            layout: None,
+        }
+    }
+
+    pub fn toggle_visible(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Moves the panel to the other edge of the window.
+    pub fn toggle_dock(&mut self) {
+        self.dock = match self.dock {
+            Dock::Bottom => Dock::Top,
+            Dock::Top => Dock::Bottom,
+        };
+    }
+
+    /// Whether the panel covers the given point, in window coordinates.
+    pub fn contains(&self, screen_position: Vec2) -> bool {
+        let position = self.to_panel(screen_position);
+        self.layout.is_some_and(|layout| layout.contains(position))
+    }
+
+    /// The tick shown at the given point, in window coordinates, if it is over a waveform or
+    /// the ruler.
+    pub fn tick_at(&self, screen_position: Vec2) -> Option<u64> {
+        let layout = self.layout?;
+        let position = self.to_panel(screen_position);
+        if !layout.contains(position) || position.x < layout.tick_x(layout.first_tick) {
+            return None;
+        }
+        let offset = (position.x - layout.tick_x(layout.first_tick)) / layout.tick_width;
+        Some(layout.first_tick + (offset as u64).min(TICKS - 1))
+    }
+
+    /// Picks up the newest values of the probes from `worker`, and lays out the panel for
+    /// them. `viewed_tick` is marked, if the simulation was rewound.
+    pub fn update(&mut self, worker: &SimulationWorker, probes: &Probes, viewed_tick: Option<u64>) {
+        self.rects.clear();
+        self.labels.clear();
+        self.layout = None;
+        if !self.visible {
+            self.request = None;
+            return;
+        }
+
+        // One request at a time, so the worker never falls behind on them.
+        if let Some(request) = &self.request {
+            match request.try_recv() {
+                Ok(trace) => {
+                    self.trace = trace;
+                    self.request = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.request = None,
+            }
+        }
+        if self.request.is_none() {
+            self.request = Some(worker.request_trace(TICKS));
+        }
+
+        let gfx_size = self.window_size();
+        self.viewport.camera_mut().pan = gfx_size / 2.0;
+        self.viewport.update(Duration::ZERO);
+
+        let trace = std::mem::take(&mut self.trace);
+        let rows: Vec<_> = probes
+            .list()
+            .iter()
+            .filter(|probe| probe.shown)
+            .filter_map(|probe| Some((probe.name.as_str(), trace.signal(probe.id)?)))
+            .collect();
+        let size = Vec2::new(
+            gfx_size.x,
+            RULER_HEIGHT + rows.len() as f32 * ROW_HEIGHT + 2.0 * PADDING,
+        );
+        let origin = match self.dock {
+            Dock::Bottom => Vec2::ZERO,
+            Dock::Top => Vec2::new(0.0, gfx_size.y - size.y),
+        };
+        let last_tick = trace.end();
+        let layout = Layout {
+            origin,
+            size,
+            first_tick: (last_tick + 1).saturating_sub(TICKS),
+            tick_width: ((size.x - LABEL_WIDTH - PADDING) / TICKS as f32).max(1.0),
+        };
+        self.layout = Some(layout);
+
+        self.add_rect(origin, size, BACKGROUND_Z_INDEX, BACKGROUND_COLOR);
+
+        // The ruler goes on top, with the rows below it.
+        let ruler_bottom = origin.y + size.y - PADDING - RULER_HEIGHT;
+        self.add_rect(
+            Vec2::new(layout.tick_x(layout.first_tick), ruler_bottom),
+            Vec2::new(TICKS as f32 * layout.tick_width, 1.0),
+            SIGNAL_Z_INDEX,
+            RULER_COLOR,
+        );
+        for tick in layout.first_tick..=last_tick {
+            let major = tick % LABEL_INTERVAL == 0;
+            let height = if major { 8.0 } else { 3.0 };
+            let x = layout.tick_x(tick);
+            self.add_rect(
+                Vec2::new(x, ruler_bottom),
+                Vec2::new(1.0, height),
+                SIGNAL_Z_INDEX,
+                RULER_COLOR,
+            );
+            if major {
+                self.add_label(
+                    tick.to_string(),
+                    Vec2::new(x + 2.0, ruler_bottom + RULER_HEIGHT),
+                );
+            }
+        }
+
+        for (row, (name, signal)) in rows.into_iter().enumerate() {
+            let top = ruler_bottom - row as f32 * ROW_HEIGHT - PADDING / 2.0;
+            let high = top - LINE_WIDTH;
+            let low = top - ROW_HEIGHT + PADDING;
+            self.add_label(name.to_string(), Vec2::new(origin.x + PADDING, top));
+
+            let mut previous = None;
+            for tick in layout.first_tick..=last_tick {
+                let value = signal.value_at(tick);
+                let x = layout.tick_x(tick);
+                match value {
+                    Some(value) => {
+                        let y = if value { high } else { low };
+                        self.add_rect(
+                            Vec2::new(x, y),
+                            Vec2::new(layout.tick_width, LINE_WIDTH),
+                            SIGNAL_Z_INDEX,
+                            SIGNAL_COLOR,
+                        );
+                        if previous.is_some_and(|previous| previous != Some(value)) {
+                            self.add_rect(
+                                Vec2::new(x, low),
+                                Vec2::new(LINE_WIDTH, high - low + LINE_WIDTH),
+                                SIGNAL_Z_INDEX,
+                                SIGNAL_COLOR,
+                            );
+                        }
+                    }
+                    None => {
+                        self.add_rect(
+                            Vec2::new(x, low),
+                            Vec2::new(layout.tick_width, high - low + LINE_WIDTH),
+                            SIGNAL_Z_INDEX,
+                            UNKNOWN_COLOR,
+                        );
+                    }
+                }
+                previous = Some(value);
+            }
+        }
+
+        if let Some(tick) = viewed_tick.filter(|&tick| tick >= layout.first_tick) {
+            self.add_rect(
+                Vec2::new(layout.tick_x(tick), origin.y),
+                Vec2::new(layout.tick_width.max(LINE_WIDTH), size.y - PADDING),
+                VIEWED_Z_INDEX,
+                VIEWED_COLOR,
+            );
+        }
+        self.trace = trace;
+    }
+
+    pub fn draw(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+    ) {
+        if self.visible {
+            self.rect_renderer
+                .draw(&self.viewport, encoder, frame_view, depth_view);
+        }
+    }
+
+    /// Queues the names of the probes and the numbers of the ruler.
+    pub fn queue_text(&self, glyph_brush: &mut GlyphBrush<()>) {
+        let height = self.window_size().y;
+        for (text, position) in &self.labels {
+            glyph_brush.queue(Section {
+                screen_position: (position.x, height - position.y),
+                text: vec![Text::new(text)
+                    .with_color([1.0, 1.0, 1.0, 1.0])
+                    .with_scale(TEXT_SCALE)],
+                ..Default::default()
+            });
+        }
+    }
+
+    fn add_rect(&mut self, position: Vec2, size: Vec2, z_index: u8, color: Vec4) {
+        let handle = self.rect_renderer.insert(&Rect {
+            position,
+            z_index,
+            size,
+            color: Color::Fixed(color),
+        });
+        self.rects.push(handle);
+    }
+
+    /// Adds text with its top left corner at `position`.
+    fn add_label(&mut self, text: String, position: Vec2) {
+        self.labels.push((text, position));
+    }
+
+    fn window_size(&self) -> Vec2 {
+        let size = self.gfx.window.inner_size();
+        Vec2::new(size.width as f32, size.height as f32)
+    }
+
+    /// Turns window coordinates, which start at the top, into panel coordinates.
+    fn to_panel(&self, screen_position: Vec2) -> Vec2 {
+        Vec2::new(screen_position.x, self.window_size().y - screen_position.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Layout, LABEL_WIDTH};
+    use glam::Vec2;
+
+    #[test]
+    fn layout() {
+        let layout = Layout {
+            origin: Vec2::new(0.0, 100.0),
+            size: Vec2::new(500.0, 50.0),
+            first_tick: 40,
+            tick_width: 3.0,
+        };
+        assert!(layout.contains(Vec2::new(10.0, 120.0)));
+        assert!(!layout.contains(Vec2::new(10.0, 90.0)));
+        assert!(!layout.contains(Vec2::new(10.0, 150.0)));
+        assert_eq!(layout.tick_x(40), LABEL_WIDTH);
+        assert_eq!(layout.tick_x(42), LABEL_WIDTH + 6.0);
+    }
+}
@@ -0,0 +1,257 @@
+//! Truth tables of combinational circuits, found by trying every combination of inputs.
+//!
+//! The inputs are switches and the outputs are lamps. Switches are toggled on a [`Harness`], one
+//! combination after the other in counting order, and the simulation is run until it settles
+//! before the lamps are read. Each row keeps how many
+//! ticks that took, counted from toggling the switches, so rows that never settle stand out.
+
+use crate::circuit::{Circuit, Port};
+use crate::harness::Harness;
+use anyhow::ensure;
+use std::io::{self, Write};
+
+/// The most inputs a table can have; it has a row for every combination of them.
+pub const MAX_INPUTS: usize = 16;
+
+pub struct TruthTable {
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    rows: Vec<Row>,
+    max_ticks: u64,
+}
+
+pub struct Row {
+    pub inputs: Vec<bool>,
+    /// The lamps once the simulation settled, or when it gave up.
+    pub outputs: Vec<bool>,
+    pub ticks: u64,
+    pub settled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Markdown,
+}
+
+impl Format {
+    pub const ALL: [Format; 2] = [Format::Csv, Format::Markdown];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Markdown => "md",
+        }
+    }
+}
+
+impl TruthTable {
+    /// Tries every combination of the switches `inputs` on a copy of `circuit`, reading the
+    /// lamps `outputs`. The first input is the most significant bit of the row number. Each row
+    /// runs for at most `max_ticks`.
+    pub fn generate(
+        circuit: &Circuit,
+        inputs: &[Port],
+        outputs: &[Port],
+        max_ticks: u64,
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            inputs.len() <= MAX_INPUTS,
+            "too many inputs ({}); at most {} are supported",
+            inputs.len(),
+            MAX_INPUTS
+        );
+        let mut harness = Harness::new(circuit, inputs, outputs)?;
+        // Start from wherever the circuit comes to rest, so the first row is timed like the rest.
+        harness.run_until_stable(max_ticks);
+        let mut rows = Vec::with_capacity(1 << inputs.len());
+        for number in 0..1usize << inputs.len() {
+            let values: Vec<bool> = (0..inputs.len())
+                .map(|index| number >> (inputs.len() - 1 - index) & 1 == 1)
+                .collect();
+            harness.set_inputs(values.iter().copied().map(Some));
+            let (ticks, settled) = harness.run_until_stable(max_ticks);
+            rows.push(Row {
+                inputs: values,
+                outputs: harness.outputs(),
+                ticks,
+                settled,
+            });
+        }
+
+        Ok(Self {
+            inputs: inputs.iter().map(|port| port.name.clone()).collect(),
+            outputs: outputs.iter().map(|port| port.name.clone()).collect(),
+            rows,
+            max_ticks,
+        })
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    /// How many rows never settled.
+    pub fn unsettled(&self) -> usize {
+        self.rows.iter().filter(|row| !row.settled).count()
+    }
+
+    pub fn write<W: Write>(&self, writer: W, format: Format) -> io::Result<()> {
+        match format {
+            Format::Csv => self.write_csv(writer),
+            Format::Markdown => self.write_markdown(writer),
+        }
+    }
+
+    /// Writes a header of the port names followed by `ticks` and `settled`, then a line for each
+    /// row with `0` and `1` for the ports, and flushes `writer`.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut header: Vec<String> = self.names().map(csv_field).collect();
+        header.extend(["ticks".to_owned(), "settled".to_owned()]);
+        writeln!(writer, "{}", header.join(","))?;
+        for row in &self.rows {
+            let mut fields: Vec<String> = row.values().map(bit).collect();
+            fields.push(row.ticks.to_string());
+            fields.push(if row.settled { "yes" } else { "no" }.to_owned());
+            writeln!(writer, "{}", fields.join(","))?;
+        }
+        writer.flush()
+    }
+
+    /// Writes a table of the ports and ticks, with a note below it on the rows that never
+    /// settled, and flushes `writer`.
+    pub fn write_markdown<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut header: Vec<String> = self.names().map(|name| name.replace('|', "\\|")).collect();
+        header.push("ticks".to_owned());
+        writeln!(writer, "| {} |", header.join(" | "))?;
+        let mut rule = vec!["---"; header.len() - 1];
+        rule.push("---:");
+        writeln!(writer, "| {} |", rule.join(" | "))?;
+        for row in &self.rows {
+            let mut cells: Vec<String> = row.values().map(bit).collect();
+            cells.push(match row.settled {
+                true => row.ticks.to_string(),
+                false => format!("> {} (unsettled)", self.max_ticks),
+            });
+            writeln!(writer, "| {} |", cells.join(" | "))?;
+        }
+        let unsettled = self.unsettled();
+        if unsettled > 0 {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "{} of {} rows never settled; their outputs are as they were after {} ticks.",
+                unsettled,
+                self.rows.len(),
+                self.max_ticks
+            )?;
+        }
+        writer.flush()
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.inputs.iter().chain(&self.outputs).map(String::as_str)
+    }
+}
+
+impl Row {
+    fn values(&self) -> impl Iterator<Item = bool> + '_ {
+        self.inputs.iter().chain(&self.outputs).copied()
+    }
+}
+
+fn bit(value: bool) -> String {
+    if value { "1" } else { "0" }.to_owned()
+}
+
+/// A CSV field, quoted if it has to be.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TruthTable;
+    use crate::circuit::{Circuit, ComponentType};
+    use crate::direction::Direction;
+    use crate::schematic::Anchor;
+    use glam::IVec2;
+
+    #[test]
+    fn nor() {
+        // Two switches into the west and north of a flip, whose inputs are all one cluster, so
+        // it lights the lamp only while both are off.
+        let mut circuit = Circuit::new();
+        for (position, ty, orientation) in [
+            (IVec2::new(0, 0), ComponentType::Switch, Direction::East),
+            (IVec2::new(2, 2), ComponentType::Switch, Direction::South),
+            (IVec2::new(2, 0), ComponentType::Flip, Direction::East),
+            (IVec2::new(4, 0), ComponentType::Lamp, Direction::East),
+        ] {
+            assert!(circuit.place_component(ty, position, orientation));
+        }
+        for (start, end) in [((0, 0), (2, 0)), ((2, 2), (2, 0)), ((2, 0), (4, 0))] {
+            assert!(circuit.place_wire(start.into(), end.into()));
+        }
+        circuit.set_label(Anchor::Tile(IVec2::new(0, 0)), "a");
+        circuit.set_label(Anchor::Tile(IVec2::new(2, 2)), "b");
+        circuit.set_label(Anchor::Tile(IVec2::new(4, 0)), "a nor b");
+        // The table doesn't depend on how the switches are left.
+        circuit.interact(IVec2::new(0, 0));
+
+        let ports = circuit.ports();
+        let table = TruthTable::generate(&circuit, &ports.inputs, &ports.outputs, 100).unwrap();
+        let outputs: Vec<bool> = table.rows().iter().map(|row| row.outputs[0]).collect();
+        assert_eq!(outputs, [true, false, false, false]);
+        assert_eq!(table.unsettled(), 0);
+        let ticks: Vec<u64> = table.rows().iter().map(|row| row.ticks).collect();
+        // The switch takes two ticks and the flip one more, unless its input stays powered.
+        assert_eq!(ticks, [3, 3, 2, 2]);
+        let mut csv = Vec::new();
+        table.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("b,a,a nor b,ticks,settled\n0,0,1,"));
+        assert!(csv.ends_with("1,1,0,2,yes\n"));
+
+        let mut markdown = Vec::new();
+        table.write_markdown(&mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert!(markdown.starts_with("| b | a | a nor b | ticks |\n| --- | --- | --- | ---: |\n"));
+        assert!(markdown.ends_with("| 1 | 1 | 0 | 2 |\n"));
+    }
+
+    #[test]
+    fn flags_unsettled_rows() {
+        // A flip whose output wraps around into its input.
+        let mut circuit = Circuit::new();
+        assert!(circuit.place_component(ComponentType::Flip, IVec2::ZERO, Direction::East));
+        assert!(circuit.place_component(ComponentType::Lamp, IVec2::new(1, 2), Direction::East));
+        for (start, end) in [
+            ((0, 0), (1, 0)),
+            ((1, 0), (1, 2)),
+            ((1, 1), (-1, 1)),
+            ((-1, 1), (-1, 0)),
+            ((-1, 0), (0, 0)),
+        ] {
+            assert!(circuit.place_wire(start.into(), end.into()));
+        }
+
+        let ports = circuit.ports();
+        let table = TruthTable::generate(&circuit, &[], &ports.outputs, 50).unwrap();
+        assert_eq!(table.rows().len(), 1);
+        assert_eq!(table.unsettled(), 1);
+        assert_eq!(table.rows()[0].ticks, 50);
+
+        let mut markdown = Vec::new();
+        table.write_markdown(&mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert!(markdown.contains("> 50 (unsettled)"));
+        assert!(markdown.ends_with(
+            "1 of 1 rows never settled; their outputs are as they were after 50 ticks.\n"
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+//! Writing a [`Trace`] as a Value Change Dump, the waveform format of IEEE 1364 that viewers
+//! such as GTKWave read.
+//!
+//! Every probe becomes a one-bit wire in a single scope, and one tick is one unit of time.
+//! Values from before a probe was added, or from while it wasn't on anything, are `x`.
+
+use crate::simulation::Trace;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+// The first and last characters that identifier codes are made of.
+const CODE_FIRST: u8 = b'!';
+const CODE_LAST: u8 = b'~';
+
+/// Writes the signals of `trace` that are named in `names`, which pairs probe IDs with names,
+/// in that order, and flushes `writer`.
+pub fn write_vcd<W: Write>(mut writer: W, trace: &Trace, names: &[(u32, &str)]) -> io::Result<()> {
+    let signals: Vec<_> = names
+        .iter()
+        .filter_map(|&(id, name)| Some((trace.signal(id)?, name)))
+        .collect();
+
+    writeln!(
+        writer,
+        "$version FlipFlop {} $end",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(writer, "$timescale 1 ns $end")?;
+    writeln!(writer, "$scope module circuit $end")?;
+    for (index, (_, name)) in signals.iter().enumerate() {
+        writeln!(
+            writer,
+            "$var wire 1 {} {} $end",
+            identifier_code(index),
+            escape_name(name)
+        )?;
+    }
+    writeln!(writer, "$upscope $end")?;
+    writeln!(writer, "$enddefinitions $end")?;
+
+    // Every change, grouped by tick.
+    let mut changes: BTreeMap<u64, Vec<(usize, Option<bool>)>> = BTreeMap::new();
+    for (index, (signal, _)) in signals.iter().enumerate() {
+        for (tick, value) in signal.changes() {
+            changes.entry(tick).or_default().push((index, value));
+        }
+    }
+
+    // The values at the start of the recording, with signals that start later as unknown.
+    let start = trace.start().unwrap_or(trace.end());
+    let mut initial = vec![None; signals.len()];
+    for (index, value) in changes.remove(&start).unwrap_or_default() {
+        initial[index] = value;
+    }
+    writeln!(writer, "#{}", start)?;
+    writeln!(writer, "$dumpvars")?;
+    for (index, &value) in initial.iter().enumerate() {
+        writeln!(writer, "{}{}", value_char(value), identifier_code(index))?;
+    }
+    writeln!(writer, "$end")?;
+
+    let mut last = start;
+    for (tick, values) in changes {
+        writeln!(writer, "#{}", tick)?;
+        for (index, value) in values {
+            writeln!(writer, "{}{}", value_char(value), identifier_code(index))?;
+        }
+        last = tick;
+    }
+    // Marks how long the recording went on for after the last change.
+    if trace.end() > last {
+        writeln!(writer, "#{}", trace.end())?;
+    }
+    writer.flush()
+}
+
+fn value_char(value: Option<bool>) -> char {
+    match value {
+        Some(true) => '1',
+        Some(false) => '0',
+        None => 'x',
+    }
+}
+
+/// A short code made of printable characters, unique to each index.
+fn identifier_code(mut index: usize) -> String {
+    let base = (CODE_LAST - CODE_FIRST + 1) as usize;
+    let mut code = String::new();
+    loop {
+        code.push((CODE_FIRST + (index % base) as u8) as char);
+        index /= base;
+        if index == 0 {
+            return code;
+        }
+        index -= 1;
+    }
+}
+
+/// Names can't contain whitespace.
+fn escape_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_whitespace() { '_' } else { c })
+        .collect();
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{identifier_code, write_vcd};
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn identifier_codes_are_unique() {
+        let codes: std::collections::HashSet<String> = (0..20_000).map(identifier_code).collect();
+        assert_eq!(codes.len(), 20_000);
+        assert_eq!(identifier_code(0), "!");
+        assert_eq!(identifier_code(93), "~");
+        assert_eq!(identifier_code(94), "!!");
+    }
+
+    #[test]
+    fn writes_changes() {
+        let mut sim = Simulation::new();
+        let clock = sim.alloc_cluster();
+        sim.add_flip(clock, clock);
+        sim.tick();
+        sim.set_probes(vec![(7, Some(clock)), (8, None)]);
+        for _ in 0..3 {
+            sim.tick();
+        }
+
+        let mut buffer = Vec::new();
+        write_vcd(
+            &mut buffer,
+            sim.trace(),
+            &[(7, "clock"), (8, "nothing here")],
+        )
+        .unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let body = text.split("$enddefinitions $end\n").nth(1).unwrap();
+        assert!(text.contains("$var wire 1 ! clock $end\n"));
+        assert!(text.contains("$var wire 1 \" nothing_here $end\n"));
+        assert_eq!(
+            body,
+            "#1\n$dumpvars\n1!\nx\"\n$end\n#2\n0!\n#3\n1!\n#4\n0!\n"
+        );
+    }
+}
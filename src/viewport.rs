@@ -181,6 +181,10 @@ impl Viewport {
         &self.bind_group
     }
 
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
     pub fn camera_mut(&mut self) -> &mut Camera {
         &mut self.camera
     }
@@ -188,6 +192,16 @@ impl Viewport {
     pub fn cursor(&self) -> &Cursor {
         &self.cursor
     }
+
+    /// Where a point of the board is in the window, in window coordinates. The opposite of
+    /// [`Cursor::world_position`].
+    pub fn world_to_screen(&self, world_position: Vec2) -> Vec2 {
+        let size = Vec2::new(
+            self.gfx.window.inner_size().width as f32,
+            self.gfx.window.inner_size().height as f32,
+        );
+        (world_position - self.camera.pan) * self.camera.zoom * Vec2::new(1.0, -1.0) + size / 2.0
+    }
 }
 
 #[repr(C)]
@@ -0,0 +1,445 @@
+//! Runs a [`Simulation`] on a thread of its own, so that ticking and drawing don't hold each
+//! other up.
+//!
+//! The [`Circuit`] being edited keeps its own copy of the simulation for working out clusters,
+//! and every change made to it is sent over as a [`Command`]. Commands and ticks are handled in
+//! the order they were sent, and only ever between two ticks. The worker publishes a
+//! [`Snapshot`] of the power states after every batch of ticks, which can be read at any time
+//! without waiting for the worker.
+
+use crate::circuit::Circuit;
+use crate::scheduler;
+use crate::simulation::{Breakpoint, Command, Simulation, Snapshot, Trace};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// How long the worker ticks before it checks for messages and publishes a snapshot.
+const BATCH_DURATION: Duration = Duration::from_millis(4);
+// The most ticks that can be waiting to run: a second's worth at the fastest tick rate. If the
+// worker falls further behind than that, the rest of the backlog is dropped, like the scheduler
+// does after a slow frame.
+const MAX_PENDING: usize = scheduler::MAX_TICK_RATE as usize;
+
+enum Message {
+    Reset(Box<Simulation>),
+    Edits(Vec<Command>),
+    Tick(usize),
+    SetUnlimited(bool),
+    Seek(u64),
+    SeekBy(i64),
+    Settle(u64, Sender<(u64, bool)>),
+    SetBreakpoints(Vec<Breakpoint>),
+    ClearBreakpointHit,
+    SetProbes(Vec<(u32, Option<u32>)>),
+    FetchTrace(u64, Sender<Trace>),
+    Fetch(Sender<Simulation>),
+    Shutdown,
+}
+
+pub struct SimulationWorker {
+    sender: Sender<Message>,
+    snapshots: Arc<Snapshots>,
+    thread: Option<JoinHandle<()>>,
+    circuit_id: Option<u64>,
+    unlimited: bool,
+}
+
+impl SimulationWorker {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let snapshots = Arc::new(Snapshots::default());
+        let thread = thread::Builder::new()
+            .name("simulation".to_string())
+            .spawn({
+                let snapshots = snapshots.clone();
+                move || run(receiver, &snapshots)
+            })
+            .expect("failed to start simulation thread");
+        Self {
+            sender,
+            snapshots,
+            thread: Some(thread),
+            circuit_id: None,
+            unlimited: false,
+        }
+    }
+
+    /// Sends every change made to the simulation of `circuit` since the last call. If it is a
+    /// different circuit than last time, its whole simulation is sent instead.
+    pub fn sync(&mut self, circuit: &mut Circuit) {
+        if self.circuit_id != Some(circuit.id()) {
+            self.circuit_id = Some(circuit.id());
+            let simulation = circuit.detach_simulation();
+            self.send(Message::Reset(Box::new(simulation)));
+        } else {
+            let commands = circuit.take_simulation_commands();
+            if !commands.is_empty() {
+                self.send(Message::Edits(commands));
+            }
+        }
+    }
+
+    /// Runs `count` more ticks, after the edits that were already sent.
+    pub fn tick(&self, count: usize) {
+        if count > 0 {
+            self.send(Message::Tick(count));
+        }
+    }
+
+    /// Makes the worker tick as fast as it can, instead of only when asked to.
+    pub fn set_unlimited(&mut self, unlimited: bool) {
+        if unlimited != self.unlimited {
+            self.unlimited = unlimited;
+            self.send(Message::SetUnlimited(unlimited));
+        }
+    }
+
+    /// Rewinds the simulation to `tick`, or as far back as its history goes. Ticks that were
+    /// asked for and haven't run yet are dropped, and the worker stops ticking on its own.
+    pub fn seek(&mut self, tick: u64) {
+        self.set_unlimited(false);
+        self.send(Message::Seek(tick));
+    }
+
+    /// Moves the viewed tick by `offset`, relative to the one being viewed or to the present.
+    pub fn seek_by(&mut self, offset: i64) {
+        self.set_unlimited(false);
+        self.send(Message::SeekBy(offset));
+    }
+
+    /// Ticks until the simulation settles, like [`Simulation::run_until_stable`]. Ticks that
+    /// were asked for and haven't run yet are dropped. The result is sent to the returned
+    /// receiver once it is known.
+    pub fn settle(&mut self, max_ticks: u64) -> Receiver<(u64, bool)> {
+        self.set_unlimited(false);
+        let (sender, receiver) = mpsc::channel();
+        self.send(Message::Settle(max_ticks, sender));
+        receiver
+    }
+
+    /// Replaces the breakpoints of the simulation. Once one fires, the worker stops ticking
+    /// until [`SimulationWorker::clear_breakpoint_hit`] is called, and drops any ticks that are
+    /// asked for in the meantime.
+    pub fn set_breakpoints(&self, breakpoints: Vec<Breakpoint>) {
+        self.send(Message::SetBreakpoints(breakpoints));
+    }
+
+    pub fn clear_breakpoint_hit(&self) {
+        self.send(Message::ClearBreakpointHit);
+    }
+
+    /// Replaces the probes of the simulation, see [`Simulation::set_probes`].
+    pub fn set_probes(&self, probes: Vec<(u32, Option<u32>)>) {
+        self.send(Message::SetProbes(probes));
+    }
+
+    /// Returns a copy of the values recorded by the probes so far. Unlike
+    /// [`SimulationWorker::fetch`], this doesn't wait for pending ticks.
+    pub fn fetch_trace(&self) -> Trace {
+        self.request_trace(u64::MAX)
+            .recv()
+            .expect("simulation thread stopped")
+    }
+
+    /// Asks for a copy of the values recorded by the probes over the last `ticks` ticks, which
+    /// is sent to the returned receiver without waiting for pending ticks.
+    pub fn request_trace(&self, ticks: u64) -> Receiver<Trace> {
+        let (sender, receiver) = mpsc::channel();
+        self.send(Message::FetchTrace(ticks, sender));
+        receiver
+    }
+
+    /// Waits for the worker to apply everything sent so far, including ticks, and returns a copy
+    /// of its simulation.
+    pub fn fetch(&self) -> Simulation {
+        let (sender, receiver) = mpsc::channel();
+        self.send(Message::Fetch(sender));
+        receiver.recv().expect("simulation thread stopped")
+    }
+
+    /// The most recently published power states. The worker doesn't wait for the guard to be
+    /// dropped, but it can't publish into it either, so it shouldn't be held for long.
+    pub fn snapshot(&self) -> MutexGuard<'_, Snapshot> {
+        self.snapshots.read()
+    }
+
+    fn send(&self, message: Message) {
+        self.sender
+            .send(message)
+            .expect("simulation thread stopped");
+    }
+}
+
+impl Default for SimulationWorker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for SimulationWorker {
+    fn drop(&mut self) {
+        // The thread may already be gone if it panicked.
+        let _ = self.sender.send(Message::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(receiver: Receiver<Message>, snapshots: &Snapshots) {
+    let mut simulation = Simulation::new();
+    let mut pending = 0;
+    let mut unlimited = false;
+    let mut fetches = Vec::new();
+    let mut settle: Option<Settle> = None;
+    loop {
+        // Sleep until there is something to do.
+        let busy = pending > 0 || unlimited || settle.is_some();
+        let idle = if !busy || simulation.breakpoint_hit().is_some() {
+            match receiver.recv() {
+                Ok(message) => Some(message),
+                Err(..) => return,
+            }
+        } else {
+            None
+        };
+        for message in idle.into_iter().chain(receiver.try_iter()) {
+            match message {
+                Message::Reset(new) => {
+                    simulation = *new;
+                    pending = 0;
+                }
+                Message::Edits(commands) => {
+                    for command in commands {
+                        simulation.apply(command);
+                    }
+                }
+                Message::Tick(count) => {
+                    if simulation.breakpoint_hit().is_none() {
+                        pending = (pending + count).min(MAX_PENDING);
+                    }
+                }
+                Message::SetUnlimited(value) => {
+                    unlimited = value;
+                }
+                Message::Seek(tick) => {
+                    pending = 0;
+                    simulation.seek(tick);
+                }
+                Message::SeekBy(offset) => {
+                    pending = 0;
+                    let from = simulation
+                        .viewed_tick()
+                        .unwrap_or_else(|| simulation.tick_count());
+                    simulation.seek(from.saturating_add_signed(offset));
+                }
+                Message::Settle(max_ticks, reply) => {
+                    pending = 0;
+                    settle = Some(Settle {
+                        remaining: max_ticks,
+                        ticks: 0,
+                        reply,
+                    });
+                }
+                Message::SetBreakpoints(breakpoints) => {
+                    simulation.set_breakpoints(breakpoints);
+                }
+                Message::ClearBreakpointHit => {
+                    simulation.clear_breakpoint_hit();
+                }
+                Message::SetProbes(probes) => {
+                    simulation.set_probes(probes);
+                }
+                Message::FetchTrace(ticks, reply) => {
+                    let trace = simulation.trace();
+                    let _ = reply.send(trace.since(trace.end().saturating_sub(ticks)));
+                }
+                Message::Fetch(reply) => {
+                    fetches.push(reply);
+                }
+                Message::Shutdown => return,
+            }
+        }
+
+        let start = Instant::now();
+        let mut settled_result = None;
+        while let Some(current) = &mut settle {
+            // One tick at a time, so that messages are still handled in between batches.
+            let (ticks, settled) = simulation.run_until_stable(current.remaining.min(1));
+            current.ticks += ticks;
+            current.remaining -= ticks;
+            let stopped = simulation.breakpoint_hit().is_some();
+            if settled || stopped || current.remaining == 0 {
+                settled_result = Some((settle.take().unwrap(), settled));
+            } else if start.elapsed() >= BATCH_DURATION {
+                break;
+            }
+        }
+        while (unlimited || pending > 0) && start.elapsed() < BATCH_DURATION {
+            simulation.tick();
+            pending = pending.saturating_sub(1);
+            if simulation.breakpoint_hit().is_some() {
+                pending = 0;
+                break;
+            }
+        }
+        snapshots.publish(&simulation);
+        // Only answer once the snapshot shows the result.
+        if let Some((finished, settled)) = settled_result {
+            let _ = finished.reply.send((finished.ticks, settled));
+        }
+        if pending == 0 && settle.is_none() {
+            for reply in fetches.drain(..) {
+                let _ = reply.send(simulation.clone());
+            }
+        }
+    }
+}
+
+/// A [`SimulationWorker::settle`] in progress.
+struct Settle {
+    remaining: u64,
+    ticks: u64,
+    reply: Sender<(u64, bool)>,
+}
+
+/// Three snapshots: the one that was published last, and two others that the worker writes the
+/// next one into, so that it never has to wait for a reader that still holds an older one.
+#[derive(Default)]
+struct Snapshots {
+    buffers: [Mutex<Snapshot>; 3],
+    front: AtomicUsize,
+}
+
+impl Snapshots {
+    fn publish(&self, simulation: &Simulation) {
+        let front = self.front.load(Ordering::Acquire);
+        // If readers hold both of the others, this one is skipped; the next batch publishes again.
+        let back = (0..self.buffers.len())
+            .filter(|&index| index != front)
+            .find_map(|index| match self.buffers[index].try_lock() {
+                Ok(guard) => Some((index, guard)),
+                Err(TryLockError::Poisoned(err)) => Some((index, err.into_inner())),
+                Err(TryLockError::WouldBlock) => None,
+            });
+        if let Some((index, mut guard)) = back {
+            simulation.write_snapshot(&mut guard);
+            drop(guard);
+            self.front.store(index, Ordering::Release);
+        }
+    }
+
+    /// Locks the front without waiting for the worker. The worker only ever writes into the
+    /// others, so if it holds the one that was looked up, it has published another one since,
+    /// which is complete and is read instead.
+    fn read(&self) -> MutexGuard<'_, Snapshot> {
+        let mut front = self.front.load(Ordering::Acquire);
+        loop {
+            match self.buffers[front].try_lock() {
+                Ok(guard) => return guard,
+                Err(TryLockError::Poisoned(err)) => return err.into_inner(),
+                Err(TryLockError::WouldBlock) => {}
+            }
+            let latest = self.front.load(Ordering::Acquire);
+            if latest == front {
+                // Another reader holds it, not the worker.
+                return self.buffers[front]
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner());
+            }
+            front = latest;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SimulationWorker, MAX_PENDING};
+    use crate::circuit::tests::place_inverter;
+    use crate::circuit::Circuit;
+    use glam::IVec2;
+
+    #[test]
+    fn matches_local_simulation() {
+        let mut remote = Circuit::new();
+        let mut worker = SimulationWorker::new();
+        worker.sync(&mut remote);
+
+        // Edits made after the simulation was sent reach the worker as commands.
+        let mut local = Circuit::new();
+        for circuit in [&mut local, &mut remote] {
+            place_inverter(circuit, 0);
+            circuit.interact(IVec2::ZERO);
+        }
+        worker.sync(&mut remote);
+        worker.tick(5);
+        for _ in 0..5 {
+            local.tick();
+        }
+
+        let simulation = worker.fetch();
+        assert_eq!(simulation.tick_count(), 5);
+        for id in 0..local.simulation().num_clusters() {
+            assert_eq!(simulation.is_powered(id), local.simulation().is_powered(id));
+            assert_eq!(
+                simulation.was_powered(id),
+                local.simulation().was_powered(id)
+            );
+        }
+        let snapshot = worker.snapshot();
+        assert_eq!(snapshot.tick_count(), 5);
+        assert_eq!(snapshot.num_clusters(), simulation.num_clusters());
+    }
+
+    #[test]
+    fn settles() {
+        let mut circuit = Circuit::new();
+        place_inverter(&mut circuit, 0);
+        let mut worker = SimulationWorker::new();
+        worker.sync(&mut circuit);
+
+        let (ticks, settled) = worker.settle(100).recv().unwrap();
+        assert!(settled);
+        assert_eq!(worker.fetch().tick_count(), ticks);
+
+        circuit.interact(IVec2::ZERO);
+        worker.sync(&mut circuit);
+        let (more_ticks, settled) = worker.settle(100).recv().unwrap();
+        assert!(settled);
+        assert!(more_ticks > 0);
+        assert_eq!(worker.snapshot().tick_count(), ticks + more_ticks);
+    }
+
+    #[test]
+    fn publishes_while_snapshot_is_held() {
+        let mut circuit = Circuit::new();
+        let mut worker = SimulationWorker::new();
+        worker.sync(&mut circuit);
+
+        let held = worker.snapshot();
+        worker.tick(3);
+        assert_eq!(worker.fetch().tick_count(), 3);
+        assert_eq!(held.tick_count(), 0);
+        drop(held);
+        assert_eq!(worker.snapshot().tick_count(), 3);
+    }
+
+    #[test]
+    fn drops_backlog() {
+        let mut circuit = Circuit::new();
+        let mut worker = SimulationWorker::new();
+        worker.sync(&mut circuit);
+
+        // Far more than the worker could run while they are being sent.
+        let sent = 1000 * MAX_PENDING as u64;
+        for _ in 0..1000 {
+            worker.tick(MAX_PENDING);
+        }
+        let tick_count = worker.fetch().tick_count();
+        assert!(tick_count >= MAX_PENDING as u64);
+        assert!(tick_count < sent);
+    }
+}